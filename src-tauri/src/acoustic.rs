@@ -0,0 +1,334 @@
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
+
+// 声学指纹（Haitsma & Kalker 的能量差分法）：重采样到 11025Hz 单声道，
+// 每 256 个采样（约 23ms）对 4096 个采样（约 0.37 秒）做一次频谱分析，
+// 频段能量取对数后做时间平滑，每 4 帧（约 93ms）输出一个 32 位子指纹
+const TARGET_RATE: f64 = 11025.0;
+const FRAME_SIZE: usize = 4096;
+const HOP_SIZE: usize = 256;
+const OUTPUT_INTERVAL: usize = 4;
+// 平滑窗口和差分间隔都是 8 帧（约 186ms）。两个文件的帧对不齐时，
+// 平滑过的能量变化不大，子指纹仍然基本相同
+const SMOOTHING: usize = 8;
+const LAG: usize = 8;
+const BANDS: usize = 33;
+const MIN_FREQUENCY: f64 = 300.0;
+const MAX_FREQUENCY: f64 = 2000.0;
+/// 只取开头两分钟，足够区分不同的录音，也让指纹大小有上限
+const MAX_SECONDS: f64 = 120.0;
+
+/// 每秒的子指纹个数
+pub const FRAMES_PER_SECOND: f64 = TARGET_RATE / (HOP_SIZE * OUTPUT_INTERVAL) as f64;
+
+// 比对时至少要重叠 10 秒，避免只靠一小段前奏判断
+const MIN_OVERLAP: usize = (10.0 * FRAMES_PER_SECOND) as usize;
+// 候选偏移至少要有这么多完全相同的子指纹
+const MIN_VOTES: usize = 4;
+// 出现在太多位置的子指纹（如静音）没有区分度，建索引时跳过
+const MAX_POSTINGS: usize = 2000;
+
+/// 流式计算声学指纹，输入交错排列的采样，用法同 LoudnessMeter
+pub struct Fingerprinter {
+    channels: usize,
+    step: f64,
+    lowpass: Option<[Lowpass; 2]>,
+    phase: f64,
+    previous: f64,
+    buffer: Vec<f64>,
+    window: Vec<f64>,
+    fft: Fft,
+    band_bins: Vec<(usize, usize)>,
+    /// 最近 SMOOTHING + LAG 帧的对数频段能量
+    history: VecDeque<[f64; BANDS]>,
+    frames: usize,
+    max_frames: usize,
+    fingerprint: Vec<u32>,
+}
+
+impl Fingerprinter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let rate = sample_rate as f64;
+        // 降采样前先低通，免得高频混叠进 300～2000Hz 的测量频段
+        let lowpass = (rate > TARGET_RATE).then(|| [Lowpass::new(rate), Lowpass::new(rate)]);
+        let bin_width = TARGET_RATE / FRAME_SIZE as f64;
+        let band_bins = (0..BANDS)
+            .map(|band| {
+                let edge = |i: usize| {
+                    let f = MIN_FREQUENCY
+                        * (MAX_FREQUENCY / MIN_FREQUENCY).powf(i as f64 / BANDS as f64);
+                    (f / bin_width).round() as usize
+                };
+                let start = edge(band);
+                (start, edge(band + 1).max(start + 1))
+            })
+            .collect();
+        Self {
+            channels: channels.max(1),
+            step: rate / TARGET_RATE,
+            lowpass,
+            phase: 0.0,
+            previous: 0.0,
+            buffer: Vec::with_capacity(FRAME_SIZE * 2),
+            window: (0..FRAME_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FRAME_SIZE as f64).cos())
+                .collect(),
+            fft: Fft::new(FRAME_SIZE),
+            band_bins,
+            history: VecDeque::with_capacity(SMOOTHING + LAG + 1),
+            frames: 0,
+            max_frames: (MAX_SECONDS * FRAMES_PER_SECOND) as usize,
+            fingerprint: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, interleaved: &[f32]) {
+        if self.fingerprint.len() >= self.max_frames {
+            return;
+        }
+        for frame in interleaved.chunks_exact(self.channels) {
+            let mut sample = frame.iter().map(|&s| s as f64).sum::<f64>() / self.channels as f64;
+            if let Some([first, second]) = &mut self.lowpass {
+                sample = second.process(first.process(sample));
+            }
+            // 线性插值重采样：phase 是下一个输出点在 previous 与当前采样之间的位置
+            while self.phase <= 1.0 {
+                self.buffer
+                    .push(self.previous + (sample - self.previous) * self.phase);
+                self.phase += self.step;
+            }
+            self.phase -= 1.0;
+            self.previous = sample;
+
+            if self.buffer.len() >= FRAME_SIZE {
+                self.push_frame();
+                self.buffer.drain(..HOP_SIZE);
+            }
+        }
+    }
+
+    fn push_frame(&mut self) {
+        let mut re: Vec<f64> = self.buffer[..FRAME_SIZE]
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut im = vec![0.0; FRAME_SIZE];
+        self.fft.transform(&mut re, &mut im);
+
+        let mut bands = [0.0; BANDS];
+        for (energy, &(start, end)) in bands.iter_mut().zip(&self.band_bins) {
+            let power: f64 = (start..end).map(|k| re[k] * re[k] + im[k] * im[k]).sum();
+            *energy = (power + 1e-9).ln();
+        }
+        if self.history.len() == SMOOTHING + LAG {
+            self.history.pop_front();
+        }
+        self.history.push_back(bands);
+        self.frames += 1;
+        if self.history.len() < SMOOTHING + LAG || !self.frames.is_multiple_of(OUTPUT_INTERVAL) {
+            return;
+        }
+
+        let smoothed = |from: usize| {
+            let mut sum = [0.0; BANDS];
+            for frame in self.history.range(from..from + SMOOTHING) {
+                for (total, energy) in sum.iter_mut().zip(frame) {
+                    *total += energy;
+                }
+            }
+            sum
+        };
+        let (last, current) = (smoothed(0), smoothed(LAG));
+        // 第 m 位：相邻两个频段的能量差比 LAG 帧之前变大时为 1
+        let mut bits = 0u32;
+        for m in 0..BANDS - 1 {
+            let difference = (current[m] - current[m + 1]) - (last[m] - last[m + 1]);
+            if difference > 0.0 {
+                bits |= 1 << m;
+            }
+        }
+        self.fingerprint.push(bits);
+    }
+
+    pub fn finish(self) -> Vec<u32> {
+        self.fingerprint
+    }
+}
+
+/// 两个指纹按 offset 对齐（a 的第 i 个对应 b 的第 i - offset 个）后相同比特的比例，
+/// 重叠部分太短时返回 None
+pub fn similarity(a: &[u32], b: &[u32], offset: isize) -> Option<f64> {
+    let start = offset.max(0) as usize;
+    let end = (b.len() as isize + offset).min(a.len() as isize);
+    if end <= start as isize || (end as usize - start) < MIN_OVERLAP {
+        return None;
+    }
+    let end = end as usize;
+    let differing: u32 = (start..end)
+        .map(|i| (a[i] ^ b[(i as isize - offset) as usize]).count_ones())
+        .sum();
+    Some(1.0 - differing as f64 / ((end - start) * 32) as f64)
+}
+
+/// 多个指纹的倒排索引：按完全相同的子指纹找出可能是同一录音的候选，
+/// 并由相同子指纹的位置差投票得出对齐偏移，不用两两全量比对
+pub struct FingerprintIndex<'a> {
+    fingerprints: &'a [Vec<u32>],
+    postings: HashMap<u32, Vec<(usize, usize)>>,
+}
+
+impl<'a> FingerprintIndex<'a> {
+    pub fn new(fingerprints: &'a [Vec<u32>]) -> Self {
+        let mut postings: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+        for (song, fingerprint) in fingerprints.iter().enumerate() {
+            for (position, &value) in fingerprint.iter().enumerate() {
+                // 全 0 或全 1 一般是静音或削波，没有区分度
+                if value != 0 && value != u32::MAX {
+                    postings.entry(value).or_default().push((song, position));
+                }
+            }
+        }
+        postings.retain(|_, list| list.len() <= MAX_POSTINGS);
+        Self {
+            fingerprints,
+            postings,
+        }
+    }
+
+    /// 找出与第 song 个指纹相似度不低于 threshold 的其他指纹（只返回序号更大的，
+    /// 每对只报告一次），结果为 (序号, 相似度, 偏移秒数)
+    pub fn matches(&self, song: usize, threshold: f64) -> Vec<(usize, f64, f64)> {
+        let fingerprint = &self.fingerprints[song];
+        let mut votes: HashMap<(usize, isize), usize> = HashMap::new();
+        for (position, value) in fingerprint.iter().enumerate() {
+            let Some(list) = self.postings.get(value) else {
+                continue;
+            };
+            for &(other, other_position) in list {
+                if other > song {
+                    *votes
+                        .entry((other, position as isize - other_position as isize))
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        // 每个候选只验证票数最多的几个偏移
+        let mut by_song: HashMap<usize, Vec<(isize, usize)>> = HashMap::new();
+        for ((other, offset), count) in votes {
+            if count >= MIN_VOTES {
+                by_song.entry(other).or_default().push((offset, count));
+            }
+        }
+        let mut result = Vec::new();
+        for (other, mut offsets) in by_song {
+            offsets.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            let best = offsets
+                .iter()
+                .take(3)
+                .filter_map(|&(offset, _)| {
+                    similarity(fingerprint, &self.fingerprints[other], offset).map(|s| (s, offset))
+                })
+                .max_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((score, offset)) = best.filter(|(s, _)| *s >= threshold) {
+                result.push((other, score, offset as f64 / FRAMES_PER_SECOND));
+            }
+        }
+        result
+    }
+}
+
+/// 指纹以十六进制字符串保存，比 JSON 数字数组紧凑得多
+pub fn encode(fingerprint: &[u32]) -> String {
+    fingerprint.iter().map(|v| format!("{:08x}", v)).collect()
+}
+
+pub fn decode(text: &str) -> Option<Vec<u32>> {
+    if !text.len().is_multiple_of(8) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(8)
+        .map(|i| u32::from_str_radix(&text[i..i + 8], 16).ok())
+        .collect()
+}
+
+/// 二阶巴特沃斯低通，截止频率 4kHz，两级串联
+#[derive(Debug, Clone, Copy)]
+struct Lowpass {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Lowpass {
+    fn new(rate: f64) -> Self {
+        let k = (PI * 4000.0 / rate).tan();
+        let q = std::f64::consts::FRAC_1_SQRT_2;
+        let a0 = 1.0 + k / q + k * k;
+        let b0 = k * k / a0;
+        Self {
+            b: [b0, 2.0 * b0, b0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// 基 2 的迭代 FFT，长度固定，旋转因子预先算好
+struct Fft {
+    size: usize,
+    cos: Vec<f64>,
+    sin: Vec<f64>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            cos: (0..size / 2)
+                .map(|i| (2.0 * PI * i as f64 / size as f64).cos())
+                .collect(),
+            sin: (0..size / 2)
+                .map(|i| -(2.0 * PI * i as f64 / size as f64).sin())
+                .collect(),
+        }
+    }
+
+    fn transform(&self, re: &mut [f64], im: &mut [f64]) {
+        let n = self.size;
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut length = 2;
+        while length <= n {
+            let stride = n / length;
+            for start in (0..n).step_by(length) {
+                for k in 0..length / 2 {
+                    let (wr, wi) = (self.cos[k * stride], self.sin[k * stride]);
+                    let (i, j) = (start + k, start + k + length / 2);
+                    let tr = re[j] * wr - im[j] * wi;
+                    let ti = re[j] * wi + im[j] * wr;
+                    re[j] = re[i] - tr;
+                    im[j] = im[i] - ti;
+                    re[i] += tr;
+                    im[i] += ti;
+                }
+            }
+            length *= 2;
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::commands::file_scan::file_key;
+use crate::models::{AlbumSummary, ArtistSummary, MusicFile};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// 合辑的专辑艺术家
+pub const VARIOUS_ARTISTS: &str = "群星";
+
+// 专辑艺术家标签里常见的合辑写法
+const VARIOUS_ARTISTS_NAMES: [&str; 4] = ["群星", "various artists", "various", "va"];
+
+static ARTIST_SEPARATOR: OnceLock<Regex> = OnceLock::new();
+
+/// 把 "周杰伦 feat. 费玉清"、"A / B"、"A、B"、"A & B" 拆成单个艺术家，去掉重复。
+/// "A (feat. B)" 的括号随分隔符一起去掉，名字本身带的括号（如 "Mili (ミリー)"）保留
+pub fn split_artists(value: &str) -> Vec<String> {
+    let separator = ARTIST_SEPARATOR.get_or_init(|| {
+        Regex::new(r"(?i)\s*[(\[（]?\s*\b(?:feat\.?|ft\.|featuring)\s+|\s*[/、&;；]\s*")
+            .expect("艺术家分隔符正则无效")
+    });
+    let mut seen = HashSet::new();
+    let mut names = Vec::new();
+    // 分隔符吃掉了左括号时，要在之后的某个名字末尾去掉对应的右括号
+    let mut closing: Option<char> = None;
+    let mut push = |segment: &str, closing: &mut Option<char>| {
+        let mut name = segment.trim();
+        if let Some(stripped) = closing.and_then(|c| name.strip_suffix(c)) {
+            name = stripped.trim();
+            *closing = None;
+        }
+        if !name.is_empty() && seen.insert(normalize(name)) {
+            names.push(name.to_string());
+        }
+    };
+    let mut rest = 0;
+    for found in separator.find_iter(value) {
+        push(&value[rest..found.start()], &mut closing);
+        closing = match found.as_str().trim_start().chars().next() {
+            Some('(') => Some(')'),
+            Some('[') => Some(']'),
+            Some('（') => Some('）'),
+            _ => closing,
+        };
+        rest = found.end();
+    }
+    push(&value[rest..], &mut closing);
+    names
+}
+
+/// 比较用的名字：去掉首尾空格、合并连续空格、转小写
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn entity_id(kind: &str, key: &str) -> String {
+    let hash = blake3::hash(format!("{}\0{}", kind, key).as_bytes()).to_hex();
+    hash[..16].to_string()
+}
+
+pub fn artist_id(name: &str) -> String {
+    entity_id("artist", &normalize(name))
+}
+
+/// 曲库按专辑、艺术家聚合后的视图，只包含本地歌曲
+pub struct Catalog<'a> {
+    songs: Vec<&'a MusicFile>,
+    pub albums: Vec<AlbumSummary>,
+    pub artists: Vec<ArtistSummary>,
+    album_tracks: HashMap<String, Vec<usize>>,
+    artist_tracks: HashMap<String, Vec<usize>>,
+}
+
+impl<'a> Catalog<'a> {
+    pub fn build(library: &'a [MusicFile]) -> Self {
+        let songs: Vec<&MusicFile> = library
+            .iter()
+            .filter(|s| s.is_online != Some(true))
+            .collect();
+        let compilations = detect_compilations(&songs);
+
+        let mut albums: Vec<AlbumSummary> = Vec::new();
+        let mut album_positions: HashMap<String, usize> = HashMap::new();
+        let mut album_tracks: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut album_discs: HashMap<String, HashSet<u32>> = HashMap::new();
+        let mut artists: Vec<ArtistSummary> = Vec::new();
+        let mut artist_positions: HashMap<String, usize> = HashMap::new();
+        let mut artist_tracks: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut artist_albums: HashMap<String, HashSet<String>> = HashMap::new();
+
+        let mut artist_entry = |name: &str, artists: &mut Vec<ArtistSummary>| -> String {
+            let id = artist_id(name);
+            artist_positions.entry(id.clone()).or_insert_with(|| {
+                artists.push(ArtistSummary {
+                    id: id.clone(),
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                artists.len() - 1
+            });
+            id
+        };
+
+        for (i, song) in songs.iter().enumerate() {
+            let duration = song.duration.unwrap_or(0.0);
+            let track_artists = song
+                .artist
+                .as_deref()
+                .map(split_artists)
+                .unwrap_or_default();
+            for name in &track_artists {
+                let id = artist_entry(name, &mut artists);
+                artist_tracks.entry(id).or_default().push(i);
+            }
+
+            let Some(title) = song
+                .album
+                .as_deref()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+            else {
+                continue;
+            };
+            let compilation = is_compilation(song) || compilations.contains(&i);
+            let album_artist = if compilation {
+                VARIOUS_ARTISTS.to_string()
+            } else {
+                song.album_artist
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|a| !a.is_empty())
+                    .map(str::to_string)
+                    .or_else(|| track_artists.first().cloned())
+                    .unwrap_or_default()
+            };
+            let id = entity_id(
+                "album",
+                &format!("{}\0{}", normalize(&album_artist), normalize(title)),
+            );
+            let position = *album_positions.entry(id.clone()).or_insert_with(|| {
+                let artist_ids = if compilation {
+                    vec![]
+                } else {
+                    split_artists(&album_artist)
+                        .iter()
+                        .map(|name| artist_entry(name, &mut artists))
+                        .collect()
+                };
+                albums.push(AlbumSummary {
+                    id: id.clone(),
+                    title: title.to_string(),
+                    album_artist: album_artist.clone(),
+                    artist_ids,
+                    compilation,
+                    cover_path: file_key(song).to_string(),
+                    ..Default::default()
+                });
+                albums.len() - 1
+            });
+
+            let album = &mut albums[position];
+            album.track_count += 1;
+            album.duration += duration;
+            album.year = match (album.year, song.year) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            album_discs
+                .entry(id.clone())
+                .or_default()
+                .insert(song.disc_number.unwrap_or(1));
+            album_tracks.entry(id.clone()).or_default().push(i);
+            for artist in &album.artist_ids {
+                artist_albums
+                    .entry(artist.clone())
+                    .or_default()
+                    .insert(id.clone());
+            }
+        }
+
+        for album in &mut albums {
+            album.disc_count = album_discs.get(&album.id).map_or(1, HashSet::len);
+        }
+        for artist in &mut artists {
+            let tracks = artist_tracks
+                .get(&artist.id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            artist.track_count = tracks.len();
+            artist.duration = tracks.iter().filter_map(|&i| songs[i].duration).sum();
+            artist.album_count = artist_albums.get(&artist.id).map_or(0, HashSet::len);
+        }
+
+        albums.sort_by(|a, b| {
+            normalize(&a.album_artist)
+                .cmp(&normalize(&b.album_artist))
+                .then_with(|| a.year.cmp(&b.year))
+                .then_with(|| normalize(&a.title).cmp(&normalize(&b.title)))
+        });
+        artists.sort_by_cached_key(|a| normalize(&a.name));
+
+        Self {
+            songs,
+            albums,
+            artists,
+            album_tracks,
+            artist_tracks,
+        }
+    }
+
+    pub fn album(&self, id: &str) -> Option<&AlbumSummary> {
+        self.albums.iter().find(|a| a.id == id)
+    }
+
+    pub fn artist(&self, id: &str) -> Option<&ArtistSummary> {
+        self.artists.iter().find(|a| a.id == id)
+    }
+
+    /// 专辑的曲目，按碟号、音轨号排列
+    pub fn album_tracks(&self, id: &str) -> Vec<MusicFile> {
+        let mut tracks = self.tracks(self.album_tracks.get(id));
+        tracks.sort_by(|a, b| {
+            (
+                a.disc_number.unwrap_or(1),
+                a.track_number.unwrap_or(u32::MAX),
+            )
+                .cmp(&(
+                    b.disc_number.unwrap_or(1),
+                    b.track_number.unwrap_or(u32::MAX),
+                ))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        tracks
+    }
+
+    /// 艺术家参与的所有歌曲（包括合作和合辑里的），按专辑、音轨号排列
+    pub fn artist_tracks(&self, id: &str) -> Vec<MusicFile> {
+        let mut tracks = self.tracks(self.artist_tracks.get(id));
+        tracks.sort_by(|a, b| {
+            (&a.album, a.disc_number, a.track_number)
+                .cmp(&(&b.album, b.disc_number, b.track_number))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        tracks
+    }
+
+    /// 艺术家作为专辑艺术家的专辑，以及只是有歌曲出现在其中的专辑（合辑、合作）
+    pub fn artist_albums(&self, id: &str) -> (Vec<AlbumSummary>, Vec<AlbumSummary>) {
+        let appears: HashSet<&str> = self
+            .album_tracks
+            .iter()
+            .filter(|(_, tracks)| {
+                tracks
+                    .iter()
+                    .any(|i| self.artist_tracks.get(id).is_some_and(|t| t.contains(i)))
+            })
+            .map(|(album, _)| album.as_str())
+            .collect();
+        self.albums
+            .iter()
+            .filter(|a| {
+                a.artist_ids.iter().any(|artist| artist == id) || appears.contains(a.id.as_str())
+            })
+            .cloned()
+            .partition(|a| a.artist_ids.iter().any(|artist| artist == id))
+    }
+
+    fn tracks(&self, indices: Option<&Vec<usize>>) -> Vec<MusicFile> {
+        indices
+            .into_iter()
+            .flatten()
+            .map(|&i| self.songs[i].clone())
+            .collect()
+    }
+}
+
+fn is_compilation(song: &MusicFile) -> bool {
+    song.compilation == Some(true)
+        || song
+            .album_artist
+            .as_deref()
+            .is_some_and(|a| VARIOUS_ARTISTS_NAMES.contains(&normalize(a).as_str()))
+}
+
+/// 没有合辑标记、也没有专辑艺术家的歌曲：同一目录下的同名专辑里，
+/// 各首歌的主艺术家大多不同（没有谁唱了一半以上）时当作合辑
+fn detect_compilations(songs: &[&MusicFile]) -> HashSet<usize> {
+    let mut groups: HashMap<(String, &Path), Vec<usize>> = HashMap::new();
+    for (i, song) in songs.iter().enumerate() {
+        let untagged = song
+            .album_artist
+            .as_deref()
+            .is_none_or(|a| a.trim().is_empty())
+            && song.compilation.is_none();
+        let (Some(album), Some(dir)) = (song.album.as_deref(), Path::new(file_key(song)).parent())
+        else {
+            continue;
+        };
+        if untagged && !album.trim().is_empty() {
+            groups.entry((normalize(album), dir)).or_default().push(i);
+        }
+    }
+
+    let mut compilations = HashSet::new();
+    for tracks in groups.into_values().filter(|t| t.len() > 1) {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for &i in &tracks {
+            let primary = songs[i]
+                .artist
+                .as_deref()
+                .and_then(|a| split_artists(a).into_iter().next())
+                .map(|a| normalize(&a))
+                .unwrap_or_default();
+            *counts.entry(primary).or_default() += 1;
+        }
+        let top = counts.values().copied().max().unwrap_or(0);
+        if counts.len() > 1 && top * 2 <= tracks.len() {
+            compilations.extend(tracks);
+        }
+    }
+    compilations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_artist_names() {
+        let cases: [(&str, &[&str]); 9] = [
+            ("周杰伦 feat. 费玉清", &["周杰伦", "费玉清"]),
+            ("A / B", &["A", "B"]),
+            ("A、B、a", &["A", "B"]),
+            ("A & B; C", &["A", "B", "C"]),
+            ("A (feat. B)", &["A", "B"]),
+            ("A [ft. B & C]", &["A", "B", "C"]),
+            ("A（featuring B）", &["A", "B"]),
+            // 名字本身带的括号不是分隔符留下的，保持完整
+            ("Mili (ミリー)", &["Mili (ミリー)"]),
+            (
+                "Mili (ミリー) feat. B (Band)",
+                &["Mili (ミリー)", "B (Band)"],
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(split_artists(value), expected, "{:?}", value);
+        }
+    }
+}
@@ -1,2 +1,19 @@
+pub mod browse;
+pub mod cover;
+pub mod duplicates;
 pub mod file_scan;
-pub mod online;
\ No newline at end of file
+pub mod library_edit;
+pub mod library_query;
+pub mod library_roots;
+pub mod lyrics;
+pub mod online;
+pub mod organize;
+pub mod playlist_import;
+pub mod playlists;
+pub mod relocate;
+pub mod replay_gain;
+pub mod scan_progress;
+pub mod tag_edit;
+pub mod tag_inference;
+pub mod tags;
+pub mod waveform;
@@ -0,0 +1,66 @@
+use crate::catalog::Catalog;
+use crate::db::read_library;
+use crate::models::{AlbumDetail, AlbumSummary, ArtistDetail, ArtistSummary};
+use tauri::AppHandle;
+use tauri_helper::auto_collect_command;
+
+/// 按专辑浏览曲库。传入 artist_id 时只列出该艺术家作为专辑艺术家的专辑。
+/// 没有专辑标签的歌曲和在线歌曲不归入任何专辑
+#[tauri::command]
+#[auto_collect_command]
+pub fn list_albums(
+    app_handle: AppHandle,
+    artist_id: Option<String>,
+) -> Result<Vec<AlbumSummary>, String> {
+    let library = read_library(&app_handle)?;
+    let catalog = Catalog::build(&library);
+    Ok(match artist_id {
+        Some(id) => catalog
+            .albums
+            .into_iter()
+            .filter(|a| a.artist_ids.contains(&id))
+            .collect(),
+        None => catalog.albums,
+    })
+}
+
+/// 按艺术家浏览曲库，"A feat. B"、"A / B" 之类的标签拆成多个艺术家
+#[tauri::command]
+#[auto_collect_command]
+pub fn list_artists(app_handle: AppHandle) -> Result<Vec<ArtistSummary>, String> {
+    let library = read_library(&app_handle)?;
+    Ok(Catalog::build(&library).artists)
+}
+
+#[tauri::command]
+#[auto_collect_command]
+pub fn get_album(app_handle: AppHandle, album_id: String) -> Result<AlbumDetail, String> {
+    let library = read_library(&app_handle)?;
+    let catalog = Catalog::build(&library);
+    let album = catalog
+        .album(&album_id)
+        .cloned()
+        .ok_or_else(|| "专辑不存在".to_string())?;
+    Ok(AlbumDetail {
+        tracks: catalog.album_tracks(&album_id),
+        album,
+    })
+}
+
+#[tauri::command]
+#[auto_collect_command]
+pub fn get_artist(app_handle: AppHandle, artist_id: String) -> Result<ArtistDetail, String> {
+    let library = read_library(&app_handle)?;
+    let catalog = Catalog::build(&library);
+    let artist = catalog
+        .artist(&artist_id)
+        .cloned()
+        .ok_or_else(|| "艺术家不存在".to_string())?;
+    let (albums, appears_on) = catalog.artist_albums(&artist_id);
+    Ok(ArtistDetail {
+        tracks: catalog.artist_tracks(&artist_id),
+        artist,
+        albums,
+        appears_on,
+    })
+}
@@ -0,0 +1,120 @@
+use image::ImageFormat;
+use lofty::picture::PictureType;
+use lofty::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri::Manager;
+use tauri_helper::auto_collect_command;
+
+const COVER_CACHE_DIR: &str = "covers";
+const THUMBNAIL_SIZE: u32 = 300;
+
+// 同目录下常见的封面文件名（不含扩展名），按优先级排列
+const FOLDER_COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "albumart"];
+const FOLDER_COVER_EXTS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// 返回歌曲封面缩略图在缓存目录中的路径，前端通过 asset 协议加载；没有封面时返回 None
+#[tauri::command]
+#[auto_collect_command]
+pub async fn get_cover_art(app_handle: AppHandle, path: String) -> Result<Option<String>, String> {
+    let cache_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(COVER_CACHE_DIR);
+
+    tokio::task::spawn_blocking(move || {
+        cache_cover(&cache_dir, Path::new(&path))
+            .map(|cached| cached.map(|p| p.to_string_lossy().to_string()))
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// 提取封面并写入缩略图缓存，缓存文件以原图内容的哈希命名，同一张专辑封面只会生成一次
+pub(crate) fn cache_cover(cache_dir: &Path, path: &Path) -> Result<Option<PathBuf>, String> {
+    let Some(bytes) = extract_cover_bytes(path) else {
+        return Ok(None);
+    };
+
+    let hash = blake3::hash(&bytes).to_hex();
+    let cached = cache_dir.join(format!("{}.jpg", &hash[..32]));
+    if cached.exists() {
+        return Ok(Some(cached));
+    }
+
+    if !cache_dir.exists() {
+        fs::create_dir_all(cache_dir).map_err(|e| format!("创建封面缓存目录失败: {}", e))?;
+    }
+
+    // 先写到临时文件再改名，其他请求不会读到写了一半的缩略图；
+    // 同一张封面可能被同时请求，临时文件名各不相同
+    let img = image::load_from_memory(&bytes).map_err(|e| format!("解码封面失败: {}", e))?;
+    let tmp = cache_dir.join(format!(
+        "{}.{}.tmp",
+        &hash[..32],
+        uuid::Uuid::new_v4().simple()
+    ));
+    let saved = img
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgb8()
+        .save_with_format(&tmp, ImageFormat::Jpeg)
+        .map_err(|e| e.to_string())
+        .and_then(|_| fs::rename(&tmp, &cached).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("保存封面缩略图失败: {}", e));
+    }
+
+    Ok(Some(cached))
+}
+
+/// 优先使用内嵌封面（ID3 APIC / FLAC PICTURE / MP4 covr），其次是同目录的封面图片
+fn extract_cover_bytes(path: &Path) -> Option<Vec<u8>> {
+    if !path.is_file() {
+        return None;
+    }
+    extract_embedded_cover(path)
+        .or_else(|| find_folder_cover(path).and_then(|cover| fs::read(cover).ok()))
+}
+
+fn extract_embedded_cover(path: &Path) -> Option<Vec<u8>> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let pictures = || tagged_file.tags().iter().flat_map(|tag| tag.pictures());
+
+    pictures()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures().next())
+        .map(|p| p.data().to_vec())
+}
+
+/// 是否是常见的目录封面文件，如 cover.jpg、Folder.png
+pub(crate) fn is_folder_cover(path: &Path) -> bool {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+    let ext = path.extension().map(|s| s.to_string_lossy().to_lowercase());
+    stem.is_some_and(|stem| FOLDER_COVER_NAMES.contains(&stem.as_str()))
+        && ext.is_some_and(|ext| FOLDER_COVER_EXTS.contains(&ext.as_str()))
+}
+
+fn find_folder_cover(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let candidates: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.is_file())
+        .collect();
+
+    FOLDER_COVER_NAMES.iter().find_map(|name| {
+        candidates
+            .iter()
+            .find(|p| {
+                let stem = p.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+                let ext = p.extension().map(|s| s.to_string_lossy().to_lowercase());
+                stem.as_deref() == Some(name)
+                    && ext.is_some_and(|ext| FOLDER_COVER_EXTS.contains(&ext.as_str()))
+            })
+            .cloned()
+    })
+}
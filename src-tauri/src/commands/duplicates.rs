@@ -0,0 +1,297 @@
+use crate::acoustic::{self, FingerprintIndex};
+use crate::commands::library_edit::trash_files;
+use crate::commands::library_roots::exclude_file;
+use crate::commands::playlists::notify_playlist_change;
+use crate::commands::relocate::QUEUE_CHANGED_EVENT;
+use crate::commands::replay_gain::fingerprint_is_current;
+use crate::db::{load_acoustic_fingerprints, read_library, read_playlists};
+use crate::models::{
+    BitrateMode, DuplicateEntry, DuplicateGroup, DuplicateReport, DuplicateResolution, MusicFile,
+    ScanDiff,
+};
+use crate::store::{self, with_db};
+use crate::watcher::LIBRARY_CHANGED_EVENT;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+// 同一首歌的不同编码一般在 0.85 以上，不同的歌在 0.5 左右
+const DEFAULT_SIMILARITY: f64 = 0.75;
+
+/// 按声学指纹查找曲库里的重复录音（如同一首歌的 MP3、FLAC 和 B 站下载版），
+/// 不看文件名和标签。只有运行过响度分析、指纹没有过期的本地歌曲参与比较。
+/// min_similarity 为 0～1，默认 0.75
+#[tauri::command]
+#[auto_collect_command]
+pub async fn find_duplicates(
+    app_handle: AppHandle,
+    min_similarity: Option<f64>,
+) -> Result<DuplicateReport, String> {
+    let library = read_library(&app_handle)?;
+    let stored = load_acoustic_fingerprints(&app_handle)?;
+    let playlists = read_playlists(&app_handle)?;
+    let threshold = min_similarity.unwrap_or(DEFAULT_SIMILARITY).clamp(0.5, 1.0);
+
+    tokio::task::spawn_blocking(move || {
+        let mut report = DuplicateReport::default();
+        let mut songs: Vec<&MusicFile> = Vec::new();
+        let mut fingerprints: Vec<Vec<u32>> = Vec::new();
+        for song in library.iter().filter(|s| s.is_online != Some(true)) {
+            let fingerprint = fingerprint_is_current(song, &stored)
+                .then(|| acoustic::decode(&stored[&song.id].fingerprint))
+                .flatten();
+            match fingerprint {
+                Some(fingerprint) => {
+                    songs.push(song);
+                    fingerprints.push(fingerprint);
+                }
+                None => report.unanalyzed += 1,
+            }
+        }
+
+        let index = FingerprintIndex::new(&fingerprints);
+        let mut matches: HashMap<(usize, usize), (f64, f64)> = HashMap::new();
+        let mut groups = DisjointSet::new(songs.len());
+        for i in 0..songs.len() {
+            for (j, similarity, offset) in index.matches(i, threshold) {
+                matches.insert((i, j), (similarity, offset));
+                groups.union(i, j);
+            }
+        }
+
+        let mut playlist_counts: HashMap<&str, usize> = HashMap::new();
+        for playlist in &playlists {
+            let ids: HashSet<&str> = playlist.songs.iter().map(|s| s.id.as_str()).collect();
+            for id in ids {
+                *playlist_counts.entry(id).or_default() += 1;
+            }
+        }
+
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..songs.len() {
+            members.entry(groups.find(i)).or_default().push(i);
+        }
+        for mut group in members.into_values().filter(|g| g.len() > 1) {
+            group.sort_by(|&a, &b| quality_rank(songs[b]).cmp(&quality_rank(songs[a])));
+            let keep = group[0];
+            let entries = group
+                .iter()
+                .map(|&i| {
+                    let (similarity, offset) = if i == keep {
+                        (1.0, Some(0.0))
+                    } else if let Some(&(s, offset)) = matches.get(&(i, keep)) {
+                        (s, Some(offset))
+                    } else if let Some(&(s, offset)) = matches.get(&(keep, i)) {
+                        (s, Some(-offset))
+                    } else {
+                        let closest = matches
+                            .iter()
+                            .filter(|((a, b), _)| *a == i || *b == i)
+                            .map(|(_, &(s, _))| s)
+                            .fold(0.0, f64::max);
+                        (closest, None)
+                    };
+                    DuplicateEntry {
+                        song: songs[i].clone(),
+                        similarity,
+                        offset,
+                        playlist_count: playlist_counts
+                            .get(songs[i].id.as_str())
+                            .copied()
+                            .unwrap_or(0),
+                    }
+                })
+                .collect();
+            report.groups.push(DuplicateGroup {
+                keep: songs[keep].id.clone(),
+                songs: entries,
+            });
+        }
+        report
+            .groups
+            .sort_by(|a, b| a.songs[0].song.name.cmp(&b.songs[0].song.name));
+
+        println!(
+            "[查重] {}首歌曲中找到{}组重复，{}首没有指纹",
+            songs.len(),
+            report.groups.len(),
+            report.unanalyzed
+        );
+        report
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))
+}
+
+/// 保留 keep，移除 remove 中的歌曲：歌单和播放队列里的引用换成保留的歌曲，
+/// 保留歌曲缺失的标签从被移除的歌曲补上（只改曲库记录，不写文件）。
+/// delete_files 为 true 时同时把被移除歌曲的文件移到回收站，否则文件留在磁盘上并加入根目录的排除规则，
+/// 以免下次扫描又被收录。CUE 分轨和在线歌曲只移除曲库记录。
+/// 文件在曲库记录删除之后才移到回收站；移动失败的文件留在磁盘上并加入排除规则，记在 failed 里
+#[tauri::command]
+#[auto_collect_command]
+pub async fn resolve_duplicates(
+    app_handle: AppHandle,
+    keep: String,
+    remove: Vec<String>,
+    delete_files: Option<bool>,
+) -> Result<DuplicateResolution, String> {
+    let delete_files = delete_files.unwrap_or(false);
+    let (mut result, owned, removed, playlists) = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let Some(mut kept) = store::read_song(&tx, &keep)? else {
+            return Ok(Err("要保留的歌曲不在曲库中".to_string()));
+        };
+        let mut targets: Vec<MusicFile> = Vec::new();
+        for id in remove.iter().filter(|id| **id != keep) {
+            if targets.iter().any(|s| s.id == *id) {
+                continue;
+            }
+            targets.extend(store::read_song(&tx, id)?);
+        }
+        if targets.is_empty() {
+            return Ok(Err("没有要移除的歌曲".to_string()));
+        }
+
+        // 独占文件的歌曲：删除文件时提交后移到回收站，否则加进排除规则，以免下次扫描又被收录
+        let owned: Vec<MusicFile> = targets
+            .iter()
+            .filter(|s| s.is_online != Some(true) && s.source_path.is_none() && s.path != kept.path)
+            .cloned()
+            .collect();
+        let paths: Vec<String> = owned.iter().map(|s| s.path.clone()).collect();
+        store::delete_index_entries(&tx, &paths)?;
+        if !delete_files {
+            let mut settings = store::read_settings(&tx)?;
+            let mut changed = false;
+            for path in &paths {
+                changed |= exclude_file(&mut settings, Path::new(path));
+            }
+            if changed {
+                store::write_settings(&tx, &settings)?;
+            }
+        }
+
+        for song in &targets {
+            merge_missing_tags(&mut kept, song);
+        }
+        let mut changed = store::update_song(&tx, &kept)?;
+        let mut result = DuplicateResolution::default();
+        let mut replaced: HashSet<String> = HashSet::new();
+        // 被移除歌曲的指纹随曲库记录一起删除
+        for song in &targets {
+            let (playlists, queue) = store::redirect_song(&tx, &song.id, &kept)?;
+            for id in playlists {
+                if !changed.contains(&id) {
+                    changed.push(id.clone());
+                }
+                replaced.insert(id);
+            }
+            result.queue += queue;
+            result.removed.push(song.id.clone());
+        }
+        result.playlists = replaced.len();
+
+        let mut playlists = Vec::with_capacity(changed.len());
+        for id in &changed {
+            playlists.extend(store::read_playlist(&tx, id)?);
+        }
+        tx.commit()?;
+        result.kept = kept;
+        let removed: Vec<String> = targets.into_iter().map(|s| s.path).collect();
+        Ok(Ok((result, owned, removed, playlists)))
+    })??;
+
+    if delete_files {
+        let (trashed, failed) = trash_files(&app_handle, &owned);
+        result.trashed_files = trashed;
+        result.failed = failed;
+    }
+    for playlist in &playlists {
+        notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+    }
+    // 播放队列里被移除的歌曲已换成保留的歌曲，保留的歌曲也合并了标签
+    let queue: HashMap<&str, &MusicFile> = result
+        .removed
+        .iter()
+        .chain([&result.kept.id])
+        .map(|id| (id.as_str(), &result.kept))
+        .collect();
+    let _ = app_handle.emit(QUEUE_CHANGED_EVENT, &queue);
+
+    println!(
+        "[查重] 保留 {}，移除{}首，移到回收站{}个文件，更新歌单{}个",
+        result.kept.path,
+        result.removed.len(),
+        result.trashed_files.len(),
+        result.playlists
+    );
+    let diff = ScanDiff {
+        updated: vec![result.kept.clone()],
+        removed,
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    Ok(result)
+}
+
+/// 音质排序：无损优先，其次位深、采样率、码率；B 站下载的版本排在本地文件后面
+fn quality_rank(song: &MusicFile) -> (bool, u32, u32, u32, bool) {
+    (
+        song.bitrate_mode == Some(BitrateMode::Lossless),
+        song.bit_depth.unwrap_or(0),
+        song.sample_rate.unwrap_or(0),
+        song.bitrate.unwrap_or(0),
+        song.bv_id.is_none(),
+    )
+}
+
+fn merge_missing_tags(target: &mut MusicFile, source: &MusicFile) {
+    fn fill<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+        if target.is_none() {
+            target.clone_from(value);
+        }
+    }
+
+    fill(&mut target.title, &source.title);
+    fill(&mut target.artist, &source.artist);
+    fill(&mut target.album, &source.album);
+    fill(&mut target.album_artist, &source.album_artist);
+    fill(&mut target.track_number, &source.track_number);
+    fill(&mut target.disc_number, &source.disc_number);
+    fill(&mut target.year, &source.year);
+    fill(&mut target.genre, &source.genre);
+}
+
+/// 并查集，把两两相似的歌曲连成组
+struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> Self {
+        Self {
+            parent: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut current = i;
+        while self.parent[current] != root {
+            current = std::mem::replace(&mut self.parent[current], root);
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b] = a;
+        }
+    }
+}
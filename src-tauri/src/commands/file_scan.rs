@@ -1,61 +1,636 @@
-use crate::models::MusicFile;
-use anyhow::{anyhow, bail, Context, Error, Result};
-use jwalk::WalkDir;
-use serde_json::error;
-use std::path::Path;
+use crate::commands::library_roots::{rules_for, unfiltered_rules, ScanRules};
+use crate::commands::playlist_import::import_discovered;
+use crate::commands::scan_progress::ScanTracker;
+use crate::commands::tags::{read_music_file, read_music_file_with};
+use crate::cue::{is_cue, referenced_audio, CueIndex};
+use crate::db::{
+    load_library_index, read_library, read_settings, save_library_index, save_scan_result,
+    write_settings, IndexEntry, LibraryIndex, LibraryRoot,
+};
+use crate::filename_pattern::FilenamePattern;
+use crate::models::{MusicFile, ScanDiff};
+use crate::playlist_file::is_playlist_file;
+use crate::song_id::{ensure_song_ids, file_fingerprint};
+use crate::watcher::watch_root;
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::AppHandle;
 use tauri_helper::auto_collect_command;
 
-// 定义支持的格式常量，方便管理
-const SUPPORTED_EXTS: [&str; 4] = ["mp3", "wav", "flac", "m4a"];
+// 可能是音频的扩展名，只对这些文件读取文件头，最终是否收录由文件内容决定
+const SUPPORTED_EXTS: [&str; 20] = [
+    "mp3", "mp2", "wav", "flac", "m4a", "m4b", "mp4", "aac", "ogg", "oga", "opus", "spx", "aif",
+    "aiff", "aifc", "ape", "wv", "dsf", "dff", "alac",
+];
+// 读取文件的解析版本，读取的内容有变化时加一，已收录的文件会在下次扫描时重新读取。
+// 1：增加容器、比特率、采样率、位深、声道数
+// 2：增加合辑标记
+const READER_VERSION: u32 = 2;
 
 #[tauri::command]
 #[auto_collect_command]
-pub fn add_music(target_file: String) -> Result<MusicFile, String> {
-    extract_filename(&target_file)
-        .map(|name| MusicFile {
-            path: target_file,
-            name: name,
-            is_online: None,
-            bv_id: None,
-            page: None,
-        })
-        .map_err(|err| format!("Error:{}", err))
+pub fn add_music(app_handle: AppHandle, target_file: String) -> Result<MusicFile, String> {
+    extract_filename(&target_file).map_err(|err| format!("Error:{}", err))?;
+    let music =
+        read_music_file(Path::new(&target_file)).ok_or_else(|| "不支持的音频格式".to_string())?;
+    let mut songs = [music];
+    ensure_song_ids(&mut songs, &read_library(&app_handle)?);
+    let [music] = songs;
+    Ok(music)
 }
 
+/// 一次性扫描目录里的所有音频文件（包括隐藏文件），不套用曲库根目录的收录规则。
+/// scan_id 可选，传入后可以用 cancel_scan 中途取消，取消时返回已扫描到的部分结果
 #[tauri::command()]
 #[auto_collect_command]
-pub async fn scan_music(target_dir: String) -> Result<Vec<MusicFile>, String> {
-    let target_dir = target_dir.clone();
-    let result = tokio::task::spawn_blocking(move || {
+pub async fn scan_music(
+    app_handle: AppHandle,
+    target_dir: String,
+    scan_id: Option<String>,
+) -> Result<Vec<MusicFile>, String> {
+    let rules = unfiltered_rules(&app_handle, &target_dir)?;
+    let mut library = read_library(&app_handle)?;
+    let mut tracker = ScanTracker::new(app_handle.clone(), scan_id);
+    let (music_files, playlist_files, library) = tokio::task::spawn_blocking(move || {
         println!("targetdir:{}", target_dir);
         let mut music_files: Vec<MusicFile> = Vec::new();
-        for entry in WalkDir::new(&target_dir) {
-            let Ok(entry) = entry else { continue };
-            let path = entry.path();
-            if !path.is_file() {
+        let mut playlist_files = Vec::new();
+        let mut cues = CueIndex::default();
+        for path in rules.walk(&rules.root) {
+            if !tracker.visit(&path) {
+                break;
+            }
+            if is_playlist_file(&path) {
+                playlist_files.push(path);
                 continue;
             }
-
-            if let Some(extension) = path.extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-                if SUPPORTED_EXTS.contains(&ext.as_str()) {
-                    music_files.push(MusicFile {
-                        path: path.to_string_lossy().to_string(),
-                        name: entry.file_name().to_string_lossy().to_string(),
-                        is_online: None,
-                        bv_id: None,
-                        page: None,
-                    });
-                }
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if !is_supported(&path) || !rules.accepts_path(&path, size) {
+                continue;
             }
+            let (_, songs) = read_music_entries(&path, &rules.filename_patterns, &mut cues);
+            let songs: Vec<MusicFile> = songs
+                .into_iter()
+                .filter(|m| rules.accepts_song(m))
+                .collect();
+            // 按文件计数，CUE 拆出的多首歌算一个文件
+            if !songs.is_empty() {
+                tracker.found_audio(&path);
+                music_files.extend(songs);
+            }
+        }
+        if tracker.is_cancelled() {
+            println!("扫描已取消，已找到{}首歌", music_files.len());
+        } else {
+            println!("扫描完成，找到{}首歌", music_files.len());
         }
-        println!("扫描完成，找到{}首歌", music_files.len());
-        music_files
+        tracker.finish();
+        // 已在曲库里的文件沿用原来的 ID
+        ensure_song_ids(&mut music_files, &library);
+        library.extend(music_files.iter().cloned());
+        (music_files, playlist_files, library)
     })
     .await
     .map_err(|e| format!("Task error: {}", e))?;
 
-    Ok(result)
+    // 播放列表里的歌曲可能还没进曲库，连同这次扫到的歌曲一起查找
+    let mut index = load_library_index(&app_handle)?;
+    if !import_discovered(&app_handle, &playlist_files, &library, &mut index).is_empty() {
+        save_library_index(&app_handle, &index)?;
+    }
+
+    Ok(music_files)
+}
+
+/// 增量扫描：只重新读取大小或修改时间变化过的文件，直接更新曲库并返回变化。
+/// 目录还不是曲库根目录时会以默认规则加入设置。
+/// 与 scan_music 一样会发送进度事件并支持取消，取消时不会移除任何歌曲
+#[tauri::command]
+#[auto_collect_command]
+pub async fn rescan_music(
+    app_handle: AppHandle,
+    target_dir: String,
+    scan_id: Option<String>,
+) -> Result<ScanDiff, String> {
+    if !Path::new(&target_dir).is_dir() {
+        return Err(format!("目录不存在: {}", target_dir));
+    }
+
+    let mut settings = read_settings(&app_handle)?;
+    let root = match settings
+        .library_roots
+        .iter()
+        .find(|r| Path::new(&r.path) == Path::new(&target_dir))
+    {
+        Some(root) => root.clone(),
+        None => {
+            let root = LibraryRoot::new(&target_dir);
+            settings.library_roots.push(root.clone());
+            write_settings(&app_handle, &settings)?;
+            watch_root(&app_handle, &target_dir);
+            root
+        }
+    };
+    let rules = ScanRules::new(&root, &settings.filename_patterns)?;
+
+    let mut index = load_library_index(&app_handle)?;
+    let mut library = read_library(&app_handle)?;
+    let before = index.clone();
+    let mut tracker = ScanTracker::new(app_handle.clone(), scan_id);
+
+    let (mut diff, mut index, library) = tokio::task::spawn_blocking(move || {
+        let diff = incremental_scan(&rules, &mut index, &mut library, &mut tracker);
+        tracker.finish();
+        (diff, index, library)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+
+    // 播放列表里的歌曲在扫描结果里查找，保存时只写入变化的部分
+    diff.playlists = import_discovered(&app_handle, &diff.playlist_files, &library, &mut index);
+    save_scan_result(&app_handle, &diff, &before, &index)?;
+
+    println!(
+        "增量扫描{}：新增{}首，更新{}首，移除{}首",
+        if diff.cancelled {
+            "已取消"
+        } else {
+            "完成"
+        },
+        diff.added.len(),
+        diff.updated.len(),
+        diff.removed.len()
+    );
+    Ok(diff)
+}
+
+/// 按根目录的规则扫描一遍，不再符合规则或已不存在的文件会从曲库移除
+pub(crate) fn incremental_scan(
+    rules: &ScanRules,
+    index: &mut LibraryIndex,
+    library: &mut Vec<MusicFile>,
+    tracker: &mut ScanTracker,
+) -> ScanDiff {
+    let mut diff = ScanDiff::default();
+    let mut groups = LibraryGroups::new(std::mem::take(library));
+    let mut seen: HashSet<String> = HashSet::new();
+
+    // 先走完目录再处理，这样 .cue 无论排在音频文件前后都能生效
+    let mut audio_files = Vec::new();
+    let mut cue_files = Vec::new();
+    for path in rules.walk(&rules.root) {
+        if !tracker.visit(&path) {
+            break;
+        }
+        if is_cue(&path) {
+            cue_files.push(path);
+        } else if is_playlist_file(&path) {
+            diff.playlist_files.push(path);
+        } else if is_supported(&path) {
+            audio_files.push(path);
+        }
+    }
+
+    let forced = changed_cue_targets(&cue_files, index);
+    let mut cues = CueIndex::default();
+    for path in audio_files {
+        if tracker.is_cancelled() {
+            break;
+        }
+        let key = path.to_string_lossy().to_string();
+        let refresh = refresh_file(
+            &path,
+            forced.contains(&path),
+            rules,
+            index,
+            &mut groups,
+            &mut cues,
+            &mut diff,
+        );
+        match refresh {
+            Refresh::Accepted => {
+                seen.insert(key);
+                tracker.found_audio(&path);
+            }
+            Refresh::Unreadable => {
+                seen.insert(key);
+            }
+            Refresh::Rejected => {}
+        }
+    }
+
+    // 取消时没走完整个目录，不能据此判断哪些文件被删除了
+    if tracker.is_cancelled() {
+        diff.cancelled = true;
+    } else {
+        // 根目录下没有再出现的文件视为已删除
+        let is_gone = |key: &str| Path::new(key).starts_with(&rules.root) && !seen.contains(key);
+        diff.removed.extend(groups.remove_files(index, is_gone));
+    }
+
+    groups.carry_over_ids(index, &mut diff);
+    *library = groups.into_library();
+    diff
+}
+
+/// 根据文件系统变化的路径更新曲库，供目录监听使用；
+/// 路径已不存在时移除它（以及它下面）的所有歌曲，目录则整体扫描
+pub(crate) fn apply_path_changes(
+    paths: &[PathBuf],
+    rules: &[ScanRules],
+    index: &mut LibraryIndex,
+    library: &mut Vec<MusicFile>,
+) -> ScanDiff {
+    let mut diff = ScanDiff::default();
+    let mut groups = LibraryGroups::new(std::mem::take(library));
+    let mut cues = CueIndex::default();
+
+    for path in paths {
+        if !path.exists() {
+            if is_cue(path) {
+                // .cue 被删除后，原来拆分的整轨文件恢复成一首歌
+                let cue = path.to_string_lossy().to_string();
+                let targets: Vec<PathBuf> = index
+                    .entries
+                    .iter()
+                    .filter(|(_, e)| e.cue_path.as_deref() == Some(cue.as_str()))
+                    .map(|(p, _)| PathBuf::from(p))
+                    .collect();
+                for target in targets {
+                    if let Some(rule) = rules_for(rules, &target) {
+                        update_file(
+                            &target,
+                            true,
+                            rule,
+                            index,
+                            &mut groups,
+                            &mut cues,
+                            &mut diff,
+                        );
+                    }
+                }
+                continue;
+            }
+
+            diff.removed
+                .extend(groups.remove_files(index, |key| Path::new(key).starts_with(path)));
+            continue;
+        }
+
+        let Some(rule) = rules_for(rules, path) else {
+            continue;
+        };
+        if path.is_dir() {
+            let (audio_files, cue_files): (Vec<PathBuf>, Vec<PathBuf>) = rule
+                .walk(path)
+                .filter(|p| {
+                    if is_playlist_file(p) {
+                        diff.playlist_files.push(p.clone());
+                    }
+                    is_supported(p) || is_cue(p)
+                })
+                .partition(|p| !is_cue(p));
+            let forced = changed_cue_targets(&cue_files, index);
+            for file in audio_files {
+                let force = forced.contains(&file);
+                update_file(&file, force, rule, index, &mut groups, &mut cues, &mut diff);
+            }
+        } else if is_cue(path) {
+            for file in referenced_audio(path) {
+                update_file(&file, true, rule, index, &mut groups, &mut cues, &mut diff);
+            }
+        } else if is_playlist_file(path) {
+            diff.playlist_files.push(path.clone());
+        } else if is_supported(path) {
+            update_file(path, false, rule, index, &mut groups, &mut cues, &mut diff);
+        }
+    }
+
+    groups.carry_over_ids(index, &mut diff);
+    *library = groups.into_library();
+    diff
+}
+
+/// 刷新单个文件，不再符合规则时把它从曲库移除
+fn update_file(
+    path: &Path,
+    force: bool,
+    rules: &ScanRules,
+    index: &mut LibraryIndex,
+    groups: &mut LibraryGroups,
+    cues: &mut CueIndex,
+    diff: &mut ScanDiff,
+) {
+    if refresh_file(path, force, rules, index, groups, cues, diff) == Refresh::Rejected {
+        let key = path.to_string_lossy().to_string();
+        diff.removed
+            .extend(groups.remove_files(index, |k| k == key));
+    }
+}
+
+/// 内容有变化的 .cue 所引用的音频文件，需要强制重新拆分
+fn changed_cue_targets(cue_files: &[PathBuf], index: &LibraryIndex) -> HashSet<PathBuf> {
+    let mut targets = HashSet::new();
+    for cue in cue_files {
+        let cue_str = cue.to_string_lossy().to_string();
+        let cue_mtime = fs::metadata(cue).ok().map(|m| modified_millis(&m));
+        for audio in referenced_audio(cue) {
+            let key = audio.to_string_lossy().to_string();
+            let up_to_date = index.entries.get(&key).is_some_and(|e| {
+                e.cue_path.as_deref() == Some(cue_str.as_str()) && e.cue_mtime == cue_mtime
+            });
+            if !up_to_date {
+                targets.insert(audio);
+            }
+        }
+    }
+    targets
+}
+
+/// 读取一个音频文件对应的所有歌曲：有 .cue 时为拆分后的各轨，否则为文件本身
+pub(crate) fn read_music_entries(
+    path: &Path,
+    patterns: &[FilenamePattern],
+    cues: &mut CueIndex,
+) -> (Option<PathBuf>, Vec<MusicFile>) {
+    match cues.tracks_for(path) {
+        Some((cue_path, songs)) => (Some(cue_path), songs),
+        None => (
+            None,
+            read_music_file_with(path, patterns).into_iter().collect(),
+        ),
+    }
+}
+
+/// refresh_file 的结果
+#[derive(PartialEq)]
+enum Refresh {
+    /// 符合规则的音频文件，留在曲库里
+    Accepted,
+    /// 已收录的文件暂时读不到（比如网络盘抖动），保留原有记录
+    Unreadable,
+    /// 不符合规则或读不出音频，不应留在曲库里
+    Rejected,
+}
+
+/// 大小、修改时间（以及 .cue）都没变的已知文件直接跳过，否则重新读取并记录到 diff
+fn refresh_file(
+    path: &Path,
+    force: bool,
+    rules: &ScanRules,
+    index: &mut LibraryIndex,
+    groups: &mut LibraryGroups,
+    cues: &mut CueIndex,
+    diff: &mut ScanDiff,
+) -> Refresh {
+    let key = path.to_string_lossy().to_string();
+    let known = groups.get(&key).filter(|songs| !songs.is_empty());
+    let Ok(meta) = fs::metadata(path) else {
+        return match known {
+            Some(_) => Refresh::Unreadable,
+            None => Refresh::Rejected,
+        };
+    };
+    let size = meta.len();
+    let mtime = modified_millis(&meta);
+    if !rules.accepts_path(path, size) {
+        return Refresh::Rejected;
+    }
+
+    if let (Some(songs), false) = (known, force) {
+        let unchanged = index.entries.get(&key).is_some_and(|e| {
+            e.size == size
+                && e.mtime == mtime
+                && e.reader_version >= READER_VERSION
+                && cue_unchanged(e)
+        });
+        if unchanged {
+            return if songs.iter().any(|s| rules.accepts_song(s)) {
+                Refresh::Accepted
+            } else {
+                Refresh::Rejected
+            };
+        }
+    }
+
+    let (cue_path, songs) = read_music_entries(path, &rules.filename_patterns, cues);
+    let mut songs: Vec<MusicFile> = songs
+        .into_iter()
+        .filter(|s| rules.accepts_song(s))
+        .collect();
+    if songs.is_empty() {
+        return Refresh::Rejected;
+    }
+    // 重新读取的歌曲沿用同一路径原来的 ID，新文件生成新 ID（改名的情况最后统一处理）
+    ensure_song_ids(&mut songs, groups.get(&key).unwrap_or_default());
+
+    index.entries.insert(
+        key.clone(),
+        IndexEntry {
+            size,
+            mtime,
+            tag_hash: tag_hash(&songs),
+            cue_mtime: cue_path
+                .as_ref()
+                .and_then(|c| fs::metadata(c).ok())
+                .map(|m| modified_millis(&m)),
+            cue_path: cue_path.map(|c| c.to_string_lossy().to_string()),
+            fingerprint: file_fingerprint(path),
+            reader_version: READER_VERSION,
+        },
+    );
+
+    let old = groups.replace(&key, songs.clone());
+    for song in &songs {
+        match old.iter().find(|o| o.path == song.path) {
+            None => diff.added.push(song.clone()),
+            Some(o)
+                if tag_hash(std::slice::from_ref(o)) != tag_hash(std::slice::from_ref(song)) =>
+            {
+                diff.updated.push(song.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed.extend(
+        old.into_iter()
+            .filter(|o| !songs.iter().any(|s| s.path == o.path))
+            .map(|o| o.path),
+    );
+    Refresh::Accepted
+}
+
+fn cue_unchanged(entry: &IndexEntry) -> bool {
+    match &entry.cue_path {
+        Some(cue) => fs::metadata(cue).ok().map(|m| modified_millis(&m)) == entry.cue_mtime,
+        None => true,
+    }
+}
+
+/// 应用自己改写了文件（比如写入标签）之后重新生成索引记录，免得下次扫描再读一遍。
+/// 写入时用 store::touch_index_entry，.cue 信息沿用原来的记录
+pub(crate) fn index_entry_for(path: &Path, songs: &[MusicFile]) -> Option<IndexEntry> {
+    let meta = fs::metadata(path).ok()?;
+    Some(IndexEntry {
+        size: meta.len(),
+        mtime: modified_millis(&meta),
+        tag_hash: tag_hash(songs),
+        cue_path: None,
+        cue_mtime: None,
+        fingerprint: file_fingerprint(path),
+        reader_version: READER_VERSION,
+    })
+}
+
+/// 歌曲实际所在的文件：CUE 分轨为整轨文件，其余为自身路径
+pub(crate) fn file_key(song: &MusicFile) -> &str {
+    song.source_path.as_deref().unwrap_or(&song.path)
+}
+
+/// 扫描期间按文件分组的曲库。CUE 整轨文件对应多首虚拟歌曲，其余文件对应一首；
+/// 展开回列表时保持原有顺序
+struct LibraryGroups {
+    groups: Vec<(String, Vec<MusicFile>)>,
+    positions: HashMap<String, usize>,
+    /// 本次移除的文件，按内容指纹记录原来的歌曲
+    removed: HashMap<String, Vec<MusicFile>>,
+}
+
+impl LibraryGroups {
+    fn new(library: Vec<MusicFile>) -> Self {
+        let mut groups: Vec<(String, Vec<MusicFile>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for song in library {
+            let key = file_key(&song).to_string();
+            match positions.get(&key) {
+                Some(&i) => groups[i].1.push(song),
+                None => {
+                    positions.insert(key.clone(), groups.len());
+                    groups.push((key, vec![song]));
+                }
+            }
+        }
+        Self {
+            groups,
+            positions,
+            removed: HashMap::new(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&[MusicFile]> {
+        self.positions
+            .get(key)
+            .map(|&i| self.groups[i].1.as_slice())
+    }
+
+    /// 替换一个文件对应的歌曲，返回原来的歌曲
+    fn replace(&mut self, key: &str, songs: Vec<MusicFile>) -> Vec<MusicFile> {
+        match self.positions.get(key) {
+            Some(&i) => std::mem::replace(&mut self.groups[i].1, songs),
+            None => {
+                self.positions.insert(key.to_string(), self.groups.len());
+                self.groups.push((key.to_string(), songs));
+                vec![]
+            }
+        }
+    }
+
+    /// 移除满足条件的本地文件对应的全部歌曲和索引，在线歌曲不受影响。
+    /// 返回被移除歌曲的路径
+    fn remove_files(
+        &mut self,
+        index: &mut LibraryIndex,
+        is_gone: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let mut removed_paths = Vec::new();
+        for (key, songs) in &mut self.groups {
+            if !is_gone(key) {
+                continue;
+            }
+            let (online, local): (Vec<MusicFile>, Vec<MusicFile>) = std::mem::take(songs)
+                .into_iter()
+                .partition(|s| s.is_online == Some(true));
+            *songs = online;
+            if local.is_empty() {
+                continue;
+            }
+            removed_paths.extend(local.iter().map(|s| s.path.clone()));
+            if let Some(fingerprint) = index
+                .entries
+                .get(key.as_str())
+                .and_then(|e| e.fingerprint.clone())
+            {
+                self.removed.insert(fingerprint, local);
+            }
+        }
+        index.entries.retain(|key, _| !is_gone(key));
+        removed_paths
+    }
+
+    /// 新出现的文件与本次移除的某个文件内容相同时（改名、移动），沿用原来的歌曲 ID，
+    /// 这样歌单、收藏里对它的引用不会丢失
+    fn carry_over_ids(&mut self, index: &LibraryIndex, diff: &mut ScanDiff) {
+        if self.removed.is_empty() {
+            return;
+        }
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        for song in &diff.added {
+            let Some(old_songs) = index
+                .entries
+                .get(file_key(song))
+                .and_then(|e| e.fingerprint.as_ref())
+                .and_then(|fingerprint| self.removed.get(fingerprint))
+            else {
+                continue;
+            };
+            // CUE 分轨按音轨号对应
+            let old = old_songs
+                .iter()
+                .find(|o| o.source_path.is_none() || o.track_number == song.track_number);
+            if let Some(old) = old {
+                renamed.insert(song.id.clone(), old.id.clone());
+            }
+        }
+
+        let songs = self
+            .groups
+            .iter_mut()
+            .flat_map(|(_, songs)| songs.iter_mut());
+        for song in diff.added.iter_mut().chain(songs) {
+            if let Some(id) = renamed.get(&song.id) {
+                song.id = id.clone();
+            }
+        }
+    }
+
+    fn into_library(self) -> Vec<MusicFile> {
+        self.groups
+            .into_iter()
+            .flat_map(|(_, songs)| songs)
+            .collect()
+    }
+}
+
+pub(crate) fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| SUPPORTED_EXTS.contains(&ext.as_str()))
+}
+
+fn modified_millis(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn tag_hash(songs: &[MusicFile]) -> String {
+    let bytes = serde_json::to_vec(songs).unwrap_or_default();
+    blake3::hash(&bytes).to_hex().to_string()
 }
 
 fn extract_filename(path: &str) -> Result<String> {
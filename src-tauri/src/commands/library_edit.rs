@@ -0,0 +1,327 @@
+use crate::commands::file_scan::file_key;
+use crate::commands::library_roots::exclude_file;
+use crate::commands::playlists::notify_playlist_change;
+use crate::models::{LibraryRemoval, MusicFile, Playlist, ScanDiff, TagEditFailure};
+use crate::song_id::new_song_id;
+use crate::store::{self, with_db};
+use crate::watcher::LIBRARY_CHANGED_EVENT;
+use rusqlite::Connection;
+use std::path::Path;
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 从曲库移除歌曲，同时从所有歌单和播放队列里去掉。
+/// delete_files 为 true 时把文件移到回收站，否则把文件加进根目录的排除规则，
+/// 以免下次扫描又被收录。在线歌曲只移除曲库记录；CUE 分轨不删除文件，
+/// 同一个 CUE 的分轨都被移除后把整轨文件加进排除规则。
+/// 文件在曲库记录删除之后才移到回收站；移动失败的文件留在磁盘上并加入排除规则，记在 failed 里
+#[tauri::command]
+#[auto_collect_command]
+pub async fn remove_from_library(
+    app_handle: AppHandle,
+    song_ids: Vec<String>,
+    delete_files: Option<bool>,
+) -> Result<LibraryRemoval, String> {
+    let delete_files = delete_files.unwrap_or(false);
+    let removal = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let mut targets: Vec<MusicFile> = Vec::new();
+        for id in &song_ids {
+            if targets.iter().any(|s| s.id == *id) {
+                continue;
+            }
+            targets.extend(store::read_song(&tx, id)?);
+        }
+        if targets.is_empty() {
+            return Ok(Err("要移除的歌曲不在曲库中".to_string()));
+        }
+
+        let mut excluded: Vec<String> = Vec::new();
+        if !delete_files {
+            excluded.extend(
+                targets
+                    .iter()
+                    .filter(|s| owns_file(s))
+                    .map(|s| s.path.clone()),
+            );
+        }
+        store::delete_index_entries(&tx, &excluded)?;
+        let removal = remove_songs(&tx, targets)?;
+
+        // 一个 CUE 的分轨都移除后排除整轨文件，否则下次扫描会把分轨全部加回来
+        let mut sources: Vec<String> = Vec::new();
+        for source in removal.songs.iter().filter_map(|s| s.source_path.as_ref()) {
+            if !sources.contains(source) && !store::has_cue_tracks(&tx, source)? {
+                sources.push(source.clone());
+            }
+        }
+        store::delete_index_entries(&tx, &sources)?;
+        excluded.extend(sources);
+
+        let mut settings = store::read_settings(&tx)?;
+        let mut changed = false;
+        for path in &excluded {
+            changed |= exclude_file(&mut settings, Path::new(path));
+        }
+        if changed {
+            store::write_settings(&tx, &settings)?;
+        }
+        tx.commit()?;
+        Ok(Ok(removal))
+    })??;
+
+    let mut result = removal.result;
+    if delete_files {
+        let (trashed, failed) = trash_files(&app_handle, &removal.songs);
+        result.trashed_files = trashed;
+        result.failed = failed;
+    }
+    notify_removal(&app_handle, &removal.playlists, &removal.songs);
+    println!(
+        "[曲库] 移除{}首，移到回收站{}个文件，更新歌单{}个",
+        result.removed.len(),
+        result.trashed_files.len(),
+        result.playlists
+    );
+    Ok(result)
+}
+
+/// 移除某个目录下的所有歌曲，用于曲库根目录被移除之后。文件留在磁盘上；
+/// 仍在其他曲库根目录（如嵌套的子目录）下的歌曲保留
+#[tauri::command]
+#[auto_collect_command]
+pub async fn prune_library_root(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<LibraryRemoval, String> {
+    let root = Path::new(&path);
+    let removal = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let settings = store::read_settings(&tx)?;
+        if settings
+            .library_roots
+            .iter()
+            .any(|r| Path::new(&r.path) == root)
+        {
+            return Ok(Err(format!("{} 仍是曲库目录，请先移除", path)));
+        }
+        let remaining: Vec<&Path> = settings
+            .library_roots
+            .iter()
+            .map(|r| Path::new(&r.path))
+            .collect();
+        let pruned = |file: &str| {
+            let file = Path::new(file);
+            file.starts_with(root) && !remaining.iter().any(|r| file.starts_with(r))
+        };
+
+        let targets: Vec<MusicFile> = store::read_songs(&tx)?
+            .into_iter()
+            .filter(|s| s.is_online != Some(true) && pruned(file_key(s)))
+            .collect();
+        let index = store::read_index(&tx)?;
+        let stale: Vec<String> = index
+            .entries
+            .keys()
+            .chain(index.playlist_files.keys())
+            .filter(|file| pruned(file))
+            .cloned()
+            .collect();
+        store::delete_index_entries(&tx, &stale)?;
+        let removal = remove_songs(&tx, targets)?;
+        tx.commit()?;
+        Ok(Ok(removal))
+    })??;
+
+    notify_removal(&app_handle, &removal.playlists, &removal.songs);
+    let result = removal.result;
+    println!(
+        "[曲库] 清理目录 {}，移除{}首，更新歌单{}个",
+        path,
+        result.removed.len(),
+        result.playlists
+    );
+    Ok(result)
+}
+
+/// 按 ID 修改曲库里的歌曲信息（只改曲库，不写回文件），歌单和播放队列里的副本一并更新。
+/// 路径由整理、重新定位等功能负责，这里保持不变。返回修改后的歌曲
+#[tauri::command]
+#[auto_collect_command]
+pub async fn update_library_entries(
+    app_handle: AppHandle,
+    songs: Vec<MusicFile>,
+) -> Result<Vec<MusicFile>, String> {
+    let (updated, playlists) = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let mut updated = Vec::with_capacity(songs.len());
+        let mut changed: Vec<String> = Vec::new();
+        for mut song in songs {
+            let Some(current) = store::read_song(&tx, &song.id)? else {
+                return Ok(Err(format!("歌曲不在曲库中: {}", song.id)));
+            };
+            song.path = current.path;
+            song.source_path = current.source_path;
+            for id in store::update_song(&tx, &song)? {
+                if !changed.contains(&id) {
+                    changed.push(id);
+                }
+            }
+            updated.push(song);
+        }
+        let playlists = read_changed_playlists(&tx, &changed)?;
+        tx.commit()?;
+        Ok(Ok((updated, playlists)))
+    })??;
+
+    for playlist in &playlists {
+        notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+    }
+    let diff = ScanDiff {
+        updated: updated.clone(),
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    Ok(updated)
+}
+
+/// 在线歌曲下载完成后换成本地文件：曲库、播放队列和歌单里的这首歌在一个事务里一起改，
+/// 沿用原来的 ID，收藏和歌单里的引用不受影响。下载目录在曲库根目录下、文件已被扫描收录时
+/// 改用曲库里的那一条。返回转换后的歌曲
+#[tauri::command]
+#[auto_collect_command]
+pub async fn convert_online_to_local(
+    app_handle: AppHandle,
+    old_path: String,
+    new_path: String,
+    name: String,
+) -> Result<MusicFile, String> {
+    let (song, playlists) = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let song = match store::find_song_by_path(&tx, &new_path)? {
+            Some(scanned) if scanned.is_online != Some(true) => scanned,
+            _ => {
+                let mut song = store::find_song_by_path(&tx, &old_path)?.unwrap_or_default();
+                if song.id.is_empty() {
+                    song.id = new_song_id();
+                }
+                song.path = new_path.clone();
+                song.name = name;
+                // 没有 BV 号的才算本地歌曲，播放时不再走在线流
+                song.is_online = Some(false);
+                song.bv_id = None;
+                song.page = None;
+                store::merge_songs(&tx, std::slice::from_ref(&song))?;
+                song
+            }
+        };
+        tx.execute("DELETE FROM songs WHERE path = ?1", [&old_path])?;
+        let changed = store::replace_song_at(&tx, &old_path, &song)?;
+        let playlists = read_changed_playlists(&tx, &changed)?;
+        tx.commit()?;
+        Ok((song, playlists))
+    })?;
+
+    for playlist in &playlists {
+        notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+    }
+    let diff = ScanDiff {
+        updated: vec![song.clone()],
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    Ok(song)
+}
+
+/// 已经在数据库里生效的移除，事务提交后用来通知前端和处理文件
+struct Removal {
+    result: LibraryRemoval,
+    songs: Vec<MusicFile>,
+    playlists: Vec<Playlist>,
+}
+
+/// 在事务里把歌曲从曲库、歌单和播放队列里删除（指纹一起删除），统计受影响的歌单和队列
+fn remove_songs(conn: &Connection, songs: Vec<MusicFile>) -> rusqlite::Result<Removal> {
+    let ids: Vec<String> = songs.iter().map(|s| s.id.clone()).collect();
+    let (changed, queue) = store::delete_songs(conn, &ids)?;
+    let playlists = read_changed_playlists(conn, &changed)?;
+    Ok(Removal {
+        result: LibraryRemoval {
+            removed: ids,
+            playlists: playlists.len(),
+            queue,
+            ..Default::default()
+        },
+        songs,
+        playlists,
+    })
+}
+
+fn read_changed_playlists(conn: &Connection, ids: &[String]) -> rusqlite::Result<Vec<Playlist>> {
+    let mut playlists = Vec::with_capacity(ids.len());
+    for id in ids {
+        playlists.extend(store::read_playlist(conn, id)?);
+    }
+    Ok(playlists)
+}
+
+/// 把已移出曲库的歌曲文件移到回收站，返回移走的文件和失败的歌曲。移动失败的文件还在根目录下，
+/// 补上排除规则，以免下次扫描又被收录
+pub(crate) fn trash_files(
+    app_handle: &AppHandle,
+    songs: &[MusicFile],
+) -> (Vec<String>, Vec<TagEditFailure>) {
+    let mut trashed = Vec::new();
+    let mut failed = Vec::new();
+    let mut kept = Vec::new();
+    for song in songs.iter().filter(|s| owns_file(s)) {
+        let path = Path::new(&song.path);
+        // 文件已经不在了就只移除记录
+        if !path.exists() {
+            continue;
+        }
+        match trash::delete(path) {
+            Ok(()) => trashed.push(song.path.clone()),
+            Err(e) => {
+                failed.push(TagEditFailure {
+                    id: song.id.clone(),
+                    path: song.path.clone(),
+                    error: format!("移到回收站失败: {}", e),
+                });
+                kept.push(path);
+            }
+        }
+    }
+    if kept.is_empty() {
+        return (trashed, failed);
+    }
+    let saved = with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let mut settings = store::read_settings(&tx)?;
+        for path in &kept {
+            exclude_file(&mut settings, path);
+        }
+        store::write_settings(&tx, &settings)?;
+        tx.commit()
+    });
+    if let Err(e) = saved {
+        println!("[曲库] 保存排除规则失败: {}", e);
+    }
+    (trashed, failed)
+}
+
+fn notify_removal(app_handle: &AppHandle, playlists: &[Playlist], songs: &[MusicFile]) {
+    for playlist in playlists {
+        notify_playlist_change(app_handle, &playlist.id, Some(playlist));
+    }
+    let diff = ScanDiff {
+        removed: songs.iter().map(|s| s.path.clone()).collect(),
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+}
+
+/// 歌曲独占一个本地文件（不是 CUE 分轨，也不是在线歌曲）
+fn owns_file(song: &MusicFile) -> bool {
+    song.is_online != Some(true) && song.source_path.is_none()
+}
@@ -0,0 +1,103 @@
+use crate::db::read_library;
+use crate::models::{LibraryQuery, LibrarySort, MusicFile};
+use std::cmp::Ordering;
+use tauri::AppHandle;
+use tauri_helper::auto_collect_command;
+
+/// 按音频参数筛选、排序曲库。参数未知的歌曲不满足任何数值条件，排序时排在最后
+#[tauri::command]
+#[auto_collect_command]
+pub fn query_library(app_handle: AppHandle, query: LibraryQuery) -> Result<Vec<MusicFile>, String> {
+    let mut songs: Vec<MusicFile> = read_library(&app_handle)?
+        .into_iter()
+        .filter(|song| matches(song, &query))
+        .collect();
+    if let Some(sort_by) = query.sort_by {
+        songs.sort_by(|a, b| {
+            let ordering = compare(a, b, sort_by);
+            // 未知值不跟着反转，始终排在最后
+            match (is_known(a, sort_by), is_known(b, sort_by)) {
+                (true, true) if query.descending => ordering.reverse(),
+                _ => ordering,
+            }
+        });
+    }
+    Ok(songs)
+}
+
+fn matches(song: &MusicFile, query: &LibraryQuery) -> bool {
+    fn one_of(value: &Option<String>, allowed: &[String]) -> bool {
+        allowed.is_empty()
+            || value
+                .as_ref()
+                .is_some_and(|v| allowed.iter().any(|a| a.eq_ignore_ascii_case(v)))
+    }
+    fn at_least(value: Option<u32>, min: Option<u32>) -> bool {
+        min.is_none_or(|min| value.is_some_and(|v| v >= min))
+    }
+    fn at_most(value: Option<u32>, max: Option<u32>) -> bool {
+        max.is_none_or(|max| value.is_some_and(|v| v <= max))
+    }
+
+    one_of(&song.codec, &query.codecs)
+        && one_of(&song.container, &query.containers)
+        && query
+            .bitrate_mode
+            .is_none_or(|mode| song.bitrate_mode == Some(mode))
+        && at_least(song.bitrate, query.min_bitrate)
+        && at_most(song.bitrate, query.max_bitrate)
+        && at_least(song.sample_rate, query.min_sample_rate)
+        && at_most(song.sample_rate, query.max_sample_rate)
+        && at_least(song.bit_depth, query.min_bit_depth)
+        && query.channels.is_none_or(|c| song.channels == Some(c))
+}
+
+fn is_known(song: &MusicFile, sort_by: LibrarySort) -> bool {
+    match sort_by {
+        LibrarySort::Title => song.title.is_some(),
+        LibrarySort::Artist => song.artist.is_some(),
+        LibrarySort::Album => song.album.is_some(),
+        LibrarySort::Duration => song.duration.is_some(),
+        LibrarySort::Codec => song.codec.is_some(),
+        LibrarySort::Bitrate => song.bitrate.is_some(),
+        LibrarySort::SampleRate => song.sample_rate.is_some(),
+        LibrarySort::BitDepth => song.bit_depth.is_some(),
+        LibrarySort::Channels => song.channels.is_some(),
+    }
+}
+
+fn compare(a: &MusicFile, b: &MusicFile, sort_by: LibrarySort) -> Ordering {
+    // Option 的 None 小于 Some，这里反过来让未知值排在后面
+    fn last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    fn text(value: &Option<String>) -> Option<String> {
+        value.as_ref().map(|v| v.to_lowercase())
+    }
+
+    match sort_by {
+        LibrarySort::Title => last(text(&a.title), text(&b.title)),
+        LibrarySort::Artist => last(text(&a.artist), text(&b.artist)),
+        LibrarySort::Album => last(text(&a.album), text(&b.album))
+            .then_with(|| last(a.disc_number, b.disc_number))
+            .then_with(|| last(a.track_number, b.track_number)),
+        LibrarySort::Duration => match (a.duration, b.duration) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (x, y) => last(x.map(|_| ()), y.map(|_| ())),
+        },
+        LibrarySort::Codec => last(text(&a.codec), text(&b.codec)),
+        LibrarySort::Bitrate => last(a.bitrate, b.bitrate),
+        LibrarySort::SampleRate => {
+            last(a.sample_rate, b.sample_rate).then_with(|| last(a.bit_depth, b.bit_depth))
+        }
+        LibrarySort::BitDepth => {
+            last(a.bit_depth, b.bit_depth).then_with(|| last(a.sample_rate, b.sample_rate))
+        }
+        LibrarySort::Channels => last(a.channels, b.channels),
+    }
+}
@@ -0,0 +1,274 @@
+use crate::commands::file_scan::incremental_scan;
+use crate::commands::playlist_import::import_discovered;
+use crate::commands::scan_progress::ScanTracker;
+use crate::db::{
+    load_library_index, read_library, read_settings, save_scan_result, write_settings, AppSettings,
+    LibraryRoot,
+};
+use crate::filename_pattern::{compile_patterns, FilenamePattern};
+use crate::models::{MusicFile, ScanDiff};
+use crate::watcher::{unwatch_root, watch_root};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use jwalk::WalkDir;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri_helper::auto_collect_command;
+
+/// 添加曲库根目录；路径已存在时用新的规则覆盖。返回最新的根目录列表
+#[tauri::command]
+#[auto_collect_command]
+pub fn add_library_root(
+    app_handle: AppHandle,
+    root: LibraryRoot,
+) -> Result<Vec<LibraryRoot>, String> {
+    if !Path::new(&root.path).is_dir() {
+        return Err(format!("目录不存在: {}", root.path));
+    }
+    // 先编译一遍，规则写错时直接报给前端，而不是等到扫描时才发现
+    ScanRules::new(&root, &[])?;
+
+    let mut settings = read_settings(&app_handle)?;
+    match settings
+        .library_roots
+        .iter_mut()
+        .find(|r| same_path(&r.path, &root.path))
+    {
+        Some(existing) => *existing = root.clone(),
+        None => settings.library_roots.push(root.clone()),
+    }
+    write_settings(&app_handle, &settings)?;
+
+    watch_root(&app_handle, &root.path);
+    Ok(settings.library_roots)
+}
+
+/// 移除曲库根目录并停止监听，曲库中已有的歌曲保持不变，需要时再调用 prune_library_root 清理
+#[tauri::command]
+#[auto_collect_command]
+pub fn remove_library_root(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<Vec<LibraryRoot>, String> {
+    let mut settings = read_settings(&app_handle)?;
+    let before = settings.library_roots.len();
+    settings
+        .library_roots
+        .retain(|r| !same_path(&r.path, &path));
+    if settings.library_roots.len() == before {
+        return Err(format!("不是曲库目录: {}", path));
+    }
+    write_settings(&app_handle, &settings)?;
+
+    unwatch_root(&app_handle, &path);
+    Ok(settings.library_roots)
+}
+
+/// 依次增量扫描所有曲库根目录，返回合并后的变化
+#[tauri::command]
+#[auto_collect_command]
+pub async fn rescan_library_roots(
+    app_handle: AppHandle,
+    scan_id: Option<String>,
+) -> Result<ScanDiff, String> {
+    let rules = library_rules(&app_handle)?;
+    let mut index = load_library_index(&app_handle)?;
+    let mut library = read_library(&app_handle)?;
+    let before = index.clone();
+    let mut tracker = ScanTracker::new(app_handle.clone(), scan_id);
+
+    let (mut diff, mut index, library) = tokio::task::spawn_blocking(move || {
+        let mut diff = ScanDiff::default();
+        for rule in rules.iter().filter(|r| r.root.is_dir()) {
+            let part = incremental_scan(rule, &mut index, &mut library, &mut tracker);
+            diff.added.extend(part.added);
+            diff.updated.extend(part.updated);
+            diff.removed.extend(part.removed);
+            diff.playlist_files.extend(part.playlist_files);
+            if part.cancelled {
+                diff.cancelled = true;
+                break;
+            }
+        }
+        tracker.finish();
+        (diff, index, library)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+
+    // 播放列表里的歌曲在扫描结果里查找，保存时只写入变化的部分
+    diff.playlists = import_discovered(&app_handle, &diff.playlist_files, &library, &mut index);
+    save_scan_result(&app_handle, &diff, &before, &index)?;
+
+    println!(
+        "全部目录扫描{}：新增{}首，更新{}首，移除{}首",
+        if diff.cancelled {
+            "已取消"
+        } else {
+            "完成"
+        },
+        diff.added.len(),
+        diff.updated.len(),
+        diff.removed.len()
+    );
+    Ok(diff)
+}
+
+/// 读取设置里所有根目录的扫描规则
+pub(crate) fn library_rules(app_handle: &AppHandle) -> Result<Vec<ScanRules>, String> {
+    let settings = read_settings(app_handle)?;
+    settings
+        .library_roots
+        .iter()
+        .map(|root| ScanRules::new(root, &settings.filename_patterns))
+        .collect()
+}
+
+/// 一次性扫描用的规则：不套用根目录的收录规则，隐藏文件也收录，只带上文件名推断规则。
+/// 收录规则只对曲库根目录的扫描和监听生效
+pub(crate) fn unfiltered_rules(app_handle: &AppHandle, dir: &str) -> Result<ScanRules, String> {
+    let settings = read_settings(app_handle)?;
+    let root = LibraryRoot {
+        include_hidden: true,
+        ..LibraryRoot::new(dir)
+    };
+    ScanRules::new(&root, &settings.filename_patterns)
+}
+
+/// 从规则列表里找出包含该路径的根目录（嵌套时取最深的那个）
+pub(crate) fn rules_for<'a>(rules: &'a [ScanRules], path: &Path) -> Option<&'a ScanRules> {
+    rules
+        .iter()
+        .filter(|r| path.starts_with(&r.root))
+        .max_by_key(|r| r.root.components().count())
+}
+
+/// 把单个文件加进所在根目录的排除规则，移出曲库但保留在磁盘上的文件下次扫描时不会再被收录。
+/// 文件不在任何根目录下时返回 false（这种文件本来就只能手动添加）
+pub(crate) fn exclude_file(settings: &mut AppSettings, path: &Path) -> bool {
+    let Some(root) = settings
+        .library_roots
+        .iter_mut()
+        .filter(|r| path.starts_with(&r.path))
+        .max_by_key(|r| Path::new(&r.path).components().count())
+    else {
+        return false;
+    };
+    let Ok(relative) = path.strip_prefix(&root.path) else {
+        return false;
+    };
+    // 规则统一用 / 分隔，文件名里的 *、? 等需要转义
+    let pattern = relative
+        .components()
+        .map(|c| globset::escape(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/");
+    if !root.exclude.contains(&pattern) {
+        root.exclude.push(pattern);
+    }
+    true
+}
+
+fn same_path(a: &str, b: &str) -> bool {
+    Path::new(a) == Path::new(b)
+}
+
+/// 编译好的根目录扫描规则
+pub(crate) struct ScanRules {
+    pub root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    min_file_size: u64,
+    min_duration: f64,
+    max_depth: Option<usize>,
+    include_hidden: bool,
+    follow_symlinks: bool,
+    /// 标签缺失时用来推断的文件名规则，对所有根目录相同
+    pub filename_patterns: Vec<FilenamePattern>,
+}
+
+impl ScanRules {
+    pub(crate) fn new(root: &LibraryRoot, filename_patterns: &[String]) -> Result<Self, String> {
+        let include = if root.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(&root.include)?)
+        };
+        Ok(Self {
+            root: PathBuf::from(&root.path),
+            include,
+            exclude: build_globset(&root.exclude)?,
+            min_file_size: root.min_file_size.unwrap_or(0),
+            min_duration: root.min_duration.unwrap_or(0.0),
+            max_depth: root.max_depth,
+            include_hidden: root.include_hidden,
+            follow_symlinks: root.follow_symlinks,
+            filename_patterns: compile_patterns(filename_patterns)?,
+        })
+    }
+
+    /// 遍历 dir（根目录本身或其子目录）下的所有文件，深度、隐藏文件和符号链接按规则处理
+    pub(crate) fn walk(&self, dir: &Path) -> impl Iterator<Item = PathBuf> {
+        let mut walker = WalkDir::new(dir)
+            .skip_hidden(!self.include_hidden)
+            .follow_links(self.follow_symlinks);
+        if let Some(max_depth) = self.max_depth {
+            let offset = dir
+                .strip_prefix(&self.root)
+                .map(|rel| rel.components().count())
+                .unwrap_or(0);
+            walker = walker.max_depth(max_depth.saturating_sub(offset));
+        }
+        walker
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+    }
+
+    /// 根据路径和文件大小判断是否收录，不需要读取标签
+    pub(crate) fn accepts_path(&self, path: &Path, size: u64) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if size < self.min_file_size {
+            return false;
+        }
+        if self
+            .max_depth
+            .is_some_and(|max| relative.components().count() > max)
+        {
+            return false;
+        }
+        if !self.include_hidden
+            && relative
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+        if self.exclude.is_match(relative) {
+            return false;
+        }
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(relative))
+    }
+
+    /// 读取标签之后的判断，目前只看时长；时长未知的歌曲照常收录
+    pub(crate) fn accepts_song(&self, song: &MusicFile) -> bool {
+        song.duration.is_none_or(|d| d >= self.min_duration)
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .case_insensitive(cfg!(windows))
+            .build()
+            .map_err(|e| format!("无效的匹配规则 {}: {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
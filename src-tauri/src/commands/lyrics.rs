@@ -0,0 +1,86 @@
+use crate::commands::file_scan::file_key;
+use crate::commands::tags::{read_embedded_lyrics, read_id3_tag};
+use crate::encoding::decode_text;
+use crate::lyrics::{from_synced_entries, parse_lrc, Lyrics};
+use crate::models::MusicFile;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri_helper::auto_collect_command;
+
+/// 查找并解析歌曲的歌词，依次尝试：同目录的 .lrc 文件、内嵌的 SYLT 同步歌词、
+/// 内嵌的普通歌词（内容是 LRC 格式时同样解析出时间轴）。都没有时返回 None
+#[tauri::command]
+#[auto_collect_command]
+pub async fn get_lyrics(song: MusicFile) -> Result<Option<Lyrics>, String> {
+    if song.is_online == Some(true) {
+        return Ok(None);
+    }
+    tokio::task::spawn_blocking(move || find_lyrics(&song))
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+fn find_lyrics(song: &MusicFile) -> Option<Lyrics> {
+    let audio = Path::new(file_key(song));
+    if let Some(lrc) = sidecar_lrc(song, audio) {
+        // 空的或解析不出歌词行的 .lrc 不算，继续找内嵌歌词
+        match fs::read(&lrc) {
+            Ok(bytes) => {
+                let lyrics = parse_lrc(&decode_text(&bytes));
+                if has_lines(&lyrics) {
+                    return Some(lyrics);
+                }
+            }
+            Err(e) => println!("[歌词] 读取失败 {}: {}", lrc.display(), e),
+        }
+    }
+
+    // CUE 分轨共用整轨文件，内嵌歌词对应的是整个文件，不适用于单独一轨
+    if song.source_path.is_some() {
+        return None;
+    }
+    let sylt = read_id3_tag(audio).and_then(|tag| {
+        tag.synchronised_lyrics()
+            .find(|l| l.timestamp_format == id3::frame::TimestampFormat::Ms)
+            .map(|l| from_synced_entries(&l.content))
+    });
+    if let Some(lyrics) = sylt.filter(has_lines) {
+        return Some(lyrics);
+    }
+
+    let mut lyrics = parse_lrc(&read_embedded_lyrics(audio)?);
+    lyrics.source = "embedded".to_string();
+    Some(lyrics).filter(has_lines)
+}
+
+/// 同目录下与音频同名的 .lrc（扩展名不区分大小写）；CUE 分轨和下载的歌曲
+/// 再按 "歌手 - 标题"、"标题" 查找
+pub(crate) fn sidecar_lrc(song: &MusicFile, audio: &Path) -> Option<PathBuf> {
+    let dir = audio.parent()?;
+    let mut stems: Vec<String> = Vec::new();
+    if song.source_path.is_none() {
+        stems.extend(audio.file_stem().map(|s| s.to_string_lossy().to_string()));
+    }
+    stems.push(song.name.clone());
+    stems.extend(song.title.clone());
+
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("lrc"))
+        })
+        .collect();
+    stems.iter().filter(|s| !s.is_empty()).find_map(|stem| {
+        entries
+            .iter()
+            .find(|p| p.file_stem().is_some_and(|s| s.to_string_lossy() == *stem))
+            .cloned()
+    })
+}
+
+fn has_lines(lyrics: &Lyrics) -> bool {
+    lyrics.lines.iter().any(|l| !l.text.is_empty())
+}
@@ -18,7 +18,7 @@ pub struct BilibiliVideoInfo {
 static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
 
 fn get_client() -> &'static Client {
-    HTTP_CLIENT.get_or_init(|| Client::new())
+    HTTP_CLIENT.get_or_init(Client::new)
 }
 
 #[tauri::command]
@@ -120,6 +120,7 @@ pub async fn parse_bilibili_url(url: String) -> Result<MusicFile, String> {
         is_online: Some(true),
         bv_id: Some(bv_id),
         page: Some(page),
+        ..Default::default()
     })
 }
 
@@ -0,0 +1,849 @@
+use crate::commands::cover::is_folder_cover;
+use crate::commands::file_scan::{file_key, is_supported};
+use crate::commands::lyrics::sidecar_lrc;
+use crate::commands::relocate::apply_rewrite;
+use crate::db::{load_library_index, read_library, read_settings, LibraryIndex};
+use crate::models::{
+    FileMove, FileMoveFailure, FileMoveKind, MusicFile, OrganizeJournalInfo, OrganizePlan,
+    OrganizeResult, RelocateResult, ScanDiff,
+};
+use crate::path_template::PathTemplate;
+use crate::recovery::write_atomic;
+use crate::watcher::{unwatch_root, watch_root, LIBRARY_CHANGED_EVENT};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_helper::auto_collect_command;
+
+const ORGANIZE_JOURNAL_DIR: &str = "organize_journals";
+
+/// 一次整理实际完成的移动，撤销时倒序还原
+#[derive(Serialize, Deserialize)]
+struct OrganizeJournal {
+    id: String,
+    created: u64,
+    root: String,
+    template: String,
+    moves: Vec<FileMove>,
+}
+
+/// 预览按模板整理曲库根目录的结果，不改动任何文件。song_ids 为空时整理根目录下的所有歌曲
+#[tauri::command]
+#[auto_collect_command]
+pub async fn preview_organize(
+    app_handle: AppHandle,
+    root: String,
+    template: String,
+    song_ids: Option<Vec<String>>,
+) -> Result<OrganizePlan, String> {
+    check_root(&app_handle, &root)?;
+    let library = read_library(&app_handle)?;
+    let index = load_library_index(&app_handle)?;
+    tokio::task::spawn_blocking(move || plan_organize(&library, &index, &root, &template, song_ids))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// 按模板（如 `{albumartist}/{year} - {album}/{disc}-{track} {title}.{ext}`）移动、重命名
+/// 曲库根目录下的文件，同名的 .lrc、.cue 和目录封面一起移动，曲库、歌单、播放队列里的路径随之更新。
+/// 目标位置已有文件时自动加序号，不会覆盖。每次整理都会记一份日志，可以用 undo_organize 撤销
+#[tauri::command]
+#[auto_collect_command]
+pub async fn organize_files(
+    app_handle: AppHandle,
+    root: String,
+    template: String,
+    song_ids: Option<Vec<String>>,
+) -> Result<OrganizeResult, String> {
+    check_root(&app_handle, &root)?;
+    let library = read_library(&app_handle)?;
+    let index = load_library_index(&app_handle)?;
+    let journal_dir = journal_dir(&app_handle)?;
+
+    let plan = tokio::task::spawn_blocking(move || {
+        plan_organize(&library, &index, &root, &template, song_ids)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
+    if plan.moves.is_empty() {
+        return Ok(OrganizeResult::default());
+    }
+
+    let created = now_millis();
+    let mut journal = OrganizeJournal {
+        id: created.to_string(),
+        created,
+        root: plan.root.clone(),
+        template: plan.template.clone(),
+        moves: plan.moves,
+    };
+    // 先把完整计划写进日志再动文件，中途崩溃时也能按日志撤销
+    write_journal(&journal_dir, &journal)?;
+
+    // 移动期间暂停监听，免得监听把移走的文件当作删除、把新位置当作新歌
+    unwatch_root(&app_handle, &journal.root);
+    let planned = std::mem::take(&mut journal.moves);
+    let (done, failed) = tokio::task::spawn_blocking(move || execute_moves(&planned))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?;
+    journal.moves = done;
+    let outcome =
+        write_journal(&journal_dir, &journal).and_then(|()| finish(&app_handle, &journal, false));
+    watch_root(&app_handle, &journal.root);
+    let relocated = outcome?;
+    println!(
+        "[整理] 按 {} 移动了{}个文件，失败{}个",
+        journal.template,
+        journal.moves.len(),
+        failed.len()
+    );
+    Ok(OrganizeResult {
+        journal_id: journal.id,
+        moved: journal.moves.len(),
+        failed,
+        relocated,
+    })
+}
+
+/// 撤销一次整理，把文件移回原位并还原曲库里的路径；journal_id 为空时撤销最近的一次。
+/// 全部还原成功后删除日志，否则日志里保留没能还原的部分
+#[tauri::command]
+#[auto_collect_command]
+pub async fn undo_organize(
+    app_handle: AppHandle,
+    journal_id: Option<String>,
+) -> Result<OrganizeResult, String> {
+    let journal_dir = journal_dir(&app_handle)?;
+    let id = match journal_id {
+        Some(id) => id,
+        None => read_journals(&journal_dir)
+            .into_iter()
+            .next()
+            .map(|j| j.id)
+            .ok_or_else(|| "没有可以撤销的整理记录".to_string())?,
+    };
+    let mut journal = read_journal(&journal_dir, &id)?;
+
+    unwatch_root(&app_handle, &journal.root);
+    let moves = std::mem::take(&mut journal.moves);
+    let (restored, failed, remaining) = tokio::task::spawn_blocking(move || {
+        let mut restored = Vec::new();
+        let mut failed = Vec::new();
+        let mut remaining = Vec::new();
+        for m in moves.into_iter().rev() {
+            let result = if m.copy {
+                fs::remove_file(&m.to).map_err(|e| e.to_string())
+            } else {
+                transfer(Path::new(&m.to), Path::new(&m.from), false)
+            };
+            match result {
+                Ok(()) => restored.push(m),
+                Err(error) => {
+                    failed.push(FileMoveFailure {
+                        from: m.to.clone(),
+                        to: m.from.clone(),
+                        error,
+                    });
+                    remaining.push(m);
+                }
+            }
+        }
+        remaining.reverse();
+        (restored, failed, remaining)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+
+    journal.moves = restored;
+    let outcome = finish(&app_handle, &journal, true);
+    watch_root(&app_handle, &journal.root);
+    let relocated = outcome?;
+
+    let moved = journal.moves.len();
+    if remaining.is_empty() {
+        let _ = fs::remove_file(journal_path(&journal_dir, &journal.id));
+    } else {
+        journal.moves = remaining;
+        write_journal(&journal_dir, &journal)?;
+    }
+    println!(
+        "[整理] 已撤销 {}：还原{}个文件，失败{}个",
+        id,
+        moved,
+        failed.len()
+    );
+    Ok(OrganizeResult {
+        journal_id: id,
+        moved,
+        failed,
+        relocated,
+    })
+}
+
+/// 列出还可以撤销的整理记录，最近的在前
+#[tauri::command]
+#[auto_collect_command]
+pub fn list_organize_journals(app_handle: AppHandle) -> Result<Vec<OrganizeJournalInfo>, String> {
+    Ok(read_journals(&journal_dir(&app_handle)?)
+        .into_iter()
+        .map(|j| OrganizeJournalInfo {
+            files: j.moves.len(),
+            id: j.id,
+            created: j.created,
+            root: j.root,
+            template: j.template,
+        })
+        .collect())
+}
+
+fn check_root(app_handle: &AppHandle, root: &str) -> Result<(), String> {
+    let is_root = read_settings(app_handle)?
+        .library_roots
+        .iter()
+        .any(|r| Path::new(&r.path) == Path::new(root));
+    if !is_root {
+        return Err(format!("不是曲库目录: {}", root));
+    }
+    Ok(())
+}
+
+fn plan_organize(
+    library: &[MusicFile],
+    index: &LibraryIndex,
+    root: &str,
+    template: &str,
+    song_ids: Option<Vec<String>>,
+) -> Result<OrganizePlan, String> {
+    let template = PathTemplate::parse(template)?;
+    let root_path = Path::new(root);
+    let only: Option<HashSet<String>> = song_ids.map(|ids| ids.into_iter().collect());
+
+    // CUE 分轨共用整轨文件，按文件分组，每个文件只移动一次
+    let mut files: BTreeMap<&str, Vec<&MusicFile>> = BTreeMap::new();
+    let mut selected: HashSet<&str> = HashSet::new();
+    for song in library.iter().filter(|s| s.is_online != Some(true)) {
+        let key = file_key(song);
+        if !Path::new(key).starts_with(root_path) || !Path::new(key).is_file() {
+            continue;
+        }
+        files.entry(key).or_default().push(song);
+        if only.as_ref().is_none_or(|ids| ids.contains(&song.id)) {
+            selected.insert(key);
+        }
+    }
+    // 整轨文件再按 .cue 分组：多 FILE 的 .cue 引用的几个文件要一起移动，.cue 只移动一次
+    let mut sheets: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for (key, songs) in &files {
+        let cue = songs[0]
+            .source_path
+            .as_ref()
+            .and_then(|_| index.entries.get(*key)?.cue_path.as_deref());
+        if let Some(cue) = cue {
+            sheets.entry(PathBuf::from(cue)).or_default().push(key);
+        }
+    }
+
+    let mut plan = OrganizePlan {
+        root: root.to_string(),
+        template: template.source.clone(),
+        ..Default::default()
+    };
+    let mut targets = TargetSet::default();
+    let mut moving: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for (cue, keys) in &sheets {
+        if keys.iter().any(|key| selected.contains(key)) {
+            plan_sheet(
+                cue,
+                keys,
+                &files,
+                &template,
+                root_path,
+                &mut targets,
+                &mut plan,
+                &mut moving,
+            );
+        }
+    }
+
+    let in_sheets: HashSet<&str> = sheets.values().flatten().copied().collect();
+    for (key, songs) in &files {
+        if !selected.contains(key) || in_sheets.contains(key) {
+            continue;
+        }
+        let from = PathBuf::from(key);
+        // 没有 .cue 记录的 CUE 整轨文件也只换目录、不改名
+        let target = if songs[0].source_path.is_some() {
+            let name = from.file_name().unwrap_or_default();
+            root_path.join(template.render_dir(songs[0])).join(name)
+        } else {
+            let ext = from
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or_default();
+            root_path.join(template.render(songs[0], &ext))
+        };
+        let Some((to, renamed)) = targets.claim(&from, target) else {
+            plan.unchanged += songs.len();
+            continue;
+        };
+        let to_dir = to.parent().map(Path::to_path_buf).unwrap_or_default();
+        plan.moves.push(FileMove {
+            from: from.to_string_lossy().to_string(),
+            to: to.to_string_lossy().to_string(),
+            kind: FileMoveKind::Audio,
+            renamed,
+            ..Default::default()
+        });
+        moving.insert(from.clone(), to.clone());
+
+        let mut lyrics: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for song in songs {
+            let Some(lrc) = sidecar_lrc(song, &from) else {
+                continue;
+            };
+            // 与音频同名的歌词跟着改名，按标题命名的（CUE 分轨、下载的歌曲）只换目录
+            let target = if songs[0].source_path.is_none() && lrc.file_stem() == from.file_stem() {
+                to.with_extension(lrc.extension().unwrap_or_default())
+            } else {
+                to_dir.join(lrc.file_name().unwrap_or_default())
+            };
+            if !lyrics.iter().any(|(source, _)| *source == lrc) {
+                lyrics.push((lrc, target));
+            }
+        }
+        for (source, target) in lyrics {
+            push_sidecar(
+                &mut plan,
+                &mut targets,
+                &from,
+                source,
+                target,
+                FileMoveKind::Lyrics,
+            );
+        }
+    }
+
+    plan_covers(&moving, &mut targets, &mut plan.moves);
+    Ok(plan)
+}
+
+/// 把一个 .cue 和它引用的整轨文件一起搬到按第一首分轨生成的目录。
+/// .cue 按文件名引用整轨文件，所以整轨文件只换目录、不改名；.cue 和整轨文件不在同一目录，
+/// 或者新目录里已有同名文件时，移动后 .cue 会找不到文件，这种情况整组保持原样
+#[allow(clippy::too_many_arguments)]
+fn plan_sheet(
+    cue: &Path,
+    keys: &[&str],
+    files: &BTreeMap<&str, Vec<&MusicFile>>,
+    template: &PathTemplate,
+    root: &Path,
+    targets: &mut TargetSet,
+    plan: &mut OrganizePlan,
+    moving: &mut HashMap<PathBuf, PathBuf>,
+) {
+    let songs = keys.iter().map(|key| files[key].len()).sum::<usize>();
+    let (Some(dir), Some(cue_name)) = (cue.parent(), cue.file_name()) else {
+        plan.unchanged += songs;
+        return;
+    };
+    let to_dir = root.join(template.render_dir(files[keys[0]][0]));
+    let mut group: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for key in keys {
+        let from = PathBuf::from(key);
+        match (from.parent(), from.file_name()) {
+            (Some(parent), Some(name)) if parent == dir => {
+                let target = to_dir.join(name);
+                group.push((from, target));
+            }
+            _ => {
+                plan.unchanged += songs;
+                return;
+            }
+        }
+    }
+    let free = group
+        .iter()
+        .chain([(cue.to_path_buf(), to_dir.join(cue_name))].iter())
+        .all(|(from, to)| to == from || targets.is_free(from, to));
+    if to_dir == dir || !free {
+        for (from, to) in &group {
+            targets.claim(from, to.clone());
+        }
+        plan.unchanged += songs;
+        return;
+    }
+
+    // 后面的整轨文件和 .cue 都跟着第一个文件，它没移动成功时整组都不动
+    let first = group[0].0.to_string_lossy().to_string();
+    for (i, (from, to)) in group.iter().enumerate() {
+        targets.claim(from, to.clone());
+        plan.moves.push(FileMove {
+            from: from.to_string_lossy().to_string(),
+            to: to.to_string_lossy().to_string(),
+            kind: FileMoveKind::Audio,
+            audio: (i > 0).then(|| first.clone()),
+            ..Default::default()
+        });
+        moving.insert(from.clone(), to.clone());
+    }
+    push_sidecar(
+        plan,
+        targets,
+        &group[0].0,
+        cue.to_path_buf(),
+        to_dir.join(cue_name),
+        FileMoveKind::Cue,
+    );
+
+    // 分轨的歌词按标题命名，只换目录
+    let mut lyrics: Vec<PathBuf> = Vec::new();
+    for (from, _) in &group {
+        for song in &files[from.to_string_lossy().as_ref()] {
+            if let Some(lrc) = sidecar_lrc(song, from).filter(|lrc| !lyrics.contains(lrc)) {
+                lyrics.push(lrc);
+            }
+        }
+    }
+    for lrc in lyrics {
+        let target = to_dir.join(lrc.file_name().unwrap_or_default());
+        push_sidecar(
+            plan,
+            targets,
+            &group[0].0,
+            lrc,
+            target,
+            FileMoveKind::Lyrics,
+        );
+    }
+}
+
+/// 计划移动跟随 audio 的歌词或 .cue
+fn push_sidecar(
+    plan: &mut OrganizePlan,
+    targets: &mut TargetSet,
+    audio: &Path,
+    source: PathBuf,
+    target: PathBuf,
+    kind: FileMoveKind,
+) {
+    if let Some((to, renamed)) = targets.claim(&source, target) {
+        plan.moves.push(FileMove {
+            from: source.to_string_lossy().to_string(),
+            to: to.to_string_lossy().to_string(),
+            kind,
+            audio: Some(audio.to_string_lossy().to_string()),
+            renamed,
+            copy: false,
+        });
+    }
+}
+
+/// 目录里的歌曲都搬到同一个新目录时封面跟着移动；被分到多个目录，
+/// 或者目录里还有不移动的歌曲时，复制到每个新目录。新目录已有同名封面时不动
+fn plan_covers(
+    moving: &HashMap<PathBuf, PathBuf>,
+    targets: &mut TargetSet,
+    moves: &mut Vec<FileMove>,
+) {
+    let mut dirs: BTreeMap<&Path, HashSet<&Path>> = BTreeMap::new();
+    for (from, to) in moving {
+        if let (Some(from_dir), Some(to_dir)) = (from.parent(), to.parent()) {
+            dirs.entry(from_dir).or_default().insert(to_dir);
+        }
+    }
+
+    for (dir, to_dirs) in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let files: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .collect();
+        let stays = files
+            .iter()
+            .any(|p| is_supported(p) && !moving.contains_key(p));
+        let to_dirs: Vec<&Path> = to_dirs.into_iter().filter(|d| *d != dir).collect();
+        let copy = stays || to_dirs.len() > 1;
+
+        for cover in files.iter().filter(|p| is_folder_cover(p)) {
+            for to_dir in &to_dirs {
+                let target = to_dir.join(cover.file_name().unwrap_or_default());
+                if !targets.is_free(cover, &target) {
+                    continue;
+                }
+                targets.claim(cover, target.clone());
+                moves.push(FileMove {
+                    from: cover.to_string_lossy().to_string(),
+                    to: target.to_string_lossy().to_string(),
+                    kind: FileMoveKind::Cover,
+                    copy,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+/// 本次整理已经占用的目标路径，按小写比较，兼容不区分大小写的文件系统
+#[derive(Default)]
+struct TargetSet {
+    claimed: HashSet<String>,
+}
+
+impl TargetSet {
+    /// 为 from 占用 target，被占用或已有文件时依次尝试 "名字 (2)"、"名字 (3)"……
+    /// 目标就是 from 本身时返回 None，表示不需要移动
+    fn claim(&mut self, from: &Path, target: PathBuf) -> Option<(PathBuf, bool)> {
+        if target == from {
+            self.claimed.insert(lowercase(&target));
+            return None;
+        }
+        let mut candidate = target.clone();
+        let mut n = 1;
+        while !self.is_free(from, &candidate) {
+            n += 1;
+            candidate = numbered(&target, n);
+        }
+        self.claimed.insert(lowercase(&candidate));
+        Some((candidate, n > 1))
+    }
+
+    /// target 没有被占用，也没有别的文件。只改大小写时，不区分大小写的文件系统上
+    /// target "已存在"，但那就是 from 自己
+    fn is_free(&self, from: &Path, target: &Path) -> bool {
+        !self.claimed.contains(&lowercase(target)) && (!target.exists() || same_file(from, target))
+    }
+}
+
+/// 两个路径是否指向同一个文件（不区分大小写的文件系统上只有大小写不同的路径）
+fn same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+fn lowercase(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// 依次执行移动，返回成功的和失败的；音频没移动成功时它的歌词和 .cue 也不动。
+/// 移动按计划顺序执行，多 FILE 的 .cue 排在它引用的所有整轨文件之后
+fn execute_moves(moves: &[FileMove]) -> (Vec<FileMove>, Vec<FileMoveFailure>) {
+    let mut done = Vec::new();
+    let mut failed = Vec::new();
+    let mut failed_audio: HashSet<&str> = HashSet::new();
+    for m in moves {
+        if m.audio.as_deref().is_some_and(|a| failed_audio.contains(a)) {
+            continue;
+        }
+        match transfer(Path::new(&m.from), Path::new(&m.to), m.copy) {
+            Ok(()) => done.push(m.clone()),
+            Err(error) => {
+                if m.kind == FileMoveKind::Audio {
+                    failed_audio.insert(&m.from);
+                    // 同一个 .cue 引用的其他整轨文件没移动成功时，.cue 和歌词也不动
+                    if let Some(first) = m.audio.as_deref() {
+                        failed_audio.insert(first);
+                    }
+                }
+                failed.push(FileMoveFailure {
+                    from: m.from.clone(),
+                    to: m.to.clone(),
+                    error,
+                });
+            }
+        }
+    }
+    (done, failed)
+}
+
+/// 移动（或复制）单个文件，从不覆盖已有文件；跨磁盘时 rename 会失败，改为复制后删除
+fn transfer(from: &Path, to: &Path, copy: bool) -> Result<(), String> {
+    if to.exists() && !same_file(from, to) {
+        return Err("目标位置已有文件".to_string());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    if copy {
+        return fs::copy(from, to).map(|_| ()).map_err(|e| e.to_string());
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).map_err(|e| e.to_string())?;
+    fs::remove_file(from).map_err(|e| {
+        let _ = fs::remove_file(to);
+        e.to_string()
+    })
+}
+
+/// 按已完成的移动更新曲库等处的路径（undo 时反过来），清理搬空的目录并通知前端
+fn finish(
+    app_handle: &AppHandle,
+    journal: &OrganizeJournal,
+    undo: bool,
+) -> Result<RelocateResult, String> {
+    let paths: HashMap<&str, &str> = journal
+        .moves
+        .iter()
+        .filter(|m| matches!(m.kind, FileMoveKind::Audio | FileMoveKind::Cue))
+        .map(|m| {
+            if undo {
+                (m.to.as_str(), m.from.as_str())
+            } else {
+                (m.from.as_str(), m.to.as_str())
+            }
+        })
+        .collect();
+    let relocated = apply_rewrite(app_handle, |path| paths.get(path).map(|p| p.to_string()))?;
+
+    let root = Path::new(&journal.root);
+    for m in journal.moves.iter().filter(|m| !m.copy) {
+        let emptied = if undo { &m.to } else { &m.from };
+        remove_empty_dirs(root, Path::new(emptied));
+    }
+
+    let moved: HashSet<&str> = paths.values().copied().collect();
+    let diff = ScanDiff {
+        updated: read_library(app_handle)?
+            .into_iter()
+            .filter(|s| moved.contains(file_key(s)))
+            .collect(),
+        ..Default::default()
+    };
+    if !diff.updated.is_empty() {
+        let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    }
+    Ok(relocated)
+}
+
+/// 从文件所在目录往上删除空目录，到根目录为止
+fn remove_empty_dirs(root: &Path, file: &Path) {
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+fn journal_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(ORGANIZE_JOURNAL_DIR))
+}
+
+fn journal_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+fn write_journal(dir: &Path, journal: &OrganizeJournal) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("创建日志目录失败: {}", e))?;
+    let json = serde_json::to_string_pretty(journal).map_err(|e| e.to_string())?;
+    write_atomic(&journal_path(dir, &journal.id), json.as_bytes())
+        .map_err(|e| format!("写入整理日志失败: {}", e))
+}
+
+fn read_journal(dir: &Path, id: &str) -> Result<OrganizeJournal, String> {
+    let content =
+        fs::read_to_string(journal_path(dir, id)).map_err(|_| format!("找不到整理记录: {}", id))?;
+    serde_json::from_str(&content).map_err(|e| format!("整理记录已损坏 {}: {}", id, e))
+}
+
+fn read_journals(dir: &Path) -> Vec<OrganizeJournal> {
+    let mut journals: Vec<OrganizeJournal> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|content| serde_json::from_str(&content).ok())
+        .collect();
+    journals.sort_by_key(|j| std::cmp::Reverse(j.created));
+    journals
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::IndexEntry;
+
+    /// 测试用的临时目录，里面按 (文件名, 内容) 建好文件
+    fn temp_dir(files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sonic-organize-{}", crate::song_id::new_song_id()));
+        for (name, content) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn numbers_taken_targets() {
+        let dir = temp_dir(&[
+            ("a.mp3", "a"),
+            ("b.mp3", "b"),
+            ("c.mp3", "c"),
+            ("x.mp3", "x"),
+        ]);
+        let mut targets = TargetSet::default();
+        let cases = [
+            ("a.mp3", "a.mp3", None),
+            ("b.mp3", "y.mp3", Some(("y.mp3", false))),
+            // 已被本次整理占用
+            ("c.mp3", "Y.mp3", Some(("Y (2).mp3", true))),
+            // 已有别的文件
+            ("a.mp3", "x.mp3", Some(("x (2).mp3", true))),
+        ];
+        for (from, target, expected) in cases {
+            let claimed = targets.claim(&dir.join(from), dir.join(target));
+            let expected = expected.map(|(name, renamed)| (dir.join(name), renamed));
+            assert_eq!(claimed, expected, "{} -> {}", from, target);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn case_only_rename_never_overwrites_another_file() {
+        let dir = temp_dir(&[("song.mp3", "lower"), ("Song.mp3", "upper")]);
+        let (from, target) = (dir.join("song.mp3"), dir.join("Song.mp3"));
+        let claimed = TargetSet::default().claim(&from, target.clone());
+        if same_file(&from, &target) {
+            // 不区分大小写的文件系统上两个名字是同一个文件，直接改名
+            assert_eq!(claimed, Some((target.clone(), false)));
+            transfer(&from, &target, false).unwrap();
+        } else {
+            assert_eq!(claimed, Some((dir.join("Song (2).mp3"), true)));
+            assert_eq!(
+                transfer(&from, &target, false),
+                Err("目标位置已有文件".to_string())
+            );
+            assert_eq!(fs::read_to_string(&from).unwrap(), "lower");
+            assert_eq!(fs::read_to_string(&target).unwrap(), "upper");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 多 FILE 的 .cue：引用的整轨文件搬到同一个目录，.cue 只移动一次
+    #[test]
+    fn moves_multi_file_cue_as_one_group() {
+        let dir = temp_dir(&[("in/x.cue", ""), ("in/a.wav", "a"), ("in/b.wav", "b")]);
+        let root = dir.to_string_lossy().to_string();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let track = |id: &str, file: &str, title: &str| MusicFile {
+            id: id.to_string(),
+            path: format!("{}#{}", path(file), id),
+            source_path: Some(path(file)),
+            title: Some(title.to_string()),
+            artist: Some("歌手".to_string()),
+            album: Some("专辑".to_string()),
+            ..Default::default()
+        };
+        let library = [
+            track("1", "in/a.wav", "一"),
+            track("2", "in/a.wav", "二"),
+            track("3", "in/b.wav", "三"),
+        ];
+        let mut index = LibraryIndex::default();
+        for file in ["in/a.wav", "in/b.wav"] {
+            let entry = IndexEntry {
+                size: 1,
+                mtime: 0,
+                tag_hash: String::new(),
+                cue_path: Some(path("in/x.cue")),
+                cue_mtime: Some(0),
+                fingerprint: None,
+                reader_version: 0,
+            };
+            index.entries.insert(path(file), entry);
+        }
+
+        type Move = (String, String, FileMoveKind, Option<String>);
+        let expected: Vec<Move> = vec![
+            (
+                path("in/a.wav"),
+                path("歌手/专辑/a.wav"),
+                FileMoveKind::Audio,
+                None,
+            ),
+            (
+                path("in/b.wav"),
+                path("歌手/专辑/b.wav"),
+                FileMoveKind::Audio,
+                Some(path("in/a.wav")),
+            ),
+            (
+                path("in/x.cue"),
+                path("歌手/专辑/x.cue"),
+                FileMoveKind::Cue,
+                Some(path("in/a.wav")),
+            ),
+        ];
+        // 只整理其中一首时整张 .cue 也一起移动
+        for only in [None, Some(vec!["3".to_string()])] {
+            let plan =
+                plan_organize(&library, &index, &root, "{artist}/{album}/{title}", only).unwrap();
+            let moves: Vec<Move> = plan
+                .moves
+                .into_iter()
+                .map(|m| (m.from, m.to, m.kind, m.audio))
+                .collect();
+            assert_eq!(moves, expected);
+        }
+
+        // 引用的文件不在 .cue 所在目录时整组不动
+        fs::create_dir_all(dir.join("other")).unwrap();
+        fs::rename(dir.join("in/b.wav"), dir.join("other/b.wav")).unwrap();
+        let mut library = library.to_vec();
+        library[2] = track("3", "other/b.wav", "三");
+        let entry = index.entries.remove(&path("in/b.wav")).unwrap();
+        index.entries.insert(path("other/b.wav"), entry);
+        let plan =
+            plan_organize(&library, &index, &root, "{artist}/{album}/{title}", None).unwrap();
+        assert!(plan.moves.is_empty());
+        assert_eq!(plan.unchanged, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+use crate::commands::file_scan::file_key;
+use crate::commands::playlists::notify_playlist_change;
+use crate::db::{read_library, read_playlists, LibraryIndex};
+use crate::models::{MusicFile, Playlist, PlaylistImport, UnresolvedEntry};
+use crate::playlist_file::{is_playlist_file, read_playlist_file, resolve_location};
+use crate::store::{self, with_db};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tauri::AppHandle;
+use tauri_helper::auto_collect_command;
+
+/// 手动导入一个 .m3u / .m3u8 / .pls 文件；导入过的文件会用最新内容覆盖对应歌单
+#[tauri::command]
+#[auto_collect_command]
+pub fn import_playlist_file(app_handle: AppHandle, path: String) -> Result<PlaylistImport, String> {
+    let path = PathBuf::from(path);
+    if !is_playlist_file(&path) {
+        return Err(format!("不是播放列表文件: {}", path.display()));
+    }
+
+    let library = read_library(&app_handle)?;
+    let (playlist, result) = parse_import(&path, &SongLookup::new(&library))?;
+    let mtime = modified_millis(&path);
+    with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        save_playlist(&tx, &playlist)?;
+        if let Some(mtime) = mtime {
+            store::record_playlist_file(&tx, &result.path, mtime)?;
+        }
+        tx.commit()
+    })?;
+    notify_playlist_change(&app_handle, &playlist.id, Some(&playlist));
+    Ok(result)
+}
+
+/// 导入扫描中发现的播放列表文件。文件自上次导入后没有变化、对应歌单也还在时跳过，
+/// 避免覆盖用户在应用里对歌单做的修改。只写入导入的歌单，调用方负责保存 index
+pub(crate) fn import_discovered(
+    app_handle: &AppHandle,
+    files: &[PathBuf],
+    library: &[MusicFile],
+    index: &mut LibraryIndex,
+) -> Vec<PlaylistImport> {
+    if files.is_empty() {
+        return vec![];
+    }
+    let existing: HashSet<String> = match read_playlists(app_handle) {
+        Ok(playlists) => playlists.into_iter().map(|p| p.id).collect(),
+        Err(e) => {
+            println!("[歌单] 读取歌单失败: {}", e);
+            return vec![];
+        }
+    };
+    let lookup = SongLookup::new(library);
+    let mut parsed = Vec::new();
+
+    for path in files {
+        let key = path.to_string_lossy().to_string();
+        let mtime = modified_millis(path);
+        let exists = existing.contains(&playlist_id(path));
+        if exists && mtime.is_some() && index.playlist_files.get(&key) == mtime.as_ref() {
+            continue;
+        }
+        match parse_import(path, &lookup) {
+            Ok((playlist, result)) => parsed.push((playlist, result, mtime)),
+            Err(e) => println!("[歌单] 导入失败 {}: {}", path.display(), e),
+        }
+    }
+    if parsed.is_empty() {
+        return vec![];
+    }
+
+    let saved = with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        for (playlist, _, _) in &parsed {
+            save_playlist(&tx, playlist)?;
+        }
+        tx.commit()
+    });
+    if let Err(e) = saved {
+        println!("[歌单] 保存失败: {}", e);
+        return vec![];
+    }
+
+    let mut imported = Vec::with_capacity(parsed.len());
+    for (playlist, result, mtime) in parsed {
+        if let Some(mtime) = mtime {
+            index.playlist_files.insert(result.path.clone(), mtime);
+        }
+        notify_playlist_change(app_handle, &playlist.id, Some(&playlist));
+        imported.push(result);
+    }
+    imported
+}
+
+/// 写入导入的歌单：已有时替换名称和歌曲，保持原来的位置；没有时新建，排在最后
+fn save_playlist(conn: &Connection, playlist: &Playlist) -> rusqlite::Result<()> {
+    if store::read_playlist(conn, &playlist.id)?.is_some() {
+        store::rename_playlist(conn, &playlist.id, &playlist.name)?;
+        store::write_playlist_entries(conn, &playlist.id, &playlist.songs)
+    } else {
+        store::insert_playlist(conn, playlist)
+    }
+}
+
+/// 解析播放列表文件，得到对应的歌单和导入结果
+fn parse_import(path: &Path, lookup: &SongLookup) -> Result<(Playlist, PlaylistImport), String> {
+    let file = read_playlist_file(path).map_err(|e| e.to_string())?;
+    let name = file.title.clone().unwrap_or_else(|| {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+
+    let mut songs = Vec::new();
+    let mut unresolved = Vec::new();
+    for entry in &file.entries {
+        let found = resolve_location(path, &entry.location)
+            .map(|location| lookup.find(&location))
+            .unwrap_or_default();
+        if found.is_empty() {
+            unresolved.push(UnresolvedEntry {
+                line: entry.line,
+                location: entry.location.clone(),
+            });
+        }
+        songs.extend(found.into_iter().cloned());
+    }
+
+    let id = playlist_id(path);
+    let playlist = Playlist {
+        id: id.clone(),
+        name: name.clone(),
+        songs,
+        is_system: None,
+    };
+    let resolved = file.entries.len() - unresolved.len();
+
+    println!(
+        "[歌单] 已导入 {}：{}条对应到曲库，{}条未找到",
+        path.display(),
+        resolved,
+        unresolved.len()
+    );
+    Ok((
+        playlist,
+        PlaylistImport {
+            playlist_id: id,
+            name,
+            path: path.to_string_lossy().to_string(),
+            resolved,
+            unresolved,
+        },
+    ))
+}
+
+/// 同一个文件导入多次得到同一个歌单
+fn playlist_id(path: &Path) -> String {
+    let hash = blake3::hash(path.to_string_lossy().as_bytes());
+    format!("import-{}", &hash.to_hex()[..16])
+}
+
+fn modified_millis(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_millis() as u64)
+}
+
+/// 按文件路径查找曲库歌曲。别的播放器（尤其是 Windows 上）导出的路径大小写
+/// 常常与实际不一致，精确匹配不到时再忽略大小写匹配一次
+struct SongLookup<'a> {
+    exact: HashMap<&'a str, Vec<&'a MusicFile>>,
+    folded: HashMap<String, Vec<&'a MusicFile>>,
+}
+
+impl<'a> SongLookup<'a> {
+    fn new(library: &'a [MusicFile]) -> Self {
+        let mut exact: HashMap<&str, Vec<&MusicFile>> = HashMap::new();
+        let mut folded: HashMap<String, Vec<&MusicFile>> = HashMap::new();
+        for song in library.iter().filter(|s| s.is_online != Some(true)) {
+            // CUE 分轨以整轨文件为键，条目指向整轨文件时导入全部分轨
+            let key = file_key(song);
+            exact.entry(key).or_default().push(song);
+            folded.entry(key.to_lowercase()).or_default().push(song);
+        }
+        Self { exact, folded }
+    }
+
+    fn find(&self, location: &Path) -> Vec<&'a MusicFile> {
+        let location = location.to_string_lossy();
+        self.exact
+            .get(location.as_ref())
+            .or_else(|| self.folded.get(&location.to_lowercase()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
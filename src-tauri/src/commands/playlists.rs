@@ -0,0 +1,435 @@
+use crate::db::LOCAL_PLAYLIST_ID;
+use crate::models::{MusicFile, Playlist, PlaylistChange};
+use crate::song_id::ensure_song_ids;
+use crate::store::{self, with_db};
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashSet};
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 歌单被创建、修改或删除时发出，负载为 PlaylistChange
+pub const PLAYLISTS_CHANGED_EVENT: &str = "playlists-changed";
+
+const MAX_NAME_CHARS: usize = 100;
+
+#[tauri::command]
+#[auto_collect_command]
+pub fn create_playlist(app_handle: AppHandle, name: String) -> Result<Playlist, String> {
+    let playlist = Playlist {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        name: validate_name(&name)?,
+        songs: vec![],
+        is_system: None,
+    };
+    with_db(&app_handle, |conn| store::insert_playlist(conn, &playlist))?;
+    notify_playlist_change(&app_handle, &playlist.id, Some(&playlist));
+    Ok(playlist)
+}
+
+/// 系统歌单不能改名
+#[tauri::command]
+#[auto_collect_command]
+pub fn rename_playlist(
+    app_handle: AppHandle,
+    playlist_id: String,
+    name: String,
+) -> Result<Playlist, String> {
+    let name = validate_name(&name)?;
+    update(&app_handle, &playlist_id, |conn, playlist| {
+        rename(conn, playlist, name)
+    })
+}
+
+/// 系统歌单（本地音乐、我喜欢的音乐）不能删除
+#[tauri::command]
+#[auto_collect_command]
+pub fn delete_playlist(app_handle: AppHandle, playlist_id: String) -> Result<(), String> {
+    with_db(&app_handle, |conn| delete(conn, &playlist_id))??;
+    notify_playlist_change(&app_handle, &playlist_id, None);
+    Ok(())
+}
+
+/// 复制歌单，新歌单排在最后。name 为空时用「原名 副本」
+#[tauri::command]
+#[auto_collect_command]
+pub fn duplicate_playlist(
+    app_handle: AppHandle,
+    playlist_id: String,
+    name: Option<String>,
+) -> Result<Playlist, String> {
+    let copy = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let Some(source) = store::read_playlist(&tx, &playlist_id)? else {
+            return Ok(Err(not_found(&playlist_id)));
+        };
+        if source.id == LOCAL_PLAYLIST_ID {
+            return Ok(Err("本地音乐不能复制".to_string()));
+        }
+        let name = match name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            Some(name) => name.to_string(),
+            None => format!("{} 副本", source.name),
+        };
+        let name = match validate_name(&name) {
+            Ok(name) => name,
+            Err(e) => return Ok(Err(e)),
+        };
+        let copy = Playlist {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            name,
+            songs: source.songs,
+            is_system: None,
+        };
+        store::insert_playlist(&tx, &copy)?;
+        tx.commit()?;
+        Ok(Ok(copy))
+    })??;
+    notify_playlist_change(&app_handle, &copy.id, Some(&copy));
+    Ok(copy)
+}
+
+/// 把歌曲加入歌单，插在 index 处，不传时加在末尾。歌单里已有的歌曲（ID 或路径相同）跳过，
+/// 曲库里有的歌曲按曲库里的最新版本保存
+#[tauri::command]
+#[auto_collect_command]
+pub fn add_to_playlist(
+    app_handle: AppHandle,
+    playlist_id: String,
+    songs: Vec<MusicFile>,
+    index: Option<usize>,
+) -> Result<Playlist, String> {
+    update(&app_handle, &playlist_id, |conn, playlist| {
+        add_songs(conn, playlist, songs, index)
+    })
+}
+
+/// 从歌单移除歌曲，同一首歌出现多次时全部移除
+#[tauri::command]
+#[auto_collect_command]
+pub fn remove_from_playlist(
+    app_handle: AppHandle,
+    playlist_id: String,
+    song_ids: Vec<String>,
+) -> Result<Playlist, String> {
+    let song_ids: HashSet<String> = song_ids.into_iter().collect();
+    update(&app_handle, &playlist_id, |_, playlist| {
+        editable(playlist)?;
+        playlist.songs.retain(|song| !song_ids.contains(&song.id));
+        Ok(())
+    })
+}
+
+/// 把 indices 处的歌曲按原来的先后顺序移到 to 处。
+/// to 是移动之后的位置，如把第 0 首移到最后一首时 to 为歌单长度减一
+#[tauri::command]
+#[auto_collect_command]
+pub fn move_in_playlist(
+    app_handle: AppHandle,
+    playlist_id: String,
+    indices: Vec<usize>,
+    to: usize,
+) -> Result<Playlist, String> {
+    let indices: BTreeSet<usize> = indices.into_iter().collect();
+    update(&app_handle, &playlist_id, |_, playlist| {
+        move_songs(playlist, &indices, to)
+    })
+}
+
+/// 在一个事务里读取歌单、修改并保存，修改失败时什么都不写
+fn update(
+    app_handle: &AppHandle,
+    playlist_id: &str,
+    edit: impl FnOnce(&Connection, &mut Playlist) -> Result<(), String>,
+) -> Result<Playlist, String> {
+    let playlist = with_db(app_handle, |conn| apply_edit(conn, playlist_id, edit))??;
+    notify_playlist_change(app_handle, &playlist.id, Some(&playlist));
+    Ok(playlist)
+}
+
+/// update 的数据库部分。保存时把歌单里的歌曲换成曲库里的最新版本
+fn apply_edit(
+    conn: &mut Connection,
+    playlist_id: &str,
+    edit: impl FnOnce(&Connection, &mut Playlist) -> Result<(), String>,
+) -> rusqlite::Result<Result<Playlist, String>> {
+    let tx = conn.transaction()?;
+    let Some(mut playlist) = store::read_playlist(&tx, playlist_id)? else {
+        return Ok(Err(not_found(playlist_id)));
+    };
+    if let Err(e) = edit(&tx, &mut playlist) {
+        return Ok(Err(e));
+    }
+    store::refresh_songs(&tx, &mut playlist.songs)?;
+    store::write_playlist_entries(&tx, &playlist.id, &playlist.songs)?;
+    tx.commit()?;
+    Ok(Ok(playlist))
+}
+
+fn rename(conn: &Connection, playlist: &mut Playlist, name: String) -> Result<(), String> {
+    if playlist.is_system == Some(true) {
+        return Err("系统歌单不能改名".to_string());
+    }
+    store::rename_playlist(conn, &playlist.id, &name).map_err(|e| e.to_string())?;
+    playlist.name = name;
+    Ok(())
+}
+
+fn delete(conn: &mut Connection, playlist_id: &str) -> rusqlite::Result<Result<(), String>> {
+    let tx = conn.transaction()?;
+    let Some(playlist) = store::read_playlist(&tx, playlist_id)? else {
+        return Ok(Err(not_found(playlist_id)));
+    };
+    if playlist.is_system == Some(true) {
+        return Ok(Err(format!("系统歌单「{}」不能删除", playlist.name)));
+    }
+    store::delete_playlist(&tx, playlist_id)?;
+    tx.commit()?;
+    Ok(Ok(()))
+}
+
+fn add_songs(
+    conn: &Connection,
+    playlist: &mut Playlist,
+    mut songs: Vec<MusicFile>,
+    index: Option<usize>,
+) -> Result<(), String> {
+    editable(playlist)?;
+    let index = index.unwrap_or(playlist.songs.len());
+    if index > playlist.songs.len() {
+        return Err(format!(
+            "插入位置 {} 超出歌单长度 {}",
+            index,
+            playlist.songs.len()
+        ));
+    }
+
+    store::refresh_songs(conn, &mut songs).map_err(|e| e.to_string())?;
+    // 曲库里没有的歌曲每次加入都会分到新 ID，所以同时按路径判断是否已在歌单里
+    let mut ids: HashSet<String> = playlist.songs.iter().map(|s| s.id.clone()).collect();
+    let mut paths: HashSet<String> = playlist.songs.iter().map(|s| s.path.clone()).collect();
+    songs.retain(|song| {
+        if !song.id.is_empty() && !ids.insert(song.id.clone()) {
+            return false;
+        }
+        paths.insert(song.path.clone())
+    });
+    ensure_song_ids(&mut songs, &[]);
+    playlist.songs.splice(index..index, songs);
+    Ok(())
+}
+
+fn move_songs(playlist: &mut Playlist, indices: &BTreeSet<usize>, to: usize) -> Result<(), String> {
+    editable(playlist)?;
+    let len = playlist.songs.len();
+    if let Some(&last) = indices.last().filter(|&&i| i >= len) {
+        return Err(format!("位置 {} 超出歌单长度 {}", last, len));
+    }
+    if to > len - indices.len() {
+        return Err(format!("目标位置 {} 超出歌单长度", to));
+    }
+
+    let (moved, mut rest): (Vec<_>, Vec<_>) = std::mem::take(&mut playlist.songs)
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| indices.contains(i));
+    rest.splice(to..to, moved);
+    playlist.songs = rest.into_iter().map(|(_, song)| song).collect();
+    Ok(())
+}
+
+/// 本地音乐展示的是曲库，不能往里加歌或调整顺序；我喜欢的音乐可以
+fn editable(playlist: &Playlist) -> Result<(), String> {
+    if playlist.id == LOCAL_PLAYLIST_ID {
+        return Err("本地音乐的内容由曲库决定，不能直接修改".to_string());
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("歌单名不能为空".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("歌单名不能超过{}个字", MAX_NAME_CHARS));
+    }
+    Ok(name.to_string())
+}
+
+fn not_found(playlist_id: &str) -> String {
+    format!("歌单不存在: {}", playlist_id)
+}
+
+pub(crate) fn notify_playlist_change(
+    app_handle: &AppHandle,
+    playlist_id: &str,
+    playlist: Option<&Playlist>,
+) {
+    let change = PlaylistChange {
+        playlist_id: playlist_id.to_string(),
+        playlist: playlist.cloned(),
+    };
+    let _ = app_handle.emit(PLAYLISTS_CHANGED_EVENT, &change);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::FAVORITES_PLAYLIST_ID;
+    use crate::migrations::{migrate, MigrationContext};
+    use std::path::Path;
+
+    /// 迁移到最新版本的内存数据库，带系统歌单，外加一个有 len 首歌的普通歌单 "p"
+    fn setup(len: usize) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("sonic-playlists-{}", crate::song_id::new_song_id()));
+        let mut ctx = MigrationContext {
+            app_dir: Path::new(&dir),
+            recoveries: Vec::new(),
+        };
+        migrate(&mut conn, &mut ctx).unwrap();
+        let playlist = Playlist {
+            id: "p".to_string(),
+            name: "歌单".to_string(),
+            songs: (0..len).map(song).collect(),
+            is_system: None,
+        };
+        store::insert_playlist(&conn, &playlist).unwrap();
+        conn
+    }
+
+    fn song(i: usize) -> MusicFile {
+        MusicFile {
+            id: format!("s{}", i),
+            path: format!("/music/{}.mp3", i),
+            name: i.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 歌单里歌曲的编号，按顺序
+    fn order(conn: &Connection, playlist_id: &str) -> Vec<String> {
+        store::read_playlist(conn, playlist_id)
+            .unwrap()
+            .unwrap()
+            .songs
+            .iter()
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn moves_songs_to_position_after_removal() {
+        let cases: &[(&[usize], usize, &[&str])] = &[
+            (&[0], 4, &["1", "2", "3", "4", "0"]),
+            (&[4], 0, &["4", "0", "1", "2", "3"]),
+            (&[2], 2, &["0", "1", "2", "3", "4"]),
+            (&[1, 3], 2, &["0", "2", "1", "3", "4"]),
+            // 原来的先后顺序保留，与传入顺序无关
+            (&[3, 1], 0, &["1", "3", "0", "2", "4"]),
+            // to 最大为 len - indices.len()，即移到末尾
+            (&[0, 1], 3, &["2", "3", "4", "0", "1"]),
+            (&[0, 1, 2, 3, 4], 0, &["0", "1", "2", "3", "4"]),
+        ];
+        for &(indices, to, expected) in cases {
+            let mut conn = setup(5);
+            let indices: BTreeSet<usize> = indices.iter().copied().collect();
+            apply_edit(&mut conn, "p", |_, p| move_songs(p, &indices, to))
+                .unwrap()
+                .unwrap();
+            assert_eq!(order(&conn, "p"), expected, "{:?} -> {}", indices, to);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_moves() {
+        let cases: &[(&[usize], usize)] = &[(&[0, 1], 4), (&[0], 5), (&[5], 0), (&[1, 7], 0)];
+        for &(indices, to) in cases {
+            let mut conn = setup(5);
+            let indices: BTreeSet<usize> = indices.iter().copied().collect();
+            let result = apply_edit(&mut conn, "p", |_, p| move_songs(p, &indices, to)).unwrap();
+            assert!(result.is_err(), "{:?} -> {}", indices, to);
+            assert_eq!(order(&conn, "p"), ["0", "1", "2", "3", "4"]);
+        }
+    }
+
+    #[test]
+    fn inserts_songs_within_bounds() {
+        let cases: &[(Option<usize>, Option<&[&str]>)] = &[
+            (None, Some(&["0", "1", "2", "9"])),
+            (Some(0), Some(&["9", "0", "1", "2"])),
+            (Some(1), Some(&["0", "9", "1", "2"])),
+            (Some(3), Some(&["0", "1", "2", "9"])),
+            (Some(4), None),
+        ];
+        for &(index, expected) in cases {
+            let mut conn = setup(3);
+            let result = apply_edit(&mut conn, "p", |conn, p| {
+                add_songs(conn, p, vec![song(9)], index)
+            })
+            .unwrap();
+            match expected {
+                Some(expected) => {
+                    assert!(result.is_ok(), "{:?}", index);
+                    assert_eq!(order(&conn, "p"), expected, "{:?}", index);
+                }
+                None => {
+                    assert!(result.is_err(), "{:?}", index);
+                    assert_eq!(order(&conn, "p"), ["0", "1", "2"]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_songs_already_in_playlist() {
+        let mut conn = setup(2);
+        // 曲库里没有、也没有 ID 的歌曲按路径判断
+        let path_only = MusicFile {
+            id: String::new(),
+            ..song(1)
+        };
+        let songs = vec![song(0), path_only, song(5), song(5)];
+        apply_edit(&mut conn, "p", |conn, p| add_songs(conn, p, songs, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(order(&conn, "p"), ["0", "1", "5"]);
+    }
+
+    #[test]
+    fn guards_system_playlists() {
+        let mut conn = setup(1);
+        for id in [LOCAL_PLAYLIST_ID, FAVORITES_PLAYLIST_ID] {
+            let result =
+                apply_edit(&mut conn, id, |conn, p| rename(conn, p, "改名".to_string())).unwrap();
+            assert!(result.is_err(), "rename {}", id);
+            assert!(delete(&mut conn, id).unwrap().is_err(), "delete {}", id);
+        }
+
+        let add = apply_edit(&mut conn, LOCAL_PLAYLIST_ID, |conn, p| {
+            add_songs(conn, p, vec![song(9)], None)
+        })
+        .unwrap();
+        assert!(add.is_err());
+        let moved = apply_edit(&mut conn, LOCAL_PLAYLIST_ID, |_, p| {
+            move_songs(p, &BTreeSet::new(), 0)
+        })
+        .unwrap();
+        assert!(moved.is_err());
+
+        // 我喜欢的音乐可以加歌，普通歌单可以改名和删除
+        apply_edit(&mut conn, FAVORITES_PLAYLIST_ID, |conn, p| {
+            add_songs(conn, p, vec![song(9)], None)
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(order(&conn, FAVORITES_PLAYLIST_ID), ["9"]);
+        apply_edit(&mut conn, "p", |conn, p| {
+            rename(conn, p, "新名字".to_string())
+        })
+        .unwrap()
+        .unwrap();
+        delete(&mut conn, "p").unwrap().unwrap();
+        assert!(store::read_playlist(&conn, "p").unwrap().is_none());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::commands::file_scan::{file_key, is_supported};
+use crate::commands::library_roots::{library_rules, ScanRules};
+use crate::commands::playlists::notify_playlist_change;
+use crate::commands::tags::read_music_file;
+use crate::db::read_app_data;
+use crate::models::{HealthReport, MissingFile, MusicFile, PathRewrite, RelocateResult};
+use crate::store::{self, with_db};
+use crate::watcher::{unwatch_root, watch_root};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 播放队列里的歌曲被后端改写时发出，负载以原歌曲 ID 为键、值为改写后的歌曲，
+/// 前端据此替换内存里的队列，避免下次保存时把旧路径写回去
+pub const QUEUE_CHANGED_EVENT: &str = "queue-changed";
+
+// 每个丢失文件最多返回的候选数
+const MAX_CANDIDATES: usize = 5;
+// 时长相差在这个范围内（秒）视为同一首歌
+const DURATION_TOLERANCE: f64 = 2.0;
+
+/// 检查曲库、播放队列和歌单里的本地文件是否还在，
+/// 对丢失的文件按文件名、大小和标签在曲库根目录下查找可能的新位置
+#[tauri::command]
+#[auto_collect_command]
+pub async fn check_library_health(app_handle: AppHandle) -> Result<HealthReport, String> {
+    let data = read_app_data(&app_handle)?;
+    let rules = library_rules(&app_handle)?;
+
+    tokio::task::spawn_blocking(move || {
+        // 同一个文件可能同时出现在曲库、队列和多个歌单里，只检查一次
+        let mut songs: HashMap<String, MusicFile> = HashMap::new();
+        let all_songs = data
+            .library
+            .iter()
+            .chain(data.queue.iter())
+            .chain(data.playlists.iter().flat_map(|p| p.songs.iter()));
+        for song in all_songs.filter(|s| s.is_online != Some(true)) {
+            songs
+                .entry(file_key(song).to_string())
+                .or_insert_with(|| song.clone());
+        }
+
+        let mut report = HealthReport {
+            checked: songs.len(),
+            ..Default::default()
+        };
+        let missing: Vec<(String, MusicFile)> = songs
+            .into_iter()
+            .filter(|(path, _)| !Path::new(path).exists())
+            .collect();
+        if missing.is_empty() {
+            return report;
+        }
+
+        // 新位置在曲库根目录下时，监听可能已经把它当作新歌收录了，所以不排除已收录的文件
+        let pool = CandidatePool::collect(&rules);
+        let mut suggestions: HashMap<(String, String), usize> = HashMap::new();
+
+        for (path, song) in missing {
+            let size = data.index.entries.get(&path).map(|e| e.size);
+            let candidates = pool.find(Path::new(&path), size, &song);
+            if let Some(change) = candidates
+                .first()
+                .and_then(|best| prefix_change(Path::new(&path), best))
+            {
+                *suggestions.entry(change).or_default() += 1;
+            }
+            report.missing.push(MissingFile {
+                name: song.name.clone(),
+                path,
+                candidates: candidates
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+            });
+        }
+
+        report.missing.sort_by(|a, b| a.path.cmp(&b.path));
+        report.prefix_suggestions = suggestions
+            .into_iter()
+            .map(|((from, to), count)| PathRewrite { from, to, count })
+            .collect();
+        report
+            .prefix_suggestions
+            .sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.from.cmp(&b.from)));
+        report
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))
+}
+
+/// 把丢失的文件指向新位置（from、to 为完整的文件路径），同时修改曲库、播放队列和歌单
+#[tauri::command]
+#[auto_collect_command]
+pub fn relocate_files(
+    app_handle: AppHandle,
+    moves: Vec<PathRewrite>,
+) -> Result<RelocateResult, String> {
+    for m in &moves {
+        if !Path::new(&m.to).is_file() {
+            return Err(format!("文件不存在: {}", m.to));
+        }
+    }
+    let targets: HashMap<String, String> = moves.into_iter().map(|m| (m.from, m.to)).collect();
+    apply_rewrite(&app_handle, |path| targets.get(path).cloned())
+}
+
+/// 把所有以 from 开头的本地路径改为以 to 开头，用于移动音乐盘或重命名文件夹之后；
+/// 曲库、播放队列、歌单、文件索引和曲库根目录一起修改，要么全部生效要么都不变
+#[tauri::command]
+#[auto_collect_command]
+pub fn rewrite_path_prefix(
+    app_handle: AppHandle,
+    from: String,
+    to: String,
+) -> Result<RelocateResult, String> {
+    if from.trim().is_empty() {
+        return Err("原路径不能为空".to_string());
+    }
+    if !Path::new(&to).is_dir() {
+        return Err(format!("目录不存在: {}", to));
+    }
+    let (from, to) = (PathBuf::from(from), PathBuf::from(to));
+    apply_rewrite(&app_handle, |path| {
+        let rest = Path::new(path).strip_prefix(&from).ok()?;
+        let new_path = if rest.as_os_str().is_empty() {
+            to.clone()
+        } else {
+            to.join(rest)
+        };
+        Some(new_path.to_string_lossy().to_string())
+    })
+}
+
+/// 按 rewrite 修改曲库、播放队列、歌单、文件索引和根目录里的本地路径。
+/// 在一个事务里只改有变化的行，要么全部生效要么都不变，期间其他操作写入的数据不会被覆盖
+pub(crate) fn apply_rewrite(
+    app_handle: &AppHandle,
+    rewrite: impl Fn(&str) -> Option<String>,
+) -> Result<RelocateResult, String> {
+    let (result, playlists, queue, moved_roots) = with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let rewritten = store::rewrite_songs(&tx, |song| rewrite_song(song, &rewrite))?;
+        let result = RelocateResult {
+            library: rewritten.library.len(),
+            queue: rewritten.queue.len(),
+            playlists: rewritten.playlist_entries,
+        };
+        let mut changed = rewritten.playlists;
+        let mut queue: HashMap<String, MusicFile> = rewritten
+            .queue
+            .into_iter()
+            .map(|song| (song.id.clone(), song))
+            .collect();
+
+        // 新位置已被扫描收录时同一个路径有两条记录：保留改过路径的这条（带着原来的 ID、收藏和歌单引用），
+        // 扫描出的那条的引用改指向它后删除
+        let mut merged: HashSet<String> = HashSet::new();
+        for song in &rewritten.library {
+            if merged.contains(&song.id) {
+                continue;
+            }
+            for copy in store::other_songs_at(&tx, &song.path, &song.id)? {
+                let (playlists, queued) = store::redirect_song(&tx, &copy, song)?;
+                if queued > 0 {
+                    queue.insert(copy.clone(), song.clone());
+                }
+                for id in playlists {
+                    if !changed.contains(&id) {
+                        changed.push(id);
+                    }
+                }
+                merged.insert(copy);
+            }
+        }
+
+        // 索引跟着改，避免下次扫描把搬过去的文件全部重新读一遍
+        store::rewrite_index_paths(&tx, &rewrite)?;
+
+        let mut settings = store::read_settings(&tx)?;
+        let mut moved_roots = Vec::new();
+        for root in &mut settings.library_roots {
+            if let Some(new_path) = rewrite(&root.path) {
+                moved_roots.push((
+                    std::mem::replace(&mut root.path, new_path.clone()),
+                    new_path,
+                ));
+            }
+        }
+        let download_folder = settings.download_folder.as_deref().and_then(&rewrite);
+        if !moved_roots.is_empty() || download_folder.is_some() {
+            if download_folder.is_some() {
+                settings.download_folder = download_folder;
+            }
+            store::write_settings(&tx, &settings)?;
+        }
+
+        let mut playlists = Vec::with_capacity(changed.len());
+        for id in &changed {
+            playlists.extend(store::read_playlist(&tx, id)?);
+        }
+        tx.commit()?;
+        Ok((result, playlists, queue, moved_roots))
+    })?;
+
+    if !queue.is_empty() {
+        let _ = app_handle.emit(QUEUE_CHANGED_EVENT, &queue);
+    }
+
+    for playlist in &playlists {
+        notify_playlist_change(app_handle, &playlist.id, Some(playlist));
+    }
+    for (old_root, new_root) in moved_roots {
+        unwatch_root(app_handle, &old_root);
+        watch_root(app_handle, &new_root);
+    }
+    if result.library + result.queue + result.playlists > 0 {
+        println!(
+            "[路径] 已修改：曲库{}首，播放队列{}首，歌单{}首",
+            result.library, result.queue, result.playlists
+        );
+    }
+    Ok(result)
+}
+
+/// 修改一首歌的路径，CUE 分轨同时修改整轨文件路径和虚拟路径
+fn rewrite_song(song: &mut MusicFile, rewrite: &impl Fn(&str) -> Option<String>) -> bool {
+    if song.is_online == Some(true) {
+        return false;
+    }
+    match song.source_path.clone() {
+        Some(source) => {
+            let Some(new_source) = rewrite(&source) else {
+                return false;
+            };
+            let track = song.path.strip_prefix(&source).unwrap_or_default();
+            song.path = format!("{}{}", new_source, track);
+            song.source_path = Some(new_source);
+        }
+        None => {
+            let Some(new_path) = rewrite(&song.path) else {
+                return false;
+            };
+            song.path = new_path;
+        }
+    }
+    true
+}
+
+/// 曲库根目录下的所有音频文件，按文件名和大小建立索引
+struct CandidatePool {
+    files: Vec<PathBuf>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_size: HashMap<u64, Vec<usize>>,
+}
+
+impl CandidatePool {
+    fn collect(rules: &[ScanRules]) -> Self {
+        let mut pool = Self {
+            files: Vec::new(),
+            by_name: HashMap::new(),
+            by_size: HashMap::new(),
+        };
+        for rule in rules.iter().filter(|r| r.root.is_dir()) {
+            for path in rule.walk(&rule.root) {
+                if !is_supported(&path) {
+                    continue;
+                }
+                let i = pool.files.len();
+                if let Some(name) = lowercase_name(&path) {
+                    pool.by_name.entry(name).or_default().push(i);
+                }
+                if let Ok(meta) = fs::metadata(&path) {
+                    pool.by_size.entry(meta.len()).or_default().push(i);
+                }
+                pool.files.push(path);
+            }
+        }
+        pool
+    }
+
+    /// 同名或同大小的文件作为候选，再用大小和标签打分排序
+    fn find(&self, missing: &Path, size: Option<u64>, song: &MusicFile) -> Vec<PathBuf> {
+        let name = lowercase_name(missing);
+        let mut indices: Vec<usize> = name
+            .as_ref()
+            .and_then(|n| self.by_name.get(n))
+            .into_iter()
+            .chain(size.and_then(|s| self.by_size.get(&s)))
+            .flatten()
+            .copied()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut scored: Vec<(u32, &PathBuf)> = indices
+            .into_iter()
+            .map(|i| {
+                let path = &self.files[i];
+                let mut score = 0;
+                if name.is_some() && lowercase_name(path) == name {
+                    score += 1;
+                }
+                if size.is_some() && fs::metadata(path).ok().map(|m| m.len()) == size {
+                    score += 2;
+                }
+                if read_music_file(path).is_some_and(|found| same_song(song, &found)) {
+                    score += 2;
+                }
+                (score, path)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        scored
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+}
+
+/// 标签是否指向同一首歌；CUE 分轨比较的是整轨文件，只看专辑
+fn same_song(song: &MusicFile, found: &MusicFile) -> bool {
+    fn eq(a: &Option<String>, b: &Option<String>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => a.trim().eq_ignore_ascii_case(b.trim()),
+            _ => false,
+        }
+    }
+
+    if song.source_path.is_some() {
+        return eq(&song.album, &found.album);
+    }
+    let duration_close = match (song.duration, found.duration) {
+        (Some(a), Some(b)) => (a - b).abs() <= DURATION_TOLERANCE,
+        _ => true,
+    };
+    eq(&song.title, &found.title) && eq(&song.artist, &found.artist) && duration_close
+}
+
+/// 根据旧路径和新路径末尾相同的部分，推测整个目录从哪里搬到了哪里
+fn prefix_change(old: &Path, new: &Path) -> Option<(String, String)> {
+    let old: Vec<_> = old.components().collect();
+    let new: Vec<_> = new.components().collect();
+    let common = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 || common >= old.len() || common >= new.len() {
+        return None;
+    }
+    let from: PathBuf = old[..old.len() - common].iter().collect();
+    let to: PathBuf = new[..new.len() - common].iter().collect();
+    Some((
+        from.to_string_lossy().to_string(),
+        to.to_string_lossy().to_string(),
+    ))
+}
+
+fn lowercase_name(path: &Path) -> Option<String> {
+    path.file_name().map(|n| n.to_string_lossy().to_lowercase())
+}
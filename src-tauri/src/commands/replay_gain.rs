@@ -0,0 +1,357 @@
+use crate::acoustic::{self, Fingerprinter};
+use crate::commands::file_scan::{file_key, index_entry_for};
+use crate::commands::playlists::notify_playlist_change;
+use crate::commands::scan_progress::{register_cancel, unregister_cancel};
+use crate::commands::waveform::file_stamp;
+use crate::db::{
+    load_acoustic_fingerprints, read_library, save_acoustic_fingerprints, StoredFingerprint,
+};
+use crate::decode::decode_file;
+use crate::loudness::{integrated_loudness, LoudnessMeter, TrackLoudness, REFERENCE_LOUDNESS};
+use crate::models::{
+    LoudnessProgress, LoudnessResult, MusicFile, ReplayGain, ScanDiff, TagEdit, TagEditFailure,
+};
+use crate::store::{self, with_db};
+use crate::watcher::LIBRARY_CHANGED_EVENT;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 响度分析进度事件，payload 为 LoudnessProgress
+pub const LOUDNESS_PROGRESS_EVENT: &str = "loudness-progress";
+
+/// 在后台解码并测量曲库歌曲的响度（EBU R128），计算 ReplayGain 音轨和专辑增益，
+/// 同时计算用于查找重复歌曲的声学指纹。
+/// song_ids 为空时分析所有还没有结果（或指纹已过期）的本地歌曲，force 为 true 时重新分析；
+/// 专辑增益需要整张专辑一起算，所以选中的歌曲会连同同专辑的其他歌曲一起分析。
+/// write_tags 为 true 时同时写入 REPLAYGAIN_* 标签（CUE 分轨不写，整轨文件只有一组标签）。
+/// job_id 可选，传入后可以用 cancel_scan 取消，已分析完的专辑会保留
+#[tauri::command]
+#[auto_collect_command]
+pub async fn analyze_loudness(
+    app_handle: AppHandle,
+    song_ids: Option<Vec<String>>,
+    force: Option<bool>,
+    write_tags: Option<bool>,
+    job_id: Option<String>,
+) -> Result<LoudnessResult, String> {
+    let library = read_library(&app_handle)?;
+    let fingerprints = load_acoustic_fingerprints(&app_handle)?;
+    let albums = select_albums(&library, &fingerprints, song_ids, force.unwrap_or(false));
+    let cancel = match &job_id {
+        Some(id) => register_cancel(id),
+        None => Arc::new(AtomicBool::new(false)),
+    };
+
+    let handle = app_handle.clone();
+    let write_tags = write_tags.unwrap_or(false);
+    let task_job_id = job_id.clone();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let mut progress = LoudnessProgress {
+            job_id: task_job_id,
+            total: albums.iter().map(Vec::len).sum(),
+            ..Default::default()
+        };
+        let mut result = LoudnessResult::default();
+        let mut gains: HashMap<String, ReplayGain> = HashMap::new();
+        let mut new_fingerprints: HashMap<String, StoredFingerprint> = HashMap::new();
+
+        for album in &albums {
+            match analyze_album(&handle, album, &cancel, &mut progress, &mut result.failed) {
+                Some(analysis) => {
+                    gains.extend(analysis.gains);
+                    new_fingerprints.extend(analysis.fingerprints);
+                }
+                None => {
+                    result.cancelled = true;
+                    break;
+                }
+            }
+        }
+        progress.done = true;
+        progress.cancelled = result.cancelled;
+        let _ = handle.emit(LOUDNESS_PROGRESS_EVENT, &progress);
+        (result, gains, new_fingerprints)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e));
+    if let Some(id) = &job_id {
+        unregister_cancel(id);
+    }
+    let (mut result, gains, new_fingerprints) = outcome?;
+    if gains.is_empty() {
+        return Ok(result);
+    }
+
+    // 分析期间曲库可能被监听更新过，写入标签用当前的路径，曲库按 ID 逐首更新；
+    // 保存指纹时顺便丢掉已不在曲库里的歌曲的指纹
+    save_acoustic_fingerprints(&app_handle, &new_fingerprints)?;
+    let mut entries = Vec::new();
+    if write_tags {
+        let library = read_library(&app_handle)?;
+        for song in &library {
+            let Some(gain) = gains.get(&song.id).filter(|_| song.source_path.is_none()) else {
+                continue;
+            };
+            let path = Path::new(&song.path);
+            let edit = TagEdit {
+                replay_gain: Some(gain.clone()),
+                ..Default::default()
+            };
+            match crate::commands::tags::write_tags(path, &edit) {
+                Ok(()) => {
+                    let mut song = song.clone();
+                    song.replay_gain = Some(gain.clone());
+                    if let Some(entry) = index_entry_for(path, std::slice::from_ref(&song)) {
+                        entries.push((song.path, entry));
+                    }
+                }
+                Err(error) => result.failed.push(TagEditFailure {
+                    id: song.id.clone(),
+                    path: song.path.clone(),
+                    error: format!("写入标签失败: {}", error),
+                }),
+            }
+        }
+    }
+    let (updated, playlists) = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let mut updated = Vec::with_capacity(gains.len());
+        let mut changed: Vec<String> = Vec::new();
+        for (id, gain) in &gains {
+            let Some(mut song) = store::read_song(&tx, id)? else {
+                continue;
+            };
+            song.replay_gain = Some(gain.clone());
+            for id in store::update_song(&tx, &song)? {
+                if !changed.contains(&id) {
+                    changed.push(id);
+                }
+            }
+            updated.push(song);
+        }
+        for (path, entry) in entries {
+            store::touch_index_entry(&tx, &path, entry)?;
+        }
+        let mut playlists = Vec::with_capacity(changed.len());
+        for id in &changed {
+            playlists.extend(store::read_playlist(&tx, id)?);
+        }
+        tx.commit()?;
+        Ok((updated, playlists))
+    })?;
+    result.updated = updated;
+    for playlist in &playlists {
+        notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+    }
+
+    println!(
+        "[响度] 分析{}：{}首，失败{}首",
+        if result.cancelled {
+            "已取消"
+        } else {
+            "完成"
+        },
+        result.updated.len(),
+        result.failed.len()
+    );
+    let diff = ScanDiff {
+        updated: result.updated.clone(),
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    Ok(result)
+}
+
+/// 按专辑分组要分析的歌曲；没有专辑信息的歌曲各自成组，只计算音轨增益
+fn select_albums(
+    library: &[MusicFile],
+    fingerprints: &HashMap<String, StoredFingerprint>,
+    song_ids: Option<Vec<String>>,
+    force: bool,
+) -> Vec<Vec<MusicFile>> {
+    let only: Option<HashSet<String>> = song_ids.map(|ids| ids.into_iter().collect());
+    let local = library.iter().filter(|s| s.is_online != Some(true));
+
+    let mut albums: Vec<Vec<MusicFile>> = Vec::new();
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+    for song in local {
+        match album_key(song) {
+            Some(key) => match positions.get(&key) {
+                Some(&i) => albums[i].push(song.clone()),
+                None => {
+                    positions.insert(key, albums.len());
+                    albums.push(vec![song.clone()]);
+                }
+            },
+            None => albums.push(vec![song.clone()]),
+        }
+    }
+
+    albums
+        .into_iter()
+        .filter(|songs| {
+            songs.iter().any(|s| {
+                let selected = only.as_ref().is_none_or(|ids| ids.contains(&s.id));
+                selected
+                    && (force
+                        || s.replay_gain.is_none()
+                        || !fingerprint_is_current(s, fingerprints))
+            })
+        })
+        .collect()
+}
+
+/// 歌曲有指纹，且计算指纹之后音频文件没有变过
+pub(crate) fn fingerprint_is_current(
+    song: &MusicFile,
+    fingerprints: &HashMap<String, StoredFingerprint>,
+) -> bool {
+    fingerprints.get(&song.id).is_some_and(|stored| {
+        file_stamp(Path::new(file_key(song)))
+            .is_ok_and(|(size, mtime)| stored.size == size && stored.mtime == mtime)
+    })
+}
+
+/// 同一专辑艺术家（没有时用艺术家）的同名专辑视为一张专辑
+fn album_key(song: &MusicFile) -> Option<(String, String)> {
+    let album = song.album.as_deref()?.trim().to_lowercase();
+    if album.is_empty() {
+        return None;
+    }
+    let artist = song
+        .album_artist
+        .as_deref()
+        .or(song.artist.as_deref())
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    Some((artist, album))
+}
+
+/// 一张专辑的分析结果，都以歌曲 ID 为键
+struct AlbumAnalysis {
+    gains: Vec<(String, ReplayGain)>,
+    fingerprints: Vec<(String, StoredFingerprint)>,
+}
+
+/// 分析一张专辑的所有歌曲，返回每首歌的增益和指纹；取消时返回 None，这张专辑的结果不保留
+fn analyze_album(
+    app_handle: &AppHandle,
+    songs: &[MusicFile],
+    cancel: &AtomicBool,
+    progress: &mut LoudnessProgress,
+    failed: &mut Vec<TagEditFailure>,
+) -> Option<AlbumAnalysis> {
+    let mut measured: Vec<(&MusicFile, TrackLoudness)> = Vec::new();
+    let mut fingerprints = Vec::new();
+    for song in songs {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        progress.current = song.name.clone();
+        let _ = app_handle.emit(LOUDNESS_PROGRESS_EVENT, &*progress);
+
+        let range = song
+            .source_path
+            .as_ref()
+            .map(|_| (song.start_time.unwrap_or(0.0), song.end_time));
+        let path = Path::new(file_key(song));
+        // 先取文件时间戳再解码，解码期间文件被改动时指纹会在下次分析时重算
+        let stamp = file_stamp(path);
+        match analyze_song(path, range, cancel) {
+            Ok((loudness, fingerprint)) if loudness.integrated.is_some() => {
+                progress.analyzed += 1;
+                measured.push((song, loudness));
+                if let Ok((size, mtime)) = stamp {
+                    fingerprints.push((
+                        song.id.clone(),
+                        StoredFingerprint {
+                            size,
+                            mtime,
+                            fingerprint: acoustic::encode(&fingerprint),
+                        },
+                    ));
+                }
+            }
+            Ok(_) => {
+                progress.failed += 1;
+                failed.push(failure(song, "整首都是静音"));
+            }
+            Err(_) if cancel.load(Ordering::Relaxed) => return None,
+            Err(error) => {
+                progress.failed += 1;
+                failed.push(failure(song, &error));
+            }
+        }
+    }
+
+    // 专辑增益按所有音轨的测量块合并计算，而不是各轨响度的平均
+    let is_album = album_key(&songs[0]).is_some();
+    let album_loudness = is_album
+        .then(|| {
+            let blocks: Vec<f64> = measured
+                .iter()
+                .flat_map(|(_, l)| l.blocks.iter().copied())
+                .collect();
+            integrated_loudness(&blocks)
+        })
+        .flatten();
+    let album_peak = measured
+        .iter()
+        .map(|(_, l)| l.true_peak)
+        .fold(0.0, f64::max);
+
+    let gains = measured
+        .into_iter()
+        .filter_map(|(song, loudness)| {
+            let integrated = loudness.integrated?;
+            Some((
+                song.id.clone(),
+                ReplayGain {
+                    loudness: integrated,
+                    true_peak: loudness.true_peak,
+                    track_gain: REFERENCE_LOUDNESS - integrated,
+                    album_gain: album_loudness.map(|l| REFERENCE_LOUDNESS - l),
+                    album_peak: album_loudness.map(|_| album_peak),
+                },
+            ))
+        })
+        .collect();
+    Some(AlbumAnalysis {
+        gains,
+        fingerprints,
+    })
+}
+
+/// 解码一次，同时测量响度和计算声学指纹
+fn analyze_song(
+    path: &Path,
+    range: Option<(f64, Option<f64>)>,
+    cancel: &AtomicBool,
+) -> Result<(TrackLoudness, Vec<u32>), String> {
+    let mut analyzers: Option<(LoudnessMeter, Fingerprinter)> = None;
+    decode_file(path, range, cancel, |sample_rate, channels, samples| {
+        let (meter, fingerprinter) = analyzers.get_or_insert_with(|| {
+            (
+                LoudnessMeter::new(sample_rate, channels),
+                Fingerprinter::new(sample_rate, channels),
+            )
+        });
+        meter.process(samples);
+        fingerprinter.process(samples);
+    })?;
+    let (meter, fingerprinter) = analyzers.ok_or_else(|| "没有解码出音频".to_string())?;
+    Ok((meter.finish(), fingerprinter.finish()))
+}
+
+fn failure(song: &MusicFile, error: &str) -> TagEditFailure {
+    TagEditFailure {
+        id: song.id.clone(),
+        path: song.path.clone(),
+        error: error.to_string(),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::models::ScanProgress;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 扫描进度事件，payload 为 ScanProgress
+pub const SCAN_PROGRESS_EVENT: &str = "scan-progress";
+
+// 文件很多时每个文件都发事件会拖慢前端，按时间间隔节流
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// 正在进行的扫描，scan_id -> 取消标记
+static ACTIVE_SCANS: OnceLock<Mutex<HashMap<String, Arc<AtomicBool>>>> = OnceLock::new();
+
+fn active_scans() -> &'static Mutex<HashMap<String, Arc<AtomicBool>>> {
+    ACTIVE_SCANS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// 取消指定的扫描（或响度分析等其他登记了 ID 的任务），任务会尽快停止并返回已完成的部分结果；
+/// 返回 false 表示没有找到这个任务（可能已经结束）
+#[tauri::command]
+#[auto_collect_command]
+pub fn cancel_scan(scan_id: String) -> Result<bool, String> {
+    let scans = active_scans().lock().map_err(|e| e.to_string())?;
+    match scans.get(&scan_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            println!("[扫描] 已请求取消: {}", scan_id);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// 登记一个可以用 cancel_scan 取消的任务，返回它的取消标记
+pub(crate) fn register_cancel(id: &str) -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    if let Ok(mut scans) = active_scans().lock() {
+        scans.insert(id.to_string(), flag.clone());
+    }
+    flag
+}
+
+pub(crate) fn unregister_cancel(id: &str) {
+    if let Ok(mut scans) = active_scans().lock() {
+        scans.remove(id);
+    }
+}
+
+/// 记录一次扫描的进度，定时向前端发送事件，并负责检查取消标记
+pub(crate) struct ScanTracker {
+    app_handle: AppHandle,
+    cancel_flag: Arc<AtomicBool>,
+    progress: ScanProgress,
+    last_emit: Instant,
+}
+
+impl ScanTracker {
+    pub(crate) fn new(app_handle: AppHandle, scan_id: Option<String>) -> Self {
+        let cancel_flag = match &scan_id {
+            Some(id) => register_cancel(id),
+            None => Arc::new(AtomicBool::new(false)),
+        };
+        Self {
+            app_handle,
+            cancel_flag,
+            progress: ScanProgress {
+                scan_id,
+                ..Default::default()
+            },
+            last_emit: Instant::now(),
+        }
+    }
+
+    /// 每访问一个文件调用一次，返回 false 表示扫描已被取消，应立即停止
+    pub(crate) fn visit(&mut self, path: &Path) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+        self.progress.files_visited += 1;
+        if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+            if let Some(dir) = path.parent() {
+                self.progress.current_dir = dir.to_string_lossy().to_string();
+            }
+            self.emit();
+        }
+        true
+    }
+
+    /// 每确认一个音频文件调用一次。读取标签比遍历目录慢得多，这里同样按间隔发送进度
+    pub(crate) fn found_audio(&mut self, path: &Path) {
+        self.progress.audio_found += 1;
+        if self.last_emit.elapsed() >= PROGRESS_INTERVAL {
+            if let Some(dir) = path.parent() {
+                self.progress.current_dir = dir.to_string_lossy().to_string();
+            }
+            self.emit();
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::Relaxed)
+    }
+
+    /// 发送最后一次进度（done 为 true），前端据此收起进度条
+    pub(crate) fn finish(mut self) {
+        self.progress.done = true;
+        self.progress.cancelled = self.is_cancelled();
+        self.emit();
+    }
+
+    fn emit(&mut self) {
+        self.last_emit = Instant::now();
+        let _ = self.app_handle.emit(SCAN_PROGRESS_EVENT, &self.progress);
+    }
+}
+
+impl Drop for ScanTracker {
+    fn drop(&mut self) {
+        if let Some(id) = &self.progress.scan_id {
+            unregister_cancel(id);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::commands::file_scan::index_entry_for;
+use crate::commands::playlists::notify_playlist_change;
+use crate::commands::tags::{read_music_file, write_tags};
+use crate::db::read_library;
+use crate::models::{ScanDiff, TagEdit, TagEditFailure, TagEditResult};
+use crate::store::{self, with_db};
+use crate::watcher::LIBRARY_CHANGED_EVENT;
+use std::path::Path;
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 修改一首或多首歌的标签并写回文件，同时更新曲库。
+/// 多选时同一个改动应用到每个文件，单个文件失败不影响其他文件
+#[tauri::command]
+#[auto_collect_command]
+pub async fn edit_tags(
+    app_handle: AppHandle,
+    song_ids: Vec<String>,
+    edit: TagEdit,
+) -> Result<TagEditResult, String> {
+    let handle = app_handle.clone();
+    let (result, entries) = tokio::task::spawn_blocking(move || {
+        let library = read_library(&handle)?;
+        let mut result = TagEditResult::default();
+        let mut entries = Vec::new();
+
+        for id in &song_ids {
+            let Some(song) = library.iter().find(|s| &s.id == id) else {
+                result.failed.push(TagEditFailure {
+                    id: id.clone(),
+                    path: String::new(),
+                    error: "曲库中找不到这首歌".to_string(),
+                });
+                continue;
+            };
+            let fail = |error: String| TagEditFailure {
+                id: id.clone(),
+                path: song.path.clone(),
+                error,
+            };
+            if song.is_online == Some(true) {
+                result.failed.push(fail("在线歌曲不能修改标签".to_string()));
+                continue;
+            }
+            // CUE 分轨的信息来自 CUE 文件，改整轨文件的标签会影响所有分轨
+            if song.source_path.is_some() {
+                result
+                    .failed
+                    .push(fail("CUE 分轨暂不支持修改标签".to_string()));
+                continue;
+            }
+
+            let path = Path::new(&song.path);
+            if let Err(e) = write_tags(path, &edit) {
+                result.failed.push(fail(e));
+                continue;
+            }
+            let Some(mut updated) = read_music_file(path) else {
+                result
+                    .failed
+                    .push(fail("写入后无法重新读取文件".to_string()));
+                continue;
+            };
+            updated.id = song.id.clone();
+            // 音频数据没变，文件里没有 REPLAYGAIN 标签时沿用已有的分析结果
+            updated.replay_gain = updated.replay_gain.or_else(|| song.replay_gain.clone());
+            if let Some(entry) = index_entry_for(path, std::slice::from_ref(&updated)) {
+                entries.push((updated.path.clone(), entry));
+            }
+            result.updated.push(updated);
+        }
+        Ok::<_, String>((result, entries))
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
+
+    if !result.updated.is_empty() {
+        // 文件已经写好，曲库、歌单、播放队列和索引里只改这几首歌
+        let playlists = with_db(&app_handle, |conn| {
+            let tx = conn.transaction()?;
+            let mut changed: Vec<String> = Vec::new();
+            for song in &result.updated {
+                for id in store::update_song(&tx, song)? {
+                    if !changed.contains(&id) {
+                        changed.push(id);
+                    }
+                }
+            }
+            for (path, entry) in entries {
+                store::touch_index_entry(&tx, &path, entry)?;
+            }
+            let mut playlists = Vec::with_capacity(changed.len());
+            for id in &changed {
+                playlists.extend(store::read_playlist(&tx, id)?);
+            }
+            tx.commit()?;
+            Ok(playlists)
+        })?;
+        for playlist in &playlists {
+            notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+        }
+        let diff = ScanDiff {
+            updated: result.updated.clone(),
+            ..Default::default()
+        };
+        let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    }
+    println!(
+        "[标签] 已修改{}首，失败{}首",
+        result.updated.len(),
+        result.failed.len()
+    );
+    Ok(result)
+}
@@ -0,0 +1,176 @@
+use crate::commands::file_scan::index_entry_for;
+use crate::commands::playlists::notify_playlist_change;
+use crate::commands::tags::read_music_file_with;
+use crate::db::{read_library, read_settings, write_settings};
+use crate::filename_pattern::{compile_patterns, infer_from_path, FilenamePattern};
+use crate::models::{MusicFile, ScanDiff, TagChange, TagInference};
+use crate::store::{self, with_db};
+use crate::watcher::LIBRARY_CHANGED_EVENT;
+use std::collections::HashSet;
+use std::path::Path;
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 预览按文件名规则会给曲库里哪些歌曲补上哪些标签，不修改曲库
+#[tauri::command]
+#[auto_collect_command]
+pub async fn preview_filename_tags(
+    app_handle: AppHandle,
+    patterns: Vec<String>,
+) -> Result<Vec<TagInference>, String> {
+    let patterns = compile_patterns(&patterns)?;
+    let library = read_library(&app_handle)?;
+
+    tokio::task::spawn_blocking(move || {
+        infer_library(&library, &patterns, None)
+            .into_iter()
+            .map(|(position, updated, pattern)| TagInference {
+                id: updated.id.clone(),
+                path: updated.path.clone(),
+                pattern,
+                changes: tag_changes(&library[position], &updated),
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))
+}
+
+/// 保存文件名规则并应用到曲库，之后扫描到的新文件也会使用这些规则。
+/// song_ids 为预览后确认的歌曲，不传时应用到所有匹配的歌曲
+#[tauri::command]
+#[auto_collect_command]
+pub async fn apply_filename_tags(
+    app_handle: AppHandle,
+    patterns: Vec<String>,
+    song_ids: Option<Vec<String>>,
+) -> Result<Vec<MusicFile>, String> {
+    let compiled = compile_patterns(&patterns)?;
+    let mut settings = read_settings(&app_handle)?;
+    settings.filename_patterns = patterns;
+    write_settings(&app_handle, &settings)?;
+
+    let library = read_library(&app_handle)?;
+    let updated = tokio::task::spawn_blocking(move || {
+        let only: Option<HashSet<String>> = song_ids.map(|ids| ids.into_iter().collect());
+        infer_library(&library, &compiled, only.as_ref())
+            .into_iter()
+            .map(|(_, song, _)| {
+                let entry = index_entry_for(Path::new(&song.path), std::slice::from_ref(&song));
+                (song, entry)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+
+    if updated.is_empty() {
+        return Ok(vec![]);
+    }
+    let playlists = with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        let mut changed: Vec<String> = Vec::new();
+        for (song, entry) in &updated {
+            for id in store::update_song(&tx, song)? {
+                if !changed.contains(&id) {
+                    changed.push(id);
+                }
+            }
+            if let Some(entry) = entry {
+                store::touch_index_entry(&tx, &song.path, entry.clone())?;
+            }
+        }
+        let mut playlists = Vec::with_capacity(changed.len());
+        for id in &changed {
+            playlists.extend(store::read_playlist(&tx, id)?);
+        }
+        tx.commit()?;
+        Ok(playlists)
+    })?;
+    let updated: Vec<MusicFile> = updated.into_iter().map(|(song, _)| song).collect();
+
+    for playlist in &playlists {
+        notify_playlist_change(&app_handle, &playlist.id, Some(playlist));
+    }
+    println!("[标签] 按文件名规则更新了{}首", updated.len());
+    let diff = ScanDiff {
+        updated: updated.clone(),
+        ..Default::default()
+    };
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+    Ok(updated)
+}
+
+/// 找出规则能匹配、且推断后标签会变化的本地歌曲，返回它们在曲库中的位置、
+/// 重新读取（带推断）后的歌曲和匹配上的规则。CUE 分轨的信息来自 CUE 文件，不参与推断
+fn infer_library(
+    library: &[MusicFile],
+    patterns: &[FilenamePattern],
+    only: Option<&HashSet<String>>,
+) -> Vec<(usize, MusicFile, String)> {
+    let mut results = Vec::new();
+    for (position, song) in library.iter().enumerate() {
+        if song.is_online == Some(true) || song.source_path.is_some() {
+            continue;
+        }
+        if only.is_some_and(|ids| !ids.contains(&song.id)) {
+            continue;
+        }
+        // 先只用路径匹配，匹配不上的文件不用重新读取
+        let path = Path::new(&song.path);
+        let Some(inferred) = infer_from_path(path, patterns) else {
+            continue;
+        };
+        // 曲库里的标签可能已经是按旧规则推断的，重新读取文件才知道哪些字段真正缺失
+        let Some(mut updated) = read_music_file_with(path, patterns) else {
+            continue;
+        };
+        updated.id = song.id.clone();
+        // 音频数据没变，文件里没有 REPLAYGAIN 标签时沿用已有的分析结果
+        updated.replay_gain = updated.replay_gain.or_else(|| song.replay_gain.clone());
+        if !tag_changes(song, &updated).is_empty() {
+            results.push((position, updated, inferred.pattern));
+        }
+    }
+    results
+}
+
+fn tag_changes(old: &MusicFile, new: &MusicFile) -> Vec<TagChange> {
+    fn number(value: &Option<u32>) -> Option<String> {
+        value.map(|n| n.to_string())
+    }
+
+    let fields = [
+        ("title", old.title.clone(), new.title.clone()),
+        ("artist", old.artist.clone(), new.artist.clone()),
+        ("album", old.album.clone(), new.album.clone()),
+        (
+            "albumArtist",
+            old.album_artist.clone(),
+            new.album_artist.clone(),
+        ),
+        (
+            "trackNumber",
+            number(&old.track_number),
+            number(&new.track_number),
+        ),
+        (
+            "discNumber",
+            number(&old.disc_number),
+            number(&new.disc_number),
+        ),
+        ("year", number(&old.year), number(&new.year)),
+        ("genre", old.genre.clone(), new.genre.clone()),
+    ];
+    fields
+        .into_iter()
+        .filter_map(|(field, from, to)| match to {
+            Some(to) if from.as_ref() != Some(&to) => Some(TagChange {
+                field: field.to_string(),
+                from,
+                to,
+            }),
+            _ => None,
+        })
+        .collect()
+}
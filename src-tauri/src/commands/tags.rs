@@ -0,0 +1,555 @@
+use crate::filename_pattern::{infer_from_path, FilenamePattern};
+use crate::loudness::REFERENCE_LOUDNESS;
+use crate::models::{BitrateMode, MusicFile, ReplayGain, TagEdit};
+use crate::probe::{is_lossless_codec, mpeg_is_vbr, probe_audio, AudioFormat, DetectedFormat};
+use id3::TagLike;
+use lofty::config::WriteOptions;
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::{Tag, TagType};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// 读取音频文件的内嵌标签（ID3v2 / Vorbis Comment / MP4 atom 等），
+/// 标签缺失时以文件名（不含扩展名）作为标题；文件头不是可识别的音频时返回 None
+pub(crate) fn read_music_file(path: &Path) -> Option<MusicFile> {
+    read_music_file_with(path, &[])
+}
+
+/// 与 read_music_file 相同，标签里缺失的字段先按文件名规则推断
+pub(crate) fn read_music_file_with(path: &Path, patterns: &[FilenamePattern]) -> Option<MusicFile> {
+    let detected = probe_audio(path)?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut music = MusicFile {
+        path: path.to_string_lossy().to_string(),
+        name: stem.clone(),
+        codec: Some(detected.codec.to_string()),
+        container: Some(detected.format.container().to_string()),
+        bitrate_mode: bitrate_mode(path, &detected),
+        ..Default::default()
+    };
+
+    // lofty 不支持 DSD，DSF 单独解析文件头和末尾的 ID3v2 标签，DFF 只读取音频参数
+    let result = match detected.format {
+        AudioFormat::Dsf => read_dsf(path, &mut music).map_err(|e| e.to_string()),
+        AudioFormat::Dff => read_dff(path, &mut music).map_err(|e| e.to_string()),
+        _ => read_with_lofty(path, &mut music),
+    };
+    if let Err(e) = result {
+        println!("[标签] 读取失败 {}: {}", path.display(), e);
+    }
+
+    if let Some(inferred) = infer_from_path(path, patterns) {
+        inferred.fill_missing(&mut music);
+    }
+    music.title.get_or_insert(stem);
+    music.name = display_name(&music);
+    Some(music)
+}
+
+/// 列表里显示的名称：有歌手时为 "歌手 - 标题"，与在线歌曲保持一致
+pub(crate) fn display_name(music: &MusicFile) -> String {
+    let title = music.title.clone().unwrap_or_default();
+    match &music.artist {
+        Some(artist) => format!("{} - {}", artist, title),
+        None => title,
+    }
+}
+
+fn read_with_lofty(path: &Path, music: &mut MusicFile) -> Result<(), String> {
+    // 按文件内容而不是扩展名判断类型，扩展名写错的文件也能读到标签
+    let tagged_file = Probe::open(path)
+        .map_err(|e| e.to_string())?
+        .guess_file_type()
+        .map_err(|e| e.to_string())?
+        .read()
+        .map_err(|e| e.to_string())?;
+
+    let properties = tagged_file.properties();
+    let duration = properties.duration().as_secs_f64();
+    if duration > 0.0 {
+        music.duration = Some(duration);
+    }
+    music.bitrate = properties
+        .audio_bitrate()
+        .or(properties.overall_bitrate())
+        .filter(|b| *b > 0);
+    music.sample_rate = properties.sample_rate().filter(|r| *r > 0);
+    music.channels = properties.channels().map(u32::from).filter(|c| *c > 0);
+    // 有损编码的"位深"只是解码输出的格式，不显示
+    if music.bitrate_mode == Some(BitrateMode::Lossless) {
+        music.bit_depth = properties.bit_depth().map(u32::from).filter(|b| *b > 0);
+    }
+    if let Some(tag) = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+    {
+        apply_tag(music, tag);
+    }
+    Ok(())
+}
+
+/// DSF 文件头：DSD 块之后紧跟 fmt 块，元数据指针指向文件末尾的 ID3v2 标签
+fn read_dsf(path: &Path, music: &mut MusicFile) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 72];
+    file.read_exact(&mut header)?;
+
+    let metadata_offset = u64::from_le_bytes(header[20..28].try_into().unwrap_or_default());
+    let channels = u32::from_le_bytes(header[52..56].try_into().unwrap_or_default());
+    let sample_rate = u32::from_le_bytes(header[56..60].try_into().unwrap_or_default());
+    let bits = u32::from_le_bytes(header[60..64].try_into().unwrap_or_default());
+    let sample_count = u64::from_le_bytes(header[64..72].try_into().unwrap_or_default());
+    set_dsd_properties(music, sample_rate, channels, bits);
+    if sample_rate > 0 {
+        music.duration = Some(sample_count as f64 / sample_rate as f64);
+    }
+
+    if metadata_offset > 0 {
+        file.seek(SeekFrom::Start(metadata_offset))?;
+        if let Ok(tag) = id3::Tag::read_from2(&mut file) {
+            apply_id3(music, &tag);
+        }
+    }
+    Ok(())
+}
+
+/// DFF（DSDIFF）：FRM8 容器里 PROP 块的 FS、CHNL 子块给出采样率和声道数，
+/// DSD 块的大小换算成时长。DFF 没有标准的标签格式，不读取标签
+fn read_dff(path: &Path, music: &mut MusicFile) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let (mut sample_rate, mut channels, mut data_len) = (0u32, 0u32, 0u64);
+
+    // FRM8 头 12 字节 + 表单类型 "DSD " 4 字节
+    let mut offset = 16u64;
+    while offset + 12 <= file_len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut chunk = [0u8; 12];
+        file.read_exact(&mut chunk)?;
+        let size = u64::from_be_bytes(chunk[4..12].try_into().unwrap_or_default());
+        match &chunk[..4] {
+            b"PROP" => {
+                let mut prop = vec![0u8; size.min(64 * 1024) as usize];
+                file.read_exact(&mut prop)?;
+                // 跳过属性类型 "SND "
+                let mut sub = 4;
+                while sub + 12 <= prop.len() {
+                    let sub_size =
+                        u64::from_be_bytes(prop[sub + 4..sub + 12].try_into().unwrap_or_default())
+                            as usize;
+                    let body = &prop[sub + 12..(sub + 12 + sub_size).min(prop.len())];
+                    match &prop[sub..sub + 4] {
+                        b"FS  " if body.len() >= 4 => {
+                            sample_rate =
+                                u32::from_be_bytes(body[..4].try_into().unwrap_or_default())
+                        }
+                        b"CHNL" if body.len() >= 2 => {
+                            channels =
+                                u16::from_be_bytes(body[..2].try_into().unwrap_or_default()) as u32
+                        }
+                        _ => {}
+                    }
+                    sub += 12 + sub_size + (sub_size & 1);
+                }
+            }
+            b"DSD " => data_len = size,
+            _ => {}
+        }
+        offset += 12 + size + (size & 1);
+    }
+
+    set_dsd_properties(music, sample_rate, channels, 1);
+    if sample_rate > 0 && channels > 0 && data_len > 0 {
+        music.duration = Some(data_len as f64 * 8.0 / (sample_rate as f64 * channels as f64));
+    }
+    Ok(())
+}
+
+fn set_dsd_properties(music: &mut MusicFile, sample_rate: u32, channels: u32, bits: u32) {
+    music.sample_rate = Some(sample_rate).filter(|r| *r > 0);
+    music.channels = Some(channels).filter(|c| *c > 0);
+    music.bit_depth = Some(bits).filter(|b| *b > 0);
+    if sample_rate > 0 && channels > 0 {
+        music.bitrate =
+            Some((sample_rate as u64 * channels as u64 * bits.max(1) as u64 / 1000) as u32);
+    }
+}
+
+/// 无损编码单独标出；MP3 检查 Xing 头或各帧比特率，Vorbis、Opus 是可变码率，
+/// 其余有损编码（AAC 等）从文件头判断不了，留空
+fn bitrate_mode(path: &Path, detected: &DetectedFormat) -> Option<BitrateMode> {
+    if is_lossless_codec(detected.codec) {
+        return Some(BitrateMode::Lossless);
+    }
+    match detected.format {
+        AudioFormat::Mpeg => mpeg_is_vbr(path).map(|vbr| {
+            if vbr {
+                BitrateMode::Vbr
+            } else {
+                BitrateMode::Cbr
+            }
+        }),
+        AudioFormat::OggVorbis | AudioFormat::OggOpus => Some(BitrateMode::Vbr),
+        _ => None,
+    }
+}
+
+fn apply_id3(music: &mut MusicFile, tag: &id3::Tag) {
+    music.title = non_empty(tag.title());
+    music.artist = non_empty(tag.artist());
+    music.album = non_empty(tag.album());
+    music.album_artist = non_empty(tag.album_artist());
+    music.compilation = tag
+        .get("TCMP")
+        .and_then(|frame| frame.content().text())
+        .map(is_true_flag);
+    music.genre = non_empty(tag.genre_parsed().as_deref());
+    music.track_number = tag.track();
+    music.disc_number = tag.disc();
+    music.year = tag.year().and_then(|y| u32::try_from(y).ok());
+    music.replay_gain = read_replay_gain(|key| {
+        tag.extended_texts()
+            .find(|t| t.description.eq_ignore_ascii_case(key))
+            .map(|t| t.value.clone())
+    });
+}
+
+fn apply_tag(music: &mut MusicFile, tag: &Tag) {
+    music.title = non_empty(tag.title().as_deref());
+    music.artist = non_empty(tag.artist().as_deref());
+    music.album = non_empty(tag.album().as_deref());
+    music.album_artist = non_empty(tag.get_string(ItemKey::AlbumArtist));
+    music.compilation = tag.get_string(ItemKey::FlagCompilation).map(is_true_flag);
+    music.genre = non_empty(tag.genre().as_deref());
+    music.track_number = tag.track();
+    music.disc_number = tag.disk();
+    music.year = tag.date().map(|d| d.year as u32).or_else(|| {
+        // 部分文件的日期不是标准格式（如 "2004/08/03"），只取前四位年份
+        tag.get_string(ItemKey::RecordingDate)
+            .and_then(|s| s.get(..4))
+            .and_then(|y| y.parse().ok())
+    });
+    music.replay_gain =
+        read_replay_gain(|key| tag.get_string(replay_gain_key(key)).map(str::to_string));
+}
+
+/// 标记类标签的值一般是 "1"，也有写成 "true" 的
+fn is_true_flag(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes")
+}
+
+/// 读取 REPLAYGAIN_* 标签；音轨增益和峰值都在时才算有效，响度按参考电平反推
+fn read_replay_gain(get: impl Fn(&str) -> Option<String>) -> Option<ReplayGain> {
+    fn number(value: Option<String>) -> Option<f64> {
+        let value = value?;
+        let value = value.trim();
+        let value = value
+            .strip_suffix("dB")
+            .or_else(|| value.strip_suffix("db"))
+            .unwrap_or(value);
+        value.trim().parse().ok()
+    }
+
+    let track_gain = number(get("REPLAYGAIN_TRACK_GAIN"))?;
+    let true_peak = number(get("REPLAYGAIN_TRACK_PEAK"))?;
+    Some(ReplayGain {
+        loudness: REFERENCE_LOUDNESS - track_gain,
+        true_peak,
+        track_gain,
+        album_gain: number(get("REPLAYGAIN_ALBUM_GAIN")),
+        album_peak: number(get("REPLAYGAIN_ALBUM_PEAK")),
+    })
+}
+
+/// lofty 会把这些键映射到各格式对应的位置（ID3v2 为 TXXX，MP4 为 ---- atom）
+fn replay_gain_key(name: &str) -> ItemKey {
+    match name {
+        "REPLAYGAIN_TRACK_GAIN" => ItemKey::ReplayGainTrackGain,
+        "REPLAYGAIN_TRACK_PEAK" => ItemKey::ReplayGainTrackPeak,
+        "REPLAYGAIN_ALBUM_GAIN" => ItemKey::ReplayGainAlbumGain,
+        _ => ItemKey::ReplayGainAlbumPeak,
+    }
+}
+
+/// 写入标签时使用的 REPLAYGAIN_* 键值，专辑增益为空时对应的键会被删除
+fn replay_gain_items(gain: &ReplayGain) -> [(&'static str, Option<String>); 4] {
+    [
+        (
+            "REPLAYGAIN_TRACK_GAIN",
+            Some(format!("{:.2} dB", gain.track_gain)),
+        ),
+        (
+            "REPLAYGAIN_TRACK_PEAK",
+            Some(format!("{:.6}", gain.true_peak)),
+        ),
+        (
+            "REPLAYGAIN_ALBUM_GAIN",
+            gain.album_gain.map(|g| format!("{:.2} dB", g)),
+        ),
+        (
+            "REPLAYGAIN_ALBUM_PEAK",
+            gain.album_peak.map(|p| format!("{:.6}", p)),
+        ),
+    ]
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(|s| s.trim_matches(char::from(0)).trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+/// 读取文件里的 ID3v2 标签（MP3、AAC、WAV、AIFF、DSF），用于 lofty 通用接口里没有的帧，如 SYLT
+pub(crate) fn read_id3_tag(path: &Path) -> Option<id3::Tag> {
+    match probe_audio(path)?.format {
+        // read_from_path 会按文件头自动处理 WAV、AIFF 里的 ID3 块
+        AudioFormat::Mpeg | AudioFormat::Adts | AudioFormat::Wav | AudioFormat::Aiff => {
+            id3::Tag::read_from_path(path).ok()
+        }
+        AudioFormat::Dsf => {
+            let mut file = File::open(path).ok()?;
+            let mut header = [0u8; 28];
+            file.read_exact(&mut header).ok()?;
+            let metadata_offset = u64::from_le_bytes(header[20..28].try_into().ok()?);
+            if metadata_offset == 0 {
+                return None;
+            }
+            file.seek(SeekFrom::Start(metadata_offset)).ok()?;
+            id3::Tag::read_from2(&mut file).ok()
+        }
+        _ => None,
+    }
+}
+
+/// 读取内嵌的普通歌词（ID3v2 USLT、Vorbis LYRICS、MP4 ©lyr 等）
+pub(crate) fn read_embedded_lyrics(path: &Path) -> Option<String> {
+    let detected = probe_audio(path)?;
+    if matches!(detected.format, AudioFormat::Dsf | AudioFormat::Dff) {
+        return read_id3_tag(path)?
+            .lyrics()
+            .map(|l| l.text.clone())
+            .find(|t| !t.trim().is_empty());
+    }
+    let tagged_file = Probe::open(path)
+        .ok()?
+        .guess_file_type()
+        .ok()?
+        .read()
+        .ok()?;
+    tagged_file.tags().iter().find_map(|tag| {
+        [ItemKey::Lyrics, ItemKey::UnsyncLyrics]
+            .into_iter()
+            .find_map(|key| non_empty(tag.get_string(key)))
+    })
+}
+
+/// 把标签改动写回音频文件。DSF 用 id3 写入文件末尾的 ID3v2 标签，其余格式交给 lofty；
+/// DFF 没有标准的标签格式，不支持写入
+pub(crate) fn write_tags(path: &Path, edit: &TagEdit) -> Result<(), String> {
+    let detected = probe_audio(path).ok_or_else(|| "不支持的音频格式".to_string())?;
+    let cover = match edit.cover.as_deref() {
+        Some("") => Some(None),
+        Some(cover_path) => Some(Some(read_cover_image(Path::new(cover_path))?)),
+        None => None,
+    };
+
+    match detected.format {
+        AudioFormat::Dsf => write_dsf(path, edit, cover).map_err(|e| e.to_string()),
+        AudioFormat::Dff => Err("DFF 文件不支持写入标签".to_string()),
+        _ => write_with_lofty(path, edit, cover),
+    }
+}
+
+/// 读取封面图片，返回数据和 MIME 类型
+fn read_cover_image(path: &Path) -> Result<(Vec<u8>, MimeType), String> {
+    let data = std::fs::read(path).map_err(|e| format!("读取封面失败: {}", e))?;
+    let mime_type = match image::guess_format(&data) {
+        Ok(image::ImageFormat::Jpeg) => MimeType::Jpeg,
+        Ok(image::ImageFormat::Png) => MimeType::Png,
+        _ => return Err("封面只支持 JPEG 或 PNG 图片".to_string()),
+    };
+    Ok((data, mime_type))
+}
+
+fn write_with_lofty(
+    path: &Path,
+    edit: &TagEdit,
+    cover: Option<Option<(Vec<u8>, MimeType)>>,
+) -> Result<(), String> {
+    let mut tagged_file = Probe::open(path)
+        .map_err(|e| e.to_string())?
+        .guess_file_type()
+        .map_err(|e| e.to_string())?
+        .read()
+        .map_err(|e| e.to_string())?;
+
+    let tag_type = tagged_file.primary_tag_type();
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or_else(|| "无法创建标签".to_string())?;
+
+    set_text(tag, ItemKey::TrackTitle, &edit.title);
+    set_text(tag, ItemKey::TrackArtist, &edit.artist);
+    set_text(tag, ItemKey::AlbumTitle, &edit.album);
+    set_text(tag, ItemKey::Genre, &edit.genre);
+    // ID3v2 的 USLT 对应 UnsyncLyrics，其他格式的歌词字段对应 Lyrics
+    let lyrics_key = if tag_type == TagType::Id3v2 {
+        ItemKey::UnsyncLyrics
+    } else {
+        ItemKey::Lyrics
+    };
+    set_text(tag, lyrics_key, &edit.lyrics);
+    match edit.track_number {
+        Some(0) => tag.remove_track(),
+        Some(track) => tag.set_track(track),
+        None => {}
+    }
+    if let Some(gain) = &edit.replay_gain {
+        for (key, value) in replay_gain_items(gain) {
+            let key = replay_gain_key(key);
+            match value {
+                Some(value) => {
+                    tag.insert_text(key, value);
+                }
+                None => tag.remove_key(key),
+            }
+        }
+    }
+    if let Some(cover) = cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        if let Some((data, mime_type)) = cover {
+            tag.push_picture(
+                Picture::unchecked(data)
+                    .pic_type(PictureType::CoverFront)
+                    .mime_type(mime_type)
+                    .build(),
+            );
+        }
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())
+}
+
+fn set_text(tag: &mut Tag, key: ItemKey, value: &Option<String>) {
+    match value.as_deref().map(str::trim) {
+        Some("") => tag.remove_key(key),
+        Some(text) => {
+            tag.insert_text(key, text.to_string());
+        }
+        None => {}
+    }
+}
+
+/// 重写 DSF 末尾的 ID3v2 标签，并更新文件头里的文件大小和元数据指针
+fn write_dsf(
+    path: &Path,
+    edit: &TagEdit,
+    cover: Option<Option<(Vec<u8>, MimeType)>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut header = [0u8; 28];
+    file.read_exact(&mut header)?;
+    let metadata_offset = u64::from_le_bytes(header[20..28].try_into()?);
+    let file_len = file.metadata()?.len();
+
+    let mut tag = if metadata_offset > 0 {
+        file.seek(SeekFrom::Start(metadata_offset))?;
+        id3::Tag::read_from2(&mut file).unwrap_or_default()
+    } else {
+        id3::Tag::new()
+    };
+    edit_id3(&mut tag, edit, cover);
+
+    let mut bytes = Vec::new();
+    tag.write_to(&mut bytes, id3::Version::Id3v24)?;
+    let data_end = if metadata_offset > 0 {
+        metadata_offset
+    } else {
+        file_len
+    };
+
+    file.set_len(data_end)?;
+    file.seek(SeekFrom::Start(data_end))?;
+    file.write_all(&bytes)?;
+    file.seek(SeekFrom::Start(12))?;
+    file.write_all(&(data_end + bytes.len() as u64).to_le_bytes())?;
+    file.write_all(&data_end.to_le_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn edit_id3(tag: &mut id3::Tag, edit: &TagEdit, cover: Option<Option<(Vec<u8>, MimeType)>>) {
+    fn text(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim)
+    }
+
+    match text(&edit.title) {
+        Some("") => tag.remove_title(),
+        Some(title) => tag.set_title(title),
+        None => {}
+    }
+    match text(&edit.artist) {
+        Some("") => tag.remove_artist(),
+        Some(artist) => tag.set_artist(artist),
+        None => {}
+    }
+    match text(&edit.album) {
+        Some("") => tag.remove_album(),
+        Some(album) => tag.set_album(album),
+        None => {}
+    }
+    match text(&edit.genre) {
+        Some("") => tag.remove_genre(),
+        Some(genre) => tag.set_genre(genre),
+        None => {}
+    }
+    match edit.track_number {
+        Some(0) => tag.remove_track(),
+        Some(track) => tag.set_track(track),
+        None => {}
+    }
+    if let Some(lyrics) = text(&edit.lyrics) {
+        tag.remove_all_lyrics();
+        if !lyrics.is_empty() {
+            tag.add_frame(id3::frame::Lyrics {
+                lang: "XXX".to_string(),
+                description: String::new(),
+                text: lyrics.to_string(),
+            });
+        }
+    }
+    if let Some(gain) = &edit.replay_gain {
+        for (key, value) in replay_gain_items(gain) {
+            tag.remove_extended_text(Some(key), None);
+            if let Some(value) = value {
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: key.to_string(),
+                    value,
+                });
+            }
+        }
+    }
+    if let Some(cover) = cover {
+        tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+        if let Some((data, mime_type)) = cover {
+            tag.add_frame(id3::frame::Picture {
+                mime_type: mime_type.as_str().to_string(),
+                picture_type: id3::frame::PictureType::CoverFront,
+                description: String::new(),
+                data,
+            });
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::commands::file_scan::file_key;
+use crate::decode::decode_file;
+use crate::models::{MusicFile, Waveform};
+use crate::recovery::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::UNIX_EPOCH;
+use tauri::AppHandle;
+use tauri::Manager;
+use tauri_helper::auto_collect_command;
+
+const WAVEFORM_CACHE_DIR: &str = "waveforms";
+const MIN_BUCKETS: usize = 16;
+const MAX_BUCKETS: usize = 4096;
+// 先按 10ms 一段统计峰值，最后再合并成请求的段数
+const SLICES_PER_SECOND: u32 = 100;
+
+/// 缓存文件里记录生成波形时音频文件的大小和修改时间，文件变了就重新生成
+#[derive(Serialize, Deserialize)]
+struct CachedWaveform {
+    size: u64,
+    mtime: u64,
+    waveform: Waveform,
+}
+
+/// 解码本地（或已下载的在线）歌曲，返回 buckets 段的波形峰值。
+/// 结果按歌曲 ID 和段数缓存在磁盘上，音频文件变化后自动失效
+#[tauri::command]
+#[auto_collect_command]
+pub async fn get_waveform(
+    app_handle: AppHandle,
+    song: MusicFile,
+    buckets: usize,
+) -> Result<Waveform, String> {
+    let path = PathBuf::from(file_key(&song));
+    if song.is_online == Some(true) || !path.is_file() {
+        return Err("只能为本地文件生成波形，在线歌曲需要先下载".to_string());
+    }
+    let buckets = buckets.clamp(MIN_BUCKETS, MAX_BUCKETS);
+    let cache_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(WAVEFORM_CACHE_DIR);
+
+    tokio::task::spawn_blocking(move || {
+        let (size, mtime) = file_stamp(&path)?;
+        // CUE 分轨共用一个文件，用歌曲 ID 区分；还没有 ID 的歌曲退回到路径
+        let identity = if song.id.is_empty() {
+            &song.path
+        } else {
+            &song.id
+        };
+        let hash = blake3::hash(identity.as_bytes()).to_hex();
+        let cached_path = cache_dir.join(format!("{}-{}.json", &hash[..32], buckets));
+
+        if let Some(cached) = fs::read_to_string(&cached_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<CachedWaveform>(&json).ok())
+            .filter(|c| c.size == size && c.mtime == mtime)
+        {
+            return Ok(cached.waveform);
+        }
+
+        let range = song
+            .source_path
+            .as_ref()
+            .map(|_| (song.start_time.unwrap_or(0.0), song.end_time));
+        let waveform = generate_waveform(&path, range, buckets)?;
+
+        fs::create_dir_all(&cache_dir).map_err(|e| format!("创建波形缓存目录失败: {}", e))?;
+        let cached = CachedWaveform {
+            size,
+            mtime,
+            waveform,
+        };
+        let json = serde_json::to_string(&cached).map_err(|e| e.to_string())?;
+        if let Err(e) = write_atomic(&cached_path, json.as_bytes()) {
+            println!("[波形] 写入缓存失败 {}: {}", cached_path.display(), e);
+        }
+        Ok(cached.waveform)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// 文件大小和修改时间（毫秒时间戳），用来判断缓存是否过期
+pub(crate) fn file_stamp(path: &Path) -> Result<(u64, u64), String> {
+    let meta = fs::metadata(path).map_err(|e| e.to_string())?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    Ok((meta.len(), mtime))
+}
+
+fn generate_waveform(
+    path: &Path,
+    range: Option<(f64, Option<f64>)>,
+    buckets: usize,
+) -> Result<Waveform, String> {
+    let mut slices: Vec<(f32, f32)> = Vec::new();
+    let mut current = (0.0f32, 0.0f32);
+    let mut frames_in_slice = 0u32;
+    let mut total_frames = 0u64;
+    let mut rate = 0u32;
+
+    let never_cancel = AtomicBool::new(false);
+    decode_file(
+        path,
+        range,
+        &never_cancel,
+        |sample_rate, channels, samples| {
+            rate = sample_rate;
+            let slice_frames = (sample_rate / SLICES_PER_SECOND).max(1);
+            for frame in samples.chunks_exact(channels) {
+                // 各声道合在一起取峰值
+                for &sample in frame {
+                    current.0 = current.0.min(sample);
+                    current.1 = current.1.max(sample);
+                }
+                frames_in_slice += 1;
+                if frames_in_slice == slice_frames {
+                    slices.push(current);
+                    current = (0.0, 0.0);
+                    frames_in_slice = 0;
+                }
+            }
+            total_frames += (samples.len() / channels) as u64;
+        },
+    )?;
+    if frames_in_slice > 0 {
+        slices.push(current);
+    }
+    if slices.is_empty() {
+        return Err("没有解码出音频".to_string());
+    }
+
+    let mut peaks = Vec::with_capacity(buckets * 2);
+    for i in 0..buckets {
+        let start = i * slices.len() / buckets;
+        let end = ((i + 1) * slices.len() / buckets).max(start + 1);
+        let (min, max) = slices[start..end.min(slices.len())]
+            .iter()
+            .fold((0.0f32, 0.0f32), |(lo, hi), &(a, b)| (lo.min(a), hi.max(b)));
+        peaks.push(scale(min));
+        peaks.push(scale(max));
+    }
+
+    Ok(Waveform {
+        buckets,
+        duration: total_frames as f64 / rate.max(1) as f64,
+        peaks,
+    })
+}
+
+fn scale(sample: f32) -> i8 {
+    (sample.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
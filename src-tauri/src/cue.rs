@@ -0,0 +1,545 @@
+use crate::commands::tags::{display_name, read_music_file};
+use crate::encoding::decode_text;
+use crate::models::MusicFile;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// CUE 的时间单位是帧，每秒 75 帧
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CueFile {
+    /// FILE 指令里写的文件名，通常是相对 .cue 所在目录的路径
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// INDEX 00（间隙开始），单位秒
+    pub pregap: Option<f64>,
+    /// INDEX 01（正式开始），单位秒
+    pub start: Option<f64>,
+    /// INDEX 01 写在下一个 FILE 之后、下一个 TRACK 之前时（EAC 把间隙接在上一轨末尾的多文件布局），
+    /// 这一轨在本文件里只有间隙，正式内容从下一个文件的这个时间开始
+    pub start_in_next_file: Option<f64>,
+}
+
+/// 读取并解析 .cue 文件，自动识别文本编码
+pub fn read_cue_sheet(path: &Path) -> std::io::Result<CueSheet> {
+    let bytes = fs::read(path)?;
+    Ok(parse_cue(&decode_text(&bytes)))
+}
+
+pub fn parse_cue(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+
+    for line in text.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => sheet.files.push(CueFile {
+                name: unquote(strip_file_type(rest)),
+                tracks: vec![],
+            }),
+            "TRACK" => {
+                if let Some(file) = sheet.files.last_mut() {
+                    let number = rest
+                        .split_whitespace()
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(file.tracks.len() as u32 + 1);
+                    file.tracks.push(CueTrack {
+                        number,
+                        ..Default::default()
+                    });
+                }
+            }
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                let index = parts.next().and_then(|n| n.parse::<u32>().ok());
+                let time = parts.next().and_then(parse_time);
+                if let Some(track) = current_track(&mut sheet) {
+                    match index {
+                        Some(0) => track.pregap = time,
+                        Some(1) => track.start = time,
+                        _ => {}
+                    }
+                } else if let Some(track) = previous_file_track(&mut sheet) {
+                    if index == Some(1) {
+                        track.start_in_next_file = time;
+                    }
+                }
+            }
+            "TITLE" => match current_track(&mut sheet) {
+                Some(track) => track.title = non_empty(unquote(rest)),
+                None => sheet.title = non_empty(unquote(rest)),
+            },
+            "PERFORMER" => match current_track(&mut sheet) {
+                Some(track) => track.performer = non_empty(unquote(rest)),
+                None => sheet.performer = non_empty(unquote(rest)),
+            },
+            "REM" => {
+                let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                match key.to_ascii_uppercase().as_str() {
+                    "GENRE" => sheet.genre = non_empty(unquote(value.trim())),
+                    "DATE" => sheet.date = non_empty(unquote(value.trim())),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    sheet
+}
+
+/// 扫描时用的 CUE 查找表。每个目录只在第一次遇到时列一遍、解析其中的 .cue，
+/// 记下每个被引用的音频文件对应哪个 .cue 的哪个 FILE，之后的音频文件直接查表
+#[derive(Default)]
+pub struct CueIndex {
+    dirs: HashMap<PathBuf, HashMap<PathBuf, CueRef>>,
+}
+
+struct CueRef {
+    cue_path: PathBuf,
+    sheet: Rc<CueSheet>,
+    file: usize,
+}
+
+impl CueIndex {
+    /// 查找引用这个音频文件的 .cue，找到时把整轨文件拆成多首虚拟歌曲。
+    /// 返回 .cue 的路径和拆分后的歌曲
+    pub fn tracks_for(&mut self, audio_path: &Path) -> Option<(PathBuf, Vec<MusicFile>)> {
+        let dir = audio_path.parent()?;
+        let refs = self
+            .dirs
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| directory_refs(dir));
+        let found = refs.get(audio_path)?;
+        let songs = split_tracks(audio_path, &found.sheet, found.file);
+        (!songs.is_empty()).then(|| (found.cue_path.clone(), songs))
+    }
+}
+
+/// 解析目录里所有的 .cue，按引用的音频文件建表；多个 .cue 引用同一个文件时以先找到的为准
+fn directory_refs(dir: &Path) -> HashMap<PathBuf, CueRef> {
+    let mut refs = HashMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return refs;
+    };
+    let listing: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    for cue_path in listing.iter().filter(|p| is_cue(p)) {
+        let Ok(sheet) = read_cue_sheet(cue_path) else {
+            continue;
+        };
+        let sheet = Rc::new(sheet);
+        for (file, entry) in sheet.files.iter().enumerate() {
+            if track_spans(&sheet, file).is_empty() {
+                continue;
+            }
+            let Some(audio) = resolve_in(dir, &listing, &entry.name) else {
+                continue;
+            };
+            refs.entry(audio).or_insert_with(|| CueRef {
+                cue_path: cue_path.clone(),
+                sheet: Rc::clone(&sheet),
+                file,
+            });
+        }
+    }
+    refs
+}
+
+/// .cue 引用的、实际存在的音频文件
+pub fn referenced_audio(cue_path: &Path) -> Vec<PathBuf> {
+    let Ok(sheet) = read_cue_sheet(cue_path) else {
+        return vec![];
+    };
+    sheet
+        .files
+        .iter()
+        .filter_map(|f| resolve_file(cue_path, &f.name))
+        .collect()
+}
+
+pub fn is_cue(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// 把 FILE 指令里的文件名解析成实际路径
+fn resolve_file(cue_path: &Path, name: &str) -> Option<PathBuf> {
+    let dir = cue_path.parent()?;
+    let listing: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    resolve_in(dir, &listing, name)
+}
+
+/// 在 .cue 所在目录（dir，listing 为其中的文件）里找 FILE 指令引用的文件。很多 .cue 是对 WAV 生成的，
+/// 之后音频转成了 FLAC/APE，所以文件名对不上时按同名（不含扩展名）再找一次
+fn resolve_in(dir: &Path, listing: &[PathBuf], name: &str) -> Option<PathBuf> {
+    let direct = dir.join(name.replace('\\', "/"));
+    if direct.is_file() {
+        return Some(direct);
+    }
+
+    let stem = Path::new(name)
+        .file_stem()?
+        .to_string_lossy()
+        .to_lowercase();
+    listing
+        .iter()
+        .find(|p| {
+            p.is_file()
+                && !is_cue(p)
+                && p.file_stem()
+                    .is_some_and(|s| s.to_string_lossy().to_lowercase() == stem)
+        })
+        .cloned()
+}
+
+/// 第 file 个 FILE 里的分轨及其开始、结束时间（秒，从这个文件开头算起，结束时间为 None 表示到文件末尾）。
+/// 上一个文件的最后一轨从这个文件开始时排在最前面；只在本文件留下间隙的轨道不算
+fn track_spans(sheet: &CueSheet, file: usize) -> Vec<(&CueTrack, f64, Option<f64>)> {
+    let tracks = &sheet.files[file].tracks;
+    // 下一轨的间隙属于上一轨，结束时间优先用下一轨的 INDEX 00
+    let end_before = |i: usize| tracks.get(i).and_then(|next| next.pregap.or(next.start));
+    let mut spans = Vec::new();
+    let carried = file
+        .checked_sub(1)
+        .and_then(|i| sheet.files[i].tracks.last())
+        .and_then(|track| Some((track, track.start_in_next_file?)));
+    if let Some((track, start)) = carried {
+        spans.push((track, start, end_before(0)));
+    }
+    for (i, track) in tracks.iter().enumerate() {
+        if track.start.is_none() && track.start_in_next_file.is_some() {
+            continue;
+        }
+        let start = track.start.or(track.pregap).unwrap_or(0.0);
+        spans.push((track, start, end_before(i + 1)));
+    }
+    spans
+}
+
+fn split_tracks(audio_path: &Path, sheet: &CueSheet, file: usize) -> Vec<MusicFile> {
+    let Some(whole) = read_music_file(audio_path) else {
+        return vec![];
+    };
+    let source = audio_path.to_string_lossy().to_string();
+    let year = sheet
+        .date
+        .as_deref()
+        .and_then(|d| d.get(..4))
+        .and_then(|y| y.parse().ok());
+
+    track_spans(sheet, file)
+        .into_iter()
+        .map(|(track, start, end)| {
+            let duration = end.or(whole.duration).map(|end| end - start);
+
+            let mut song = MusicFile {
+                path: format!("{}#{:02}", source, track.number),
+                source_path: Some(source.clone()),
+                start_time: Some(start),
+                end_time: end,
+                title: Some(
+                    track
+                        .title
+                        .clone()
+                        .unwrap_or_else(|| format!("Track {:02}", track.number)),
+                ),
+                artist: track
+                    .performer
+                    .clone()
+                    .or_else(|| sheet.performer.clone())
+                    .or_else(|| whole.artist.clone()),
+                album: sheet.title.clone().or_else(|| whole.album.clone()),
+                album_artist: sheet
+                    .performer
+                    .clone()
+                    .or_else(|| whole.album_artist.clone()),
+                compilation: whole.compilation,
+                track_number: Some(track.number),
+                disc_number: whole.disc_number,
+                year: year.or(whole.year),
+                genre: sheet.genre.clone().or_else(|| whole.genre.clone()),
+                duration: duration.filter(|d| *d > 0.0),
+                codec: whole.codec.clone(),
+                ..Default::default()
+            };
+            song.name = display_name(&song);
+            song
+        })
+        .collect()
+}
+
+fn current_track(sheet: &mut CueSheet) -> Option<&mut CueTrack> {
+    sheet.files.last_mut()?.tracks.last_mut()
+}
+
+/// 当前 FILE 还没有 TRACK 时，上一个 FILE 的最后一轨
+fn previous_file_track(sheet: &mut CueSheet) -> Option<&mut CueTrack> {
+    let count = sheet.files.len();
+    sheet
+        .files
+        .get_mut(count.checked_sub(2)?)?
+        .tracks
+        .last_mut()
+}
+
+/// "mm:ss:ff" 转换为秒
+fn parse_time(value: &str) -> Option<f64> {
+    let mut parts = value.split(':').map(|p| p.parse::<u32>().ok());
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = parts.next()??;
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+/// 去掉 FILE 指令末尾的类型（WAVE / MP3 / AIFF / BINARY）
+fn strip_file_type(value: &str) -> &str {
+    match value.rsplit_once(char::is_whitespace) {
+        Some((name, kind)) if !kind.contains('"') => name.trim(),
+        _ => value,
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches('"').to_string()
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.trim().is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 编号、标题、演唱者、INDEX 00、INDEX 01
+    type TrackSummary<'a> = (
+        u32,
+        Option<&'a str>,
+        Option<&'a str>,
+        Option<f64>,
+        Option<f64>,
+    );
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/cue")
+            .join(name)
+    }
+
+    fn time(minutes: u32, seconds: u32, frames: u32) -> Option<f64> {
+        Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+    }
+
+    fn tracks(file: &CueFile) -> Vec<TrackSummary<'_>> {
+        file.tracks
+            .iter()
+            .map(|t| {
+                (
+                    t.number,
+                    t.title.as_deref(),
+                    t.performer.as_deref(),
+                    t.pregap,
+                    t.start,
+                )
+            })
+            .collect()
+    }
+
+    /// 分轨编号、开始和结束时间
+    fn spans(sheet: &CueSheet, file: usize) -> Vec<(u32, f64, Option<f64>)> {
+        track_spans(sheet, file)
+            .into_iter()
+            .map(|(track, start, end)| (track.number, start, end))
+            .collect()
+    }
+
+    #[test]
+    fn parses_index_times() {
+        let cases = [
+            ("00:00:00", Some(0.0)),
+            ("00:01:00", Some(1.0)),
+            ("00:00:75", Some(1.0)),
+            ("03:25:30", time(3, 25, 30)),
+            ("74:59:74", time(74, 59, 74)),
+            // 分钟可以超过两位数（长于 99 分钟的整轨）
+            ("120:00:00", Some(7200.0)),
+            ("03:25", None),
+            ("03:25:xx", None),
+            ("-1:00:00", None),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_time(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parses_file_names() {
+        let cases = [
+            (r#"FILE "CD1 - Symphony.wav" WAVE"#, "CD1 - Symphony.wav"),
+            ("FILE CD2.flac WAVE", "CD2.flac"),
+            (r#"FILE "no type.ape""#, "no type.ape"),
+            (r#"file "Disc 1\album.wav" binary"#, r"Disc 1\album.wav"),
+        ];
+        for (line, expected) in cases {
+            let sheet = parse_cue(line);
+            assert_eq!(sheet.files.len(), 1, "{:?}", line);
+            assert_eq!(sheet.files[0].name, expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn parses_multi_file_sheet() {
+        let sheet = read_cue_sheet(&fixture("multi_file.cue")).unwrap();
+        assert_eq!(sheet.title.as_deref(), Some("Beethoven: Symphonies 5 & 7"));
+        assert_eq!(sheet.performer.as_deref(), Some("Berliner Philharmoniker"));
+        assert_eq!(sheet.genre.as_deref(), Some("Classical"));
+        assert_eq!(sheet.date.as_deref(), Some("1999/05"));
+
+        let names: Vec<&str> = sheet.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["CD1 - Symphony No. 5.wav", "CD2.flac"]);
+        // 每个 FILE 的时间都从该文件的开头算起，TRACK 编号跨文件连续
+        assert_eq!(
+            tracks(&sheet.files[0]),
+            [
+                (1, Some("I. Allegro con brio"), None, None, time(0, 0, 0)),
+                (
+                    2,
+                    Some("II. Andante con moto"),
+                    Some("Herbert von Karajan"),
+                    time(7, 31, 20),
+                    time(7, 33, 0)
+                ),
+            ]
+        );
+        assert_eq!(
+            tracks(&sheet.files[1]),
+            [
+                (
+                    3,
+                    Some("III. Poco sostenuto - Vivace"),
+                    None,
+                    None,
+                    time(0, 0, 0)
+                ),
+                (4, None, None, None, time(13, 5, 74)),
+            ]
+        );
+    }
+
+    /// EAC 把间隙接在上一轨末尾的多文件布局：INDEX 01 写在新 FILE 之后、下一个 TRACK 之前
+    #[test]
+    fn parses_gaps_appended_to_previous_track() {
+        let sheet = read_cue_sheet(&fixture("gaps_appended.cue")).unwrap();
+        assert_eq!(sheet.files.len(), 3);
+        assert_eq!(
+            tracks(&sheet.files[0]),
+            [
+                (1, Some("Airbag"), None, None, time(0, 0, 0)),
+                (2, Some("Paranoid Android"), None, time(4, 44, 20), None),
+            ]
+        );
+        assert_eq!(sheet.files[0].tracks[1].start_in_next_file, time(0, 0, 0));
+        assert_eq!(
+            tracks(&sheet.files[1]),
+            [(
+                3,
+                Some("Subterranean Homesick Alien"),
+                None,
+                time(6, 23, 10),
+                None
+            )]
+        );
+        assert!(sheet.files[2].tracks.is_empty());
+
+        // 每一轨都落在自己的文件里，间隙留在上一个文件末尾
+        let cases = [
+            (0, vec![(1, 0.0, time(4, 44, 20))]),
+            (1, vec![(2, 0.0, time(6, 23, 10))]),
+            (2, vec![(3, 0.0, None)]),
+        ];
+        for (file, expected) in cases {
+            assert_eq!(spans(&sheet, file), expected, "FILE {}", file);
+        }
+    }
+
+    #[test]
+    fn splits_tracks_within_one_file() {
+        let sheet = read_cue_sheet(&fixture("multi_file.cue")).unwrap();
+        let cases = [
+            (0, vec![(1, 0.0, time(7, 31, 20)), (2, 453.0, None)]),
+            (
+                1,
+                vec![
+                    (3, 0.0, time(13, 5, 74)),
+                    (4, time(13, 5, 74).unwrap(), None),
+                ],
+            ),
+        ];
+        for (file, expected) in cases {
+            assert_eq!(spans(&sheet, file), expected, "FILE {}", file);
+        }
+    }
+
+    #[test]
+    fn decodes_legacy_encodings() {
+        let cases = [
+            (
+                "gbk.cue",
+                "叶惠美",
+                "周杰伦",
+                "周杰伦 - 叶惠美.wav",
+                ["以父之名", "懦夫", "晴天"],
+            ),
+            (
+                "shift_jis.cue",
+                "初恋",
+                "宇多田ヒカル",
+                "宇多田ヒカル - 初恋.wav",
+                ["あなた", "初恋", "誓い"],
+            ),
+        ];
+        for (name, title, performer, file, titles) in cases {
+            let sheet = read_cue_sheet(&fixture(name)).unwrap();
+            assert_eq!(sheet.title.as_deref(), Some(title), "{}", name);
+            assert_eq!(sheet.performer.as_deref(), Some(performer), "{}", name);
+            assert_eq!(sheet.files.len(), 1, "{}", name);
+            assert_eq!(sheet.files[0].name, file, "{}", name);
+            let track_titles: Vec<&str> = sheet.files[0]
+                .tracks
+                .iter()
+                .filter_map(|t| t.title.as_deref())
+                .collect();
+            assert_eq!(track_titles, titles, "{}", name);
+        }
+    }
+}
@@ -1,107 +1,132 @@
-use crate::models::{MusicFile, Playlist};
-use std::fs;
+use crate::models::{MusicFile, Playlist, ScanDiff};
+use crate::song_id::ensure_song_ids;
+use crate::store::{self, with_db};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use tauri::AppHandle;
-use tauri::Manager;
 use tauri_helper::auto_collect_command;
 
-const LIBRARY_FILE: &str = "local_library.json";
-const PLAYQUEUE_FILE: &str = "play_queue.json";
-const PLAYLISTS_FILE: &str = "playlists.json";
-const SETTINGS_FILE: &str = "settings.json";
+/// 系统歌单「本地音乐」，只是曲库的入口，本身不保存歌曲
+pub(crate) const LOCAL_PLAYLIST_ID: &str = "local";
+/// 系统歌单「我喜欢的音乐」
+pub(crate) const FAVORITES_PLAYLIST_ID: &str = "favorites";
 
-const OLD_LIBRARY_FILE: &str = "library.json";
-const OLD_PLAYLIST_FILE: &str = "playlist.json";
-
-fn migrate_old_data(app_handle: &AppHandle) -> Result<(), String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct AppSettings {
+    pub download_folder: Option<String>,
+    /// 曲库根目录，扫描、监听都以这里为准
+    #[serde(default)]
+    pub library_roots: Vec<LibraryRoot>,
+    /// 标签缺失时按顺序尝试的文件名规则，如 `{artist} - {title}`
+    #[serde(default)]
+    pub filename_patterns: Vec<String>,
+}
 
-    if !app_dir.exists() {
-        let _ = fs::create_dir_all(&app_dir);
-    }
+/// 一个曲库根目录及其扫描规则
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct LibraryRoot {
+    pub path: String,
+    /// 相对根目录的 glob，非空时只收录匹配的文件
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// 相对根目录的 glob，如 `**/Samples/**`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// 小于这个大小（字节）的文件不收录
+    #[serde(default)]
+    pub min_file_size: Option<u64>,
+    /// 短于这个时长（秒）的歌曲不收录
+    #[serde(default)]
+    pub min_duration: Option<f64>,
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    pub include_hidden: bool,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+}
 
-    // 迁移 library.json -> local_library.json
-    let old_library_path = app_dir.join(OLD_LIBRARY_FILE);
-    let new_library_path = app_dir.join(LIBRARY_FILE);
-    if old_library_path.exists() && !new_library_path.exists() {
-        if let Ok(content) = fs::read_to_string(&old_library_path) {
-            if !content.trim().is_empty() {
-                fs::write(&new_library_path, &content).map_err(|e| e.to_string())?;
-                println!("[迁移] library.json -> local_library.json 完成");
-            }
+impl LibraryRoot {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Default::default()
         }
     }
+}
 
-    // 迁移 playlist.json -> play_queue.json
-    let old_playlist_path = app_dir.join(OLD_PLAYLIST_FILE);
-    let new_playlist_path = app_dir.join(PLAYQUEUE_FILE);
-    if old_playlist_path.exists() && !new_playlist_path.exists() {
-        if let Ok(content) = fs::read_to_string(&old_playlist_path) {
-            if !content.trim().is_empty() {
-                fs::write(&new_playlist_path, &content).map_err(|e| e.to_string())?;
-                println!("[迁移] playlist.json -> play_queue.json 完成");
-            }
-        }
-    }
+/// 增量扫描用的文件索引，记录每个文件上次扫描时的大小、修改时间和标签哈希
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub struct LibraryIndex {
+    /// 以文件路径为键
+    pub entries: HashMap<String, IndexEntry>,
+    /// 已导入的播放列表文件及导入时的修改时间，文件没变时不再重复导入
+    #[serde(default)]
+    pub playlist_files: HashMap<String, u64>,
+}
 
-    Ok(())
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IndexEntry {
+    pub size: u64,
+    /// 修改时间（毫秒时间戳）
+    pub mtime: u64,
+    pub tag_hash: String,
+    /// 文件被 .cue 拆分时记录 .cue 的路径和修改时间，.cue 变化时需要重新拆分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cue_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cue_mtime: Option<u64>,
+    /// 文件内容指纹，文件改名或移动后据此沿用原来的歌曲 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// 读取文件时的解析版本，低于当前版本的记录下次扫描时会重新读取（比如新增了读取的字段）
+    #[serde(default)]
+    pub reader_version: u32,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
-pub struct AppSettings {
-    pub download_folder: Option<String>,
+/// 响度分析时顺带计算的声学指纹，以歌曲 ID 为键存在单独的表里，不放进歌曲数据以免曲库膨胀
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StoredFingerprint {
+    /// 计算指纹时音频文件的大小和修改时间（毫秒时间戳），文件变化后指纹失效
+    pub size: u64,
+    pub mtime: u64,
+    /// 十六进制编码的子指纹序列，见 acoustic::encode
+    pub fingerprint: String,
 }
 
-fn save_json_file<T: serde::Serialize>(
-    app_handle: &AppHandle,
-    filename: &str,
-    data: &T,
-) -> Result<(), String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
-    if !app_dir.exists() {
-        let _ = fs::create_dir_all(&app_dir);
-    }
-    let path = app_dir.join(filename);
-    let json = serde_json::to_string_pretty(data).map_err(|e| e.to_string())?;
-    fs::write(path, json).map_err(|e| e.to_string())?;
-    Ok(())
+/// 曲库、播放队列、歌单和文件索引在同一时刻的快照，供需要一起检查这几项的操作（如检查丢失的文件）读取
+pub(crate) struct AppData {
+    pub library: Vec<MusicFile>,
+    pub queue: Vec<MusicFile>,
+    pub playlists: Vec<Playlist>,
+    pub index: LibraryIndex,
 }
 
-fn load_json_file<T: serde::de::DeserializeOwned>(
-    app_handle: &AppHandle,
-    filename: &str,
-) -> Result<T, String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
-    let path = app_dir.join(filename);
-    if !path.exists() {
-        return Err(format!("文件不存在: {}", filename));
-    }
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    if content.trim().is_empty() {
-        return Err(format!("文件为空: {}", filename));
-    }
-    let data: T = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+/// 读取失败时返回错误而不是空数据，免得调用方当作曲库为空处理
+pub(crate) fn read_app_data(app_handle: &AppHandle) -> Result<AppData, String> {
+    let mut data = with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        Ok(AppData {
+            library: store::read_songs(&tx)?,
+            queue: store::read_queue(&tx)?,
+            playlists: store::read_playlists(&tx)?,
+            index: store::read_index(&tx)?,
+        })
+    })?;
+    ensure_system_playlists(&mut data.playlists);
     Ok(data)
 }
 
-fn ensure_system_playlists(playlists: &mut Vec<Playlist>) {
-    let has_local = playlists.iter().any(|p| p.id == "local");
-    let has_favorites = playlists.iter().any(|p| p.id == "favorites");
+/// 补上缺少的系统歌单：「本地音乐」排第一，「我喜欢的音乐」排第二
+pub(crate) fn ensure_system_playlists(playlists: &mut Vec<Playlist>) {
+    let has_local = playlists.iter().any(|p| p.id == LOCAL_PLAYLIST_ID);
+    let has_favorites = playlists.iter().any(|p| p.id == FAVORITES_PLAYLIST_ID);
 
     if !has_local {
         playlists.insert(
             0,
             Playlist {
-                id: "local".to_string(),
+                id: LOCAL_PLAYLIST_ID.to_string(),
                 name: "本地音乐".to_string(),
                 songs: vec![],
                 is_system: Some(true),
@@ -113,7 +138,7 @@ fn ensure_system_playlists(playlists: &mut Vec<Playlist>) {
         playlists.insert(
             1,
             Playlist {
-                id: "favorites".to_string(),
+                id: FAVORITES_PLAYLIST_ID.to_string(),
                 name: "我喜欢的音乐".to_string(),
                 songs: vec![],
                 is_system: Some(true),
@@ -122,68 +147,189 @@ fn ensure_system_playlists(playlists: &mut Vec<Playlist>) {
     }
 }
 
-#[tauri::command]
-#[auto_collect_command]
-pub fn save_to_library(app_handle: AppHandle, songs: Vec<MusicFile>) -> Result<(), String> {
-    // 增量追加而不是覆盖
-    let existing: Vec<MusicFile> = load_json_file(&app_handle, LIBRARY_FILE).unwrap_or_default();
-
-    let mut merged = existing;
-    for song in songs {
-        if !merged.iter().any(|s| s.path == song.path) {
-            merged.push(song);
+/// 和 read_app_data 一样，读取失败时返回错误而不是空数据
+pub(crate) fn read_library(app_handle: &AppHandle) -> Result<Vec<MusicFile>, String> {
+    with_db(app_handle, |conn| store::read_songs(conn))
+}
+
+pub(crate) fn load_library_index(app_handle: &AppHandle) -> Result<LibraryIndex, String> {
+    with_db(app_handle, |conn| store::read_index(conn))
+}
+
+pub(crate) fn save_library_index(
+    app_handle: &AppHandle,
+    index: &LibraryIndex,
+) -> Result<(), String> {
+    with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::write_index(&tx, index)?;
+        tx.commit()
+    })
+}
+
+/// 保存一次扫描的结果：曲库只改 diff 里的歌曲，索引只写 before 之后有变化的记录，
+/// 两者在同一个事务里。扫描耗时较长，这样不会覆盖期间其他操作（改标签、移除歌曲等）的修改
+pub(crate) fn save_scan_result(
+    app_handle: &AppHandle,
+    diff: &ScanDiff,
+    before: &LibraryIndex,
+    index: &LibraryIndex,
+) -> Result<(), String> {
+    with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::apply_scan_diff(&tx, diff)?;
+        store::write_index_changes(&tx, before, index)?;
+        tx.commit()
+    })
+}
+
+pub(crate) fn load_acoustic_fingerprints(
+    app_handle: &AppHandle,
+) -> Result<HashMap<String, StoredFingerprint>, String> {
+    with_db(app_handle, |conn| store::read_fingerprints(conn))
+}
+
+/// 写入新算出的指纹，同时删除已不在曲库里的歌曲的指纹
+pub(crate) fn save_acoustic_fingerprints(
+    app_handle: &AppHandle,
+    fingerprints: &HashMap<String, StoredFingerprint>,
+) -> Result<(), String> {
+    with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::upsert_fingerprints(&tx, fingerprints)?;
+        store::delete_orphan_fingerprints(&tx)?;
+        tx.commit()
+    })
+}
+
+/// 播放队列、歌单里保存的是歌曲的副本，文件改名或标签更新后会过时，
+/// 加载时按 ID 换成曲库里的最新版本
+fn sync_with_library(songs: &mut [MusicFile], library: &[MusicFile]) {
+    let by_id: HashMap<&str, &MusicFile> = library
+        .iter()
+        .filter(|s| !s.id.is_empty())
+        .map(|s| (s.id.as_str(), s))
+        .collect();
+    for song in songs.iter_mut() {
+        if let Some(current) = by_id.get(song.id.as_str()) {
+            *song = (*current).clone();
         }
     }
+}
 
-    save_json_file(&app_handle, LIBRARY_FILE, &merged)
+#[tauri::command]
+#[auto_collect_command]
+pub fn save_to_library(app_handle: AppHandle, mut songs: Vec<MusicFile>) -> Result<(), String> {
+    // 增量追加而不是覆盖；ID 已存在时更新那一条（如在线歌曲下载成了本地文件）
+    ensure_song_ids(&mut songs, &read_library(&app_handle)?);
+    with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::merge_songs(&tx, &songs)?;
+        tx.commit()
+    })
 }
 
 #[tauri::command]
 #[auto_collect_command]
 pub fn load_library(app_handle: AppHandle) -> Result<Vec<MusicFile>, String> {
-    migrate_old_data(&app_handle)?;
-    load_json_file(&app_handle, LIBRARY_FILE).or_else(|_| Ok(vec![]))
+    with_db(&app_handle, |conn| store::read_songs(conn))
 }
 
+/// 返回保存后的播放队列：没有 ID 的歌曲在这里分配 ID，前端要换成返回的歌曲，
+/// 否则下次保存又会分配一个新的
 #[tauri::command]
 #[auto_collect_command]
-pub fn save_play_queue(app_handle: AppHandle, songs: Vec<MusicFile>) -> Result<(), String> {
-    save_json_file(&app_handle, PLAYQUEUE_FILE, &songs)
+pub fn save_play_queue(
+    app_handle: AppHandle,
+    mut songs: Vec<MusicFile>,
+) -> Result<Vec<MusicFile>, String> {
+    ensure_song_ids(&mut songs, &read_library(&app_handle)?);
+    with_db(&app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::write_queue(&tx, &songs)?;
+        tx.commit()
+    })?;
+    Ok(songs)
 }
 
 #[tauri::command]
 #[auto_collect_command]
 pub fn load_play_queue(app_handle: AppHandle) -> Result<Vec<MusicFile>, String> {
-    migrate_old_data(&app_handle)?;
-    load_json_file(&app_handle, PLAYQUEUE_FILE).or_else(|_| Ok(vec![]))
+    let (mut songs, library) = with_db(&app_handle, |conn| {
+        Ok((store::read_queue(conn)?, store::read_songs(conn)?))
+    })?;
+    sync_with_library(&mut songs, &library);
+    Ok(songs)
+}
+
+pub(crate) fn read_playlists(app_handle: &AppHandle) -> Result<Vec<Playlist>, String> {
+    let mut playlists = with_db(app_handle, |conn| store::read_playlists(conn))?;
+    ensure_system_playlists(&mut playlists);
+    Ok(playlists)
+}
+
+pub(crate) fn write_playlists(
+    app_handle: &AppHandle,
+    playlists: &[Playlist],
+) -> Result<(), String> {
+    with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::write_playlists(&tx, playlists)?;
+        tx.commit()
+    })
 }
 
+/// 与 save_play_queue 一样返回分配过 ID 的歌单
 #[tauri::command]
 #[auto_collect_command]
-pub fn save_playlists(app_handle: AppHandle, mut playlists: Vec<Playlist>) -> Result<(), String> {
+pub fn save_playlists(
+    app_handle: AppHandle,
+    mut playlists: Vec<Playlist>,
+) -> Result<Vec<Playlist>, String> {
     ensure_system_playlists(&mut playlists);
-    save_json_file(&app_handle, PLAYLISTS_FILE, &playlists)
+    let library = read_library(&app_handle)?;
+    for playlist in &mut playlists {
+        ensure_song_ids(&mut playlist.songs, &library);
+    }
+    write_playlists(&app_handle, &playlists)?;
+    Ok(playlists)
 }
 
 #[tauri::command]
 #[auto_collect_command]
 pub fn load_playlists(app_handle: AppHandle) -> Result<Vec<Playlist>, String> {
-    let mut playlists: Vec<Playlist> =
-        load_json_file(&app_handle, PLAYLISTS_FILE).unwrap_or_default();
+    let (mut playlists, library) = with_db(&app_handle, |conn| {
+        Ok((store::read_playlists(conn)?, store::read_songs(conn)?))
+    })?;
     ensure_system_playlists(&mut playlists);
+    for playlist in &mut playlists {
+        sync_with_library(&mut playlist.songs, &library);
+    }
     Ok(playlists)
 }
 
+pub(crate) fn read_settings(app_handle: &AppHandle) -> Result<AppSettings, String> {
+    with_db(app_handle, |conn| store::read_settings(conn))
+}
+
+pub(crate) fn write_settings(app_handle: &AppHandle, settings: &AppSettings) -> Result<(), String> {
+    with_db(app_handle, |conn| {
+        let tx = conn.transaction()?;
+        store::write_settings(&tx, settings)?;
+        tx.commit()
+    })
+}
+
 #[tauri::command]
 #[auto_collect_command]
 pub fn save_settings(app_handle: AppHandle, settings: AppSettings) -> Result<(), String> {
-    save_json_file(&app_handle, SETTINGS_FILE, &settings)
+    write_settings(&app_handle, &settings)
 }
 
 #[tauri::command]
 #[auto_collect_command]
 pub fn load_settings(app_handle: AppHandle) -> Result<AppSettings, String> {
-    load_json_file(&app_handle, SETTINGS_FILE).or_else(|_| Ok(AppSettings::default()))
+    with_db(&app_handle, |conn| store::read_settings(conn))
 }
 
 #[tauri::command]
@@ -0,0 +1,112 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+/// 用 symphonia 解码音频文件（或其中 start..end 秒的一段，用于 CUE 分轨），
+/// 把交错排列的 f32 采样连同采样率、声道数依次交给 sink。cancel 被置位时尽快返回错误
+pub fn decode_file(
+    path: &Path,
+    range: Option<(f64, Option<f64>)>,
+    cancel: &AtomicBool,
+    mut sink: impl FnMut(u32, usize, &[f32]),
+) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension() {
+        hint.with_extension(&ext.to_string_lossy());
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("无法解码: {}", e))?;
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| "没有音轨".to_string())?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| "未知的采样率".to_string())?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("无法解码: {}", e))?;
+
+    // CUE 分轨：跳到起点，精确定位时多解出来的帧丢掉
+    let mut skip_frames = 0u64;
+    let mut remaining_frames = u64::MAX;
+    if let Some((start, end)) = range {
+        if start > 0.0 {
+            let seeked = format
+                .seek(
+                    SeekMode::Accurate,
+                    SeekTo::Time {
+                        time: Time::from(start),
+                        track_id: Some(track_id),
+                    },
+                )
+                .map_err(|e| format!("定位失败: {}", e))?;
+            skip_frames = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        }
+        if let Some(end) = end {
+            remaining_frames = ((end - start).max(0.0) * sample_rate as f64) as u64;
+        }
+    }
+
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    while remaining_frames > 0 {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 个别损坏的帧跳过，和播放器的处理一致
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+        let buffer = match &mut samples {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+            _ => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        let mut interleaved = buffer.samples();
+
+        let frames = (interleaved.len() / channels) as u64;
+        let skipped = skip_frames.min(frames);
+        skip_frames -= skipped;
+        let taken = (frames - skipped).min(remaining_frames);
+        remaining_frames -= taken;
+        interleaved =
+            &interleaved[skipped as usize * channels..(skipped + taken) as usize * channels];
+
+        sink(spec.rate, channels, interleaved);
+    }
+    Ok(())
+}
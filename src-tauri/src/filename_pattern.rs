@@ -0,0 +1,365 @@
+use crate::models::MusicFile;
+use regex::Regex;
+use std::path::Path;
+
+/// 从文件名和上层目录推断标签的规则，如 `{artist}/{album}/{track}. {title}`、`{artist} - {title}`。
+/// `/` 分隔目录层级，从路径末尾开始对齐，最后一段对应不含扩展名的文件名；
+/// `{*}` 匹配任意内容但不使用
+#[derive(Debug, Clone)]
+pub struct FilenamePattern {
+    pub source: String,
+    regex: Regex,
+    fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Track,
+    Disc,
+    Year,
+    Genre,
+    Ignore,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "albumartist" => Field::AlbumArtist,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "year" => Field::Year,
+            "genre" => Field::Genre,
+            "*" => Field::Ignore,
+            _ => return None,
+        })
+    }
+
+    /// 数字字段只匹配数字，避免 "03. 夜曲" 里的 "03." 被当成标题的一部分
+    fn regex(self) -> &'static str {
+        match self {
+            Field::Track | Field::Disc => r"(\d{1,3})",
+            Field::Year => r"(\d{4})",
+            _ => r"([^/\\]+?)",
+        }
+    }
+}
+
+/// 按规则推断出的标签，只包含规则里出现的字段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InferredTags {
+    pub pattern: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+}
+
+impl FilenamePattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("无效的文件名规则 {}: {}", pattern, reason);
+        let mut regex = String::from(r"(?:^|[/\\])");
+        let mut fields = Vec::new();
+        let mut after_field = false;
+        let mut rest = pattern.trim();
+        if rest.is_empty() {
+            return Err(invalid("规则为空"));
+        }
+
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(start) => {
+                    push_literal(&mut regex, &rest[..start]);
+                    // 两个字段紧挨着时无法确定分界
+                    if after_field && start == 0 {
+                        return Err(invalid("字段之间需要分隔符"));
+                    }
+                    let end = rest[start..]
+                        .find('}')
+                        .map(|i| start + i)
+                        .ok_or_else(|| invalid("缺少 }"))?;
+                    let name = &rest[start + 1..end];
+                    let field = Field::parse(name)
+                        .ok_or_else(|| invalid(&format!("未知字段 {{{}}}", name)))?;
+                    regex.push_str(field.regex());
+                    fields.push(field);
+                    after_field = true;
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    push_literal(&mut regex, rest);
+                    rest = "";
+                }
+            }
+        }
+        if fields.iter().all(|f| *f == Field::Ignore) {
+            return Err(invalid("至少需要一个字段"));
+        }
+        regex.push('$');
+
+        Ok(Self {
+            source: pattern.trim().to_string(),
+            regex: Regex::new(&regex).map_err(|e| invalid(&e.to_string()))?,
+            fields,
+        })
+    }
+
+    /// 用这条规则匹配路径，匹配不上时返回 None
+    pub fn infer(&self, path: &Path) -> Option<InferredTags> {
+        let stem = path.with_extension("");
+        let captures = self.regex.captures(stem.to_str()?)?;
+        let mut tags = InferredTags {
+            pattern: self.source.clone(),
+            ..Default::default()
+        };
+        for (i, field) in self.fields.iter().enumerate() {
+            let Some(value) = captures.get(i + 1).map(|m| m.as_str().trim()) else {
+                continue;
+            };
+            if value.is_empty() {
+                return None;
+            }
+            let text = Some(value.to_string());
+            match field {
+                Field::Title => tags.title = text,
+                Field::Artist => tags.artist = text,
+                Field::Album => tags.album = text,
+                Field::AlbumArtist => tags.album_artist = text,
+                Field::Genre => tags.genre = text,
+                Field::Track => tags.track_number = value.parse().ok(),
+                Field::Disc => tags.disc_number = value.parse().ok(),
+                Field::Year => tags.year = value.parse().ok(),
+                Field::Ignore => {}
+            }
+        }
+        Some(tags)
+    }
+}
+
+/// 空格两侧可以有多个空格或没有空格，其余字符原样匹配；规则里的 `/` 同时匹配 `\`
+fn push_literal(regex: &mut String, literal: &str) {
+    for (i, part) in literal.split(' ').enumerate() {
+        if i > 0 && !regex.ends_with(r"\s*") {
+            regex.push_str(r"\s*");
+        }
+        for c in part.chars() {
+            if c == '/' || c == '\\' {
+                regex.push_str(r"[/\\]");
+            } else {
+                regex.push_str(&regex::escape(&c.to_string()));
+            }
+        }
+    }
+}
+
+pub fn compile_patterns(patterns: &[String]) -> Result<Vec<FilenamePattern>, String> {
+    patterns
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| FilenamePattern::parse(p))
+        .collect()
+}
+
+/// 按顺序尝试每条规则，返回第一条匹配的结果
+pub fn infer_from_path(path: &Path, patterns: &[FilenamePattern]) -> Option<InferredTags> {
+    patterns.iter().find_map(|p| p.infer(path))
+}
+
+impl InferredTags {
+    /// 只填充标签里缺失的字段，文件自带的标签优先。返回是否有字段被填充
+    pub fn fill_missing(&self, music: &mut MusicFile) -> bool {
+        fn fill<T: Clone>(target: &mut Option<T>, value: &Option<T>) -> bool {
+            if target.is_none() && value.is_some() {
+                *target = value.clone();
+                true
+            } else {
+                false
+            }
+        }
+
+        let mut changed = fill(&mut music.title, &self.title);
+        changed |= fill(&mut music.artist, &self.artist);
+        changed |= fill(&mut music.album, &self.album);
+        changed |= fill(&mut music.album_artist, &self.album_artist);
+        changed |= fill(&mut music.track_number, &self.track_number);
+        changed |= fill(&mut music.disc_number, &self.disc_number);
+        changed |= fill(&mut music.year, &self.year);
+        changed |= fill(&mut music.genre, &self.genre);
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let cases = [
+            ("", "规则为空"),
+            ("   ", "规则为空"),
+            ("{artist}{title}", "字段之间需要分隔符"),
+            ("{artist} - {title", "缺少 }"),
+            ("{artist} - {name}", "未知字段 {name}"),
+            ("{*} - 固定文字", "至少需要一个字段"),
+            ("没有字段", "至少需要一个字段"),
+        ];
+        for (pattern, reason) in cases {
+            let error = FilenamePattern::parse(pattern).unwrap_err();
+            assert!(error.ends_with(reason), "{:?}: {}", pattern, error);
+        }
+    }
+
+    #[test]
+    fn infers_tags_from_path() {
+        let cases = [
+            (
+                "{artist} - {title}",
+                "/music/周杰伦 - 夜曲.mp3",
+                Some(InferredTags {
+                    artist: text("周杰伦"),
+                    title: text("夜曲"),
+                    ..Default::default()
+                }),
+            ),
+            // 分隔符两侧的空格可有可无
+            (
+                "{artist} - {title}",
+                "/music/Artist-Title.flac",
+                Some(InferredTags {
+                    artist: text("Artist"),
+                    title: text("Title"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "{track}. {title}",
+                "/music/03. 夜曲.flac",
+                Some(InferredTags {
+                    track_number: Some(3),
+                    title: text("夜曲"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "{artist}/{album}/{disc}-{track} {title}",
+                "/music/周杰伦/十一月的萧邦/1-03 夜曲.flac",
+                Some(InferredTags {
+                    artist: text("周杰伦"),
+                    album: text("十一月的萧邦"),
+                    disc_number: Some(1),
+                    track_number: Some(3),
+                    title: text("夜曲"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "{albumartist}/{year} - {album}/{track} {title}",
+                "/music/周杰伦/2006 - 依然范特西/01 夜的第七章.mp3",
+                Some(InferredTags {
+                    album_artist: text("周杰伦"),
+                    year: Some(2006),
+                    album: text("依然范特西"),
+                    track_number: Some(1),
+                    title: text("夜的第七章"),
+                    ..Default::default()
+                }),
+            ),
+            // 规则里的 / 也匹配 Windows 路径分隔符
+            (
+                "{genre}/{artist} - {title}",
+                r"D:\Music\Jazz\Miles Davis - So What.wav",
+                Some(InferredTags {
+                    genre: text("Jazz"),
+                    artist: text("Miles Davis"),
+                    title: text("So What"),
+                    ..Default::default()
+                }),
+            ),
+            // {*} 匹配但不使用，字段名不区分大小写
+            (
+                "{*} - { Title }",
+                "/music/[BV1xx411c7mD] - 晴天.m4a",
+                Some(InferredTags {
+                    title: text("晴天"),
+                    ..Default::default()
+                }),
+            ),
+            // 数字字段只匹配数字
+            ("{track}. {title}", "/music/夜曲.mp3", None),
+            ("{track}. {title}", "/music/1000. 夜曲.mp3", None),
+            ("{year} - {album}", "/music/06 - 依然范特西.mp3", None),
+            // 目录层级不够
+            ("{artist}/{album}/{title}", "夜曲.mp3", None),
+            // 去掉首尾空格后为空的字段视为不匹配
+            ("{artist} - {title}", "/music/ - 夜曲.mp3", None),
+        ];
+        for (pattern, path, expected) in cases {
+            let expected = expected.map(|tags| InferredTags {
+                pattern: pattern.to_string(),
+                ..tags
+            });
+            let inferred = FilenamePattern::parse(pattern)
+                .unwrap()
+                .infer(Path::new(path));
+            assert_eq!(inferred, expected, "{:?} {:?}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn uses_first_matching_pattern() {
+        let patterns = compile_patterns(&[
+            "{track}. {title}".to_string(),
+            "".to_string(),
+            "{artist} - {title}".to_string(),
+        ])
+        .unwrap();
+        let cases = [
+            ("/music/05. 晴天.mp3", Some("{track}. {title}")),
+            ("/music/周杰伦 - 晴天.mp3", Some("{artist} - {title}")),
+            ("/music/晴天.mp3", None),
+        ];
+        for (path, expected) in cases {
+            let inferred = infer_from_path(Path::new(path), &patterns);
+            assert_eq!(
+                inferred.as_ref().map(|t| t.pattern.as_str()),
+                expected,
+                "{:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn fills_only_missing_tags() {
+        let inferred = InferredTags {
+            title: text("夜曲"),
+            artist: text("周杰伦"),
+            track_number: Some(3),
+            ..Default::default()
+        };
+        let mut music = MusicFile {
+            title: text("夜曲 (Live)"),
+            ..Default::default()
+        };
+        assert!(inferred.fill_missing(&mut music));
+        assert_eq!(music.title, text("夜曲 (Live)"));
+        assert_eq!(music.artist, text("周杰伦"));
+        assert_eq!(music.track_number, Some(3));
+        assert!(!inferred.fill_missing(&mut music));
+    }
+}
@@ -1,14 +1,46 @@
 use tauri_helper::{auto_collect_command, tauri_collect_commands};
 
+pub mod acoustic;
 pub mod ai;
+pub mod catalog;
 pub mod commands;
+pub mod cue;
 pub mod db;
+pub mod decode;
+pub mod encoding;
+pub mod filename_pattern;
+pub mod loudness;
+pub mod lyrics;
+pub mod migrations;
 pub mod models;
+pub mod path_template;
+pub mod playlist_file;
+pub mod probe;
+pub mod recovery;
+pub mod song_id;
+pub mod store;
+pub mod watcher;
 use ai::*;
+use commands::browse::*;
+use commands::cover::*;
+use commands::duplicates::*;
 use commands::file_scan::*;
+use commands::library_edit::*;
+use commands::library_query::*;
+use commands::library_roots::*;
+use commands::lyrics::*;
 use commands::online::*;
+use commands::organize::*;
+use commands::playlist_import::*;
+use commands::playlists::*;
+use commands::relocate::*;
+use commands::replay_gain::*;
+use commands::scan_progress::*;
+use commands::tag_edit::*;
+use commands::tag_inference::*;
+use commands::waveform::*;
 use db::*;
-use models::*;
+use recovery::*;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -23,6 +55,16 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // 数据迁移要在处理任何命令之前完成
+            if let Err(e) = store::open_database(app.handle()) {
+                println!("[数据库] 打开失败: {}", e);
+            }
+            if let Err(e) = watcher::start_library_watcher(app.handle()) {
+                println!("[监听] 启动失败: {}", e);
+            }
+            Ok(())
+        })
         .invoke_handler(tauri_collect_commands!())
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
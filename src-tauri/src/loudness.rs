@@ -0,0 +1,247 @@
+use std::f64::consts::PI;
+
+/// ReplayGain 2.0 的参考电平（LUFS）
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+// EBU R128 / ITU-R BS.1770：400ms 的测量块，每 100ms 前进一次
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+// 真峰值的过采样滤波器，每个相位的抽头数
+const TAPS_PER_PHASE: usize = 12;
+
+/// 一段音频的测量结果。blocks 为通过绝对门限前的全部测量块能量，
+/// 合并同一专辑各轨的 blocks 就能算出专辑的综合响度
+#[derive(Debug, Clone, Default)]
+pub struct TrackLoudness {
+    /// 综合响度（LUFS），整段都是静音时为 None
+    pub integrated: Option<f64>,
+    /// 真峰值，线性值，1.0 为满幅
+    pub true_peak: f64,
+    pub blocks: Vec<f64>,
+}
+
+/// 按 BS.1770 门限规则计算综合响度
+pub fn integrated_loudness(blocks: &[f64]) -> Option<f64> {
+    let above_absolute: Vec<f64> = blocks
+        .iter()
+        .copied()
+        .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE)
+        .collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let relative_gate = energy_to_lufs(mean(&above_absolute)) + RELATIVE_GATE;
+    let gated: Vec<f64> = above_absolute
+        .into_iter()
+        .filter(|&e| energy_to_lufs(e) > relative_gate)
+        .collect();
+    if gated.is_empty() {
+        return None;
+    }
+    Some(energy_to_lufs(mean(&gated)))
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.max(f64::MIN_POSITIVE).log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// EBU R128 响度测量：K 计权滤波、分块能量、过采样真峰值
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    sub_block_frames: usize,
+    frames_in_sub_block: usize,
+    sub_block_energy: f64,
+    recent_sub_blocks: Vec<f64>,
+    blocks: Vec<f64>,
+    peak: TruePeak,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let rate = sample_rate as f64;
+        // 5.1 声道：LFE 不计，环绕声道加权 1.41（约 +1.5dB）
+        let weights = if channels == 6 {
+            vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+        } else {
+            vec![1.0; channels]
+        };
+        Self {
+            channels,
+            weights,
+            filters: (0..channels)
+                .map(|_| [Biquad::high_shelf(rate), Biquad::high_pass(rate)])
+                .collect(),
+            sub_block_frames: (sample_rate as usize / 10).max(1),
+            frames_in_sub_block: 0,
+            sub_block_energy: 0.0,
+            recent_sub_blocks: Vec::with_capacity(SUB_BLOCKS_PER_BLOCK),
+            blocks: Vec::new(),
+            peak: TruePeak::new(sample_rate, channels),
+        }
+    }
+
+    /// 输入交错排列的采样
+    pub fn process(&mut self, interleaved: &[f32]) {
+        for frame in interleaved.chunks_exact(self.channels) {
+            let mut energy = 0.0;
+            for (channel, &sample) in frame.iter().enumerate() {
+                let [shelf, pass] = &mut self.filters[channel];
+                let filtered = pass.process(shelf.process(sample as f64));
+                energy += self.weights[channel] * filtered * filtered;
+            }
+            self.peak.process(frame);
+
+            self.sub_block_energy += energy;
+            self.frames_in_sub_block += 1;
+            if self.frames_in_sub_block == self.sub_block_frames {
+                self.push_sub_block();
+            }
+        }
+    }
+
+    fn push_sub_block(&mut self) {
+        let energy = self.sub_block_energy / self.sub_block_frames as f64;
+        self.sub_block_energy = 0.0;
+        self.frames_in_sub_block = 0;
+        if self.recent_sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            self.recent_sub_blocks.remove(0);
+        }
+        self.recent_sub_blocks.push(energy);
+        if self.recent_sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            self.blocks.push(mean(&self.recent_sub_blocks));
+        }
+    }
+
+    pub fn finish(self) -> TrackLoudness {
+        TrackLoudness {
+            integrated: integrated_loudness(&self.blocks),
+            true_peak: self.peak.max,
+            blocks: self.blocks,
+        }
+    }
+}
+
+/// 直接 II 型转置结构的二阶滤波器
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// K 计权第一级：模拟头部声学效应的高频搁架滤波，系数按采样率重新计算（与 libebur128 相同）
+    fn high_shelf(rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// K 计权第二级：RLB 高通
+    fn high_pass(rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// 过采样后取峰值，找出采样点之间的峰（BS.1770 附录 2）。
+/// 采样率低于 96kHz 时 4 倍过采样，低于 192kHz 时 2 倍，更高时直接取采样峰值
+struct TruePeak {
+    factor: usize,
+    /// 按相位排列的多相滤波器系数
+    phases: Vec<[f64; TAPS_PER_PHASE]>,
+    /// 每个声道最近 TAPS_PER_PHASE 个采样，环形缓冲
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+    position: usize,
+    max: f64,
+}
+
+impl TruePeak {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let factor = match sample_rate {
+            0..96000 => 4,
+            96000..192000 => 2,
+            _ => 1,
+        };
+        // 加汉宁窗的 sinc 低通，截止频率为原采样率的奈奎斯特频率
+        let length = factor * TAPS_PER_PHASE;
+        let center = (length - 1) as f64 / 2.0;
+        let mut phases = vec![[0.0; TAPS_PER_PHASE]; factor];
+        for (i, phase) in phases.iter_mut().enumerate() {
+            for (tap, coefficient) in phase.iter_mut().enumerate() {
+                let n = (tap * factor + i) as f64;
+                let x = (n - center) / factor as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * (n + 0.5) / length as f64).cos();
+                *coefficient = sinc * window;
+            }
+        }
+        Self {
+            factor,
+            phases,
+            history: vec![[0.0; TAPS_PER_PHASE]; channels],
+            position: 0,
+            max: 0.0,
+        }
+    }
+
+    fn process(&mut self, frame: &[f32]) {
+        self.position = (self.position + 1) % TAPS_PER_PHASE;
+        for (channel, &sample) in frame.iter().enumerate() {
+            let sample = sample as f64;
+            self.max = self.max.max(sample.abs());
+            if self.factor == 1 {
+                continue;
+            }
+            let history = &mut self.history[channel];
+            history[self.position] = sample;
+            for phase in &self.phases {
+                let mut y = 0.0;
+                for (tap, coefficient) in phase.iter().enumerate() {
+                    let index = (self.position + TAPS_PER_PHASE - tap) % TAPS_PER_PHASE;
+                    y += coefficient * history[index];
+                }
+                self.max = self.max.max(y.abs());
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+
+/// 解析后的歌词。synced 为 false 时是没有时间轴的纯文本歌词，各行 time 为空
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    pub synced: bool,
+    /// 歌词来源："lrc"（同目录的 .lrc 文件）、"sylt"（内嵌的同步歌词帧）或 "embedded"（内嵌的普通歌词）
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    pub lines: Vec<LyricLine>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLine {
+    /// 开始时间（秒），已经计入 [offset:]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<f64>,
+    pub text: String,
+    /// 逐字时间（增强型 LRC 的 <mm:ss.xx> 或 SYLT 的分段），没有时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<LyricWord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricWord {
+    pub time: f64,
+    pub text: String,
+}
+
+/// 解析 LRC 文本。支持一行多个时间标签（副歌重复）、[offset:] 整体偏移和
+/// 增强型 LRC 的逐字时间；一个时间标签都没有时按纯文本歌词返回
+pub fn parse_lrc(text: &str) -> Lyrics {
+    let mut lyrics = Lyrics::default();
+    let mut offset = 0.0;
+    let mut timed: Vec<LyricLine> = Vec::new();
+    let mut plain: Vec<LyricLine> = Vec::new();
+
+    for raw in text.lines() {
+        let mut rest = raw.trim();
+        let mut times = Vec::new();
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(end) = tag.find(']') else {
+                break;
+            };
+            let content = &tag[..end];
+            match parse_timestamp(content) {
+                Some(time) => times.push(time),
+                None => {
+                    if let Some((key, value)) = content.split_once(':') {
+                        let value = value.trim();
+                        match key.trim().to_lowercase().as_str() {
+                            "ti" => lyrics.title = non_empty(value),
+                            "ar" => lyrics.artist = non_empty(value),
+                            "al" => lyrics.album = non_empty(value),
+                            // 正值表示歌词提前显示，单位毫秒
+                            "offset" => offset = value.parse::<f64>().unwrap_or(0.0) / 1000.0,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            rest = tag[end + 1..].trim_start();
+        }
+
+        if times.is_empty() {
+            // 只有元数据标签的行不当作歌词
+            if !raw.trim_start().starts_with('[') && !rest.is_empty() {
+                plain.push(LyricLine {
+                    time: None,
+                    text: rest.to_string(),
+                    words: vec![],
+                });
+            }
+            continue;
+        }
+
+        let (text, words) = parse_words(rest);
+        let first = times[0];
+        for time in times {
+            // 重复出现的行，逐字时间按与第一次出现的差值平移
+            let shift = time - first;
+            timed.push(LyricLine {
+                time: Some(time),
+                text: text.clone(),
+                words: words
+                    .iter()
+                    .map(|w| LyricWord {
+                        time: w.time + shift,
+                        text: w.text.clone(),
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    lyrics.source = "lrc".to_string();
+    if timed.is_empty() {
+        lyrics.lines = plain;
+        return lyrics;
+    }
+    for line in &mut timed {
+        line.time = line.time.map(|t| (t - offset).max(0.0));
+        for word in &mut line.words {
+            word.time = (word.time - offset).max(0.0);
+        }
+    }
+    // 时间相同的行保持原来的顺序（常见于双语歌词）
+    timed.sort_by(|a, b| a.time.unwrap_or(0.0).total_cmp(&b.time.unwrap_or(0.0)));
+    lyrics.synced = true;
+    lyrics.lines = timed;
+    lyrics
+}
+
+/// 拆出增强型 LRC 的 <mm:ss.xx> 逐字时间，返回去掉时间标签后的整行文本
+fn parse_words(line: &str) -> (String, Vec<LyricWord>) {
+    let mut text = String::new();
+    let mut words: Vec<LyricWord> = Vec::new();
+    let mut rest = line;
+
+    loop {
+        let next_tag = rest.find('<').and_then(|start| {
+            let end = start + rest[start..].find('>')?;
+            parse_timestamp(&rest[start + 1..end]).map(|time| (start, end, time))
+        });
+        let Some((start, end, time)) = next_tag else {
+            text.push_str(rest);
+            if let Some(word) = words.last_mut() {
+                word.text.push_str(rest);
+            }
+            break;
+        };
+        let before = &rest[..start];
+        text.push_str(before);
+        if let Some(word) = words.last_mut() {
+            word.text.push_str(before);
+        }
+        words.push(LyricWord {
+            time,
+            text: String::new(),
+        });
+        rest = &rest[end + 1..];
+    }
+
+    // 行尾的时间标签只表示最后一个字的结束时间，没有文字
+    words.retain(|w| !w.text.is_empty());
+    (text.trim().to_string(), words)
+}
+
+/// 解析 mm:ss、mm:ss.xx 或 mm:ss:xx 形式的时间，返回秒
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let (minutes, seconds) = value.trim().split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    // 部分歌词用冒号分隔百分秒
+    let seconds = seconds.replacen(':', ".", 1);
+    if !seconds.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: f64 = seconds.parse().ok()?;
+    Some(minutes as f64 * 60.0 + seconds)
+}
+
+/// SYLT 帧：每个条目是一段文字及其开始时间（毫秒）。以换行开头的条目开始新的一行，
+/// 其余条目接在当前行后面作为逐字时间
+pub fn from_synced_entries(entries: &[(u32, String)]) -> Lyrics {
+    let mut lines: Vec<LyricLine> = Vec::new();
+    for (time, text) in entries {
+        let time = *time as f64 / 1000.0;
+        let starts_line = text.starts_with(['\n', '\r']) || lines.is_empty();
+        let text = text.trim_start_matches(['\n', '\r']);
+        if starts_line {
+            lines.push(LyricLine {
+                time: Some(time),
+                text: String::new(),
+                words: vec![],
+            });
+        }
+        let Some(line) = lines.last_mut() else {
+            continue;
+        };
+        line.text.push_str(text);
+        line.words.push(LyricWord {
+            time,
+            text: text.to_string(),
+        });
+    }
+    // 整行只有一段时就不需要逐字时间
+    for line in &mut lines {
+        if line.words.len() <= 1 {
+            line.words.clear();
+        }
+        line.text = line.text.trim().to_string();
+    }
+    lines.sort_by(|a, b| a.time.unwrap_or(0.0).total_cmp(&b.time.unwrap_or(0.0)));
+
+    Lyrics {
+        synced: true,
+        source: "sylt".to_string(),
+        lines,
+        ..Default::default()
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 逐字时间的期望值：(开始时间, 文字)
+    type Words<'a> = &'a [(f64, &'a str)];
+
+    /// 每行的时间和文本，方便和期望值整体比较
+    fn lines(lyrics: &Lyrics) -> Vec<(Option<f64>, &str)> {
+        lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let cases = [
+            ("00:05", Some(5.0)),
+            ("01:02.50", Some(62.5)),
+            ("01:02.5", Some(62.5)),
+            // 冒号分隔百分秒
+            ("01:02:50", Some(62.5)),
+            (" 03:00.000 ", Some(180.0)),
+            ("120:00", Some(7200.0)),
+            ("ti:标题", None),
+            ("00:", None),
+            ("-1:00", None),
+            ("aa:10", None),
+            ("0010", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_timestamp(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parses_timed_lines() {
+        let cases: &[(&str, &[(f64, &str)])] = &[
+            ("[00:01.00]a\n[00:02.00]b", &[(1.0, "a"), (2.0, "b")]),
+            // 一行多个时间标签
+            (
+                "[00:10.00][00:30.00]副歌\n[00:20.00]主歌",
+                &[(10.0, "副歌"), (20.0, "主歌"), (30.0, "副歌")],
+            ),
+            // 乱序的行按时间排列，时间相同的保持原顺序
+            (
+                "[00:05]c\n[00:01]a\n[00:01]a'",
+                &[(1.0, "a"), (1.0, "a'"), (5.0, "c")],
+            ),
+            // offset 为正时提前显示，减到负数时取 0
+            ("[offset:500]\n[00:01.00]a", &[(0.5, "a")]),
+            ("[offset:-500]\n[00:01.00]a", &[(1.5, "a")]),
+            ("[offset:2000]\n[00:01.00]a", &[(0.0, "a")]),
+            // 空行保留，用来表示间奏
+            (
+                "[00:01]a\n[00:02]\n[00:03]b",
+                &[(1.0, "a"), (2.0, ""), (3.0, "b")],
+            ),
+            // 没有时间标签的行在有时间轴的歌词里忽略
+            ("说明文字\n[00:01]a", &[(1.0, "a")]),
+        ];
+        for &(text, expected) in cases {
+            let lyrics = parse_lrc(text);
+            assert!(lyrics.synced, "{:?}", text);
+            let expected: Vec<(Option<f64>, &str)> =
+                expected.iter().map(|&(t, s)| (Some(t), s)).collect();
+            assert_eq!(lines(&lyrics), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn reads_metadata_tags() {
+        let lyrics = parse_lrc("[ti:晴天]\n[ar: 周杰伦 ]\n[al:叶惠美]\n[by:]\n[00:01]故事的小黄花");
+        assert_eq!(lyrics.title.as_deref(), Some("晴天"));
+        assert_eq!(lyrics.artist.as_deref(), Some("周杰伦"));
+        assert_eq!(lyrics.album.as_deref(), Some("叶惠美"));
+        assert_eq!(lines(&lyrics), [(Some(1.0), "故事的小黄花")]);
+    }
+
+    #[test]
+    fn falls_back_to_plain_text() {
+        let cases: &[(&str, &[&str])] = &[
+            ("第一行\n第二行", &["第一行", "第二行"]),
+            ("[ti:标题]\n第一行\n\n  第二行  ", &["第一行", "第二行"]),
+            ("", &[]),
+        ];
+        for &(text, expected) in cases {
+            let lyrics = parse_lrc(text);
+            assert!(!lyrics.synced, "{:?}", text);
+            assert_eq!(lyrics.source, "lrc");
+            let expected: Vec<(Option<f64>, &str)> = expected.iter().map(|&s| (None, s)).collect();
+            assert_eq!(lines(&lyrics), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parses_word_timing() {
+        let cases: &[(&str, &str, Words)] = &[
+            (
+                "[00:01.00]<00:01.00>He<00:01.50>llo<00:02.00>",
+                "Hello",
+                &[(1.0, "He"), (1.5, "llo")],
+            ),
+            (
+                "[00:01.00]<00:01.00>你 <00:01.50>好",
+                "你 好",
+                &[(1.0, "你 "), (1.5, "好")],
+            ),
+            // 不是时间的尖括号当作普通文字
+            ("[00:01.00]<b>粗体</b>", "<b>粗体</b>", &[]),
+            (
+                "[offset:500]\n[00:01.00]<00:01.00>a<00:02.00>b",
+                "ab",
+                &[(0.5, "a"), (1.5, "b")],
+            ),
+        ];
+        for &(text, line, expected) in cases {
+            let lyrics = parse_lrc(text);
+            assert_eq!(lyrics.lines.len(), 1, "{:?}", text);
+            assert_eq!(lyrics.lines[0].text, line, "{:?}", text);
+            let words: Vec<(f64, &str)> = lyrics.lines[0]
+                .words
+                .iter()
+                .map(|w| (w.time, w.text.as_str()))
+                .collect();
+            assert_eq!(words, expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn shifts_word_timing_of_repeated_lines() {
+        let lyrics = parse_lrc("[00:01.00][00:11.00]<00:01.00>A<00:01.50>B");
+        let words: Vec<Vec<f64>> = lyrics
+            .lines
+            .iter()
+            .map(|l| l.words.iter().map(|w| w.time).collect())
+            .collect();
+        assert_eq!(words, [vec![1.0, 1.5], vec![11.0, 11.5]]);
+    }
+
+    #[test]
+    fn builds_lines_from_synced_entries() {
+        let entries = [
+            (1000, "Hello".to_string()),
+            (1500, " world".to_string()),
+            (3000, "\nNext".to_string()),
+        ];
+        let lyrics = from_synced_entries(&entries);
+        assert!(lyrics.synced);
+        assert_eq!(lyrics.source, "sylt");
+        assert_eq!(
+            lines(&lyrics),
+            [(Some(1.0), "Hello world"), (Some(3.0), "Next")]
+        );
+        // 只有一段的行不保留逐字时间
+        assert_eq!(lyrics.lines[0].words.len(), 2);
+        assert!(lyrics.lines[1].words.is_empty());
+    }
+}
@@ -0,0 +1,465 @@
+use crate::db::{ensure_system_playlists, AppSettings, LibraryIndex, StoredFingerprint};
+use crate::models::{DataRecovery, MusicFile, Playlist};
+use crate::recovery;
+use crate::song_id::ensure_song_ids;
+use crate::store;
+use rusqlite::{Connection, Transaction};
+use std::collections::HashMap;
+use std::path::Path;
+
+// 改用 SQLite 之前的数据文件
+const LIBRARY_FILE: &str = "local_library.json";
+const PLAYQUEUE_FILE: &str = "play_queue.json";
+const PLAYLISTS_FILE: &str = "playlists.json";
+const SETTINGS_FILE: &str = "settings.json";
+const LIBRARY_INDEX_FILE: &str = "library_index.json";
+const ACOUSTIC_FINGERPRINTS_FILE: &str = "acoustic_fingerprints.json";
+// 更早的版本里曲库和播放队列用的文件名
+const OLD_LIBRARY_FILE: &str = "library.json";
+const OLD_PLAYLIST_FILE: &str = "playlist.json";
+
+/// 一个迁移步骤，把数据从 version - 1 升级到 version
+pub(crate) struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Transaction, &mut MigrationContext) -> rusqlite::Result<()>,
+}
+
+pub(crate) struct MigrationContext<'a> {
+    /// 应用数据目录，旧版本的数据文件在这里
+    pub app_dir: &'a Path,
+    /// 迁移过程中遇到的损坏文件及恢复结果，由调用方上报
+    pub recoveries: Vec<DataRecovery>,
+}
+
+/// 按版本号排列的迁移步骤。数据库的版本号存在 user_version 里，
+/// 新步骤只能追加在末尾，已经发布的步骤不要再改
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "创建数据表",
+        apply: create_tables,
+    },
+    Migration {
+        version: 2,
+        description: "导入旧版本的 JSON 数据文件",
+        apply: import_json_files,
+    },
+    Migration {
+        version: 3,
+        description: "创建系统歌单",
+        apply: create_system_playlists,
+    },
+    Migration {
+        version: 4,
+        description: "声学指纹改存数据库",
+        apply: import_fingerprints,
+    },
+];
+
+pub(crate) fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub(crate) fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// 依次执行数据库还没有执行过的迁移。每一步在单独的事务里完成并更新版本号，
+/// 中途失败时停在上一个完成的版本，下次启动从失败的那一步继续。返回执行了的版本号
+pub(crate) fn migrate(
+    conn: &mut Connection,
+    ctx: &mut MigrationContext,
+) -> Result<Vec<u32>, String> {
+    let current = schema_version(conn).map_err(|e| format!("读取数据版本失败: {}", e))?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(format!(
+            "数据版本 {} 比当前程序支持的 {} 新，请升级程序",
+            current, latest
+        ));
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let failed = |e: rusqlite::Error| {
+            format!(
+                "迁移到数据版本 {}（{}）失败: {}",
+                migration.version, migration.description, e
+            )
+        };
+        // 损坏的数据文件等事务提交后再改名、换成备份；事务失败时文件保持原样，
+        // 下次启动重试时还能读到
+        let pending = ctx.recoveries.len();
+        let result = conn.transaction().and_then(|tx| {
+            (migration.apply)(&tx, ctx)?;
+            tx.pragma_update(None, "user_version", migration.version)?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            ctx.recoveries.truncate(pending);
+            return Err(failed(e));
+        }
+        for recovery in &mut ctx.recoveries[pending..] {
+            if let Err(e) = recovery::repair(recovery) {
+                println!("[迁移] {}", e);
+            }
+        }
+        println!(
+            "[迁移] 数据版本 {} -> {}：{}",
+            migration.version - 1,
+            migration.version,
+            migration.description
+        );
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+// 歌曲、队列和歌单条目整条存成 JSON，常用于查询的字段用生成列取出来建索引，
+// MusicFile 加字段时不用改表。
+// 用 IF NOT EXISTS 是因为引入版本号之前创建的数据库已经有这些表，版本号却是 0
+fn create_tables(tx: &Transaction, _: &mut MigrationContext) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS songs (
+            id TEXT PRIMARY KEY,
+            position INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            path TEXT GENERATED ALWAYS AS (json_extract(data, '$.path')) VIRTUAL,
+            artist TEXT GENERATED ALWAYS AS (json_extract(data, '$.artist')) VIRTUAL,
+            album TEXT GENERATED ALWAYS AS (json_extract(data, '$.album')) VIRTUAL
+        );
+        CREATE INDEX IF NOT EXISTS songs_path ON songs(path);
+        CREATE INDEX IF NOT EXISTS songs_artist ON songs(artist);
+        CREATE INDEX IF NOT EXISTS songs_album ON songs(album);
+        CREATE TABLE IF NOT EXISTS playlists (
+            id TEXT PRIMARY KEY,
+            position INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            is_system INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS playlist_entries (
+            playlist_id TEXT NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            song_id TEXT NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (playlist_id, position)
+        );
+        CREATE INDEX IF NOT EXISTS playlist_entries_song ON playlist_entries(song_id);
+        CREATE TABLE IF NOT EXISTS queue (
+            position INTEGER PRIMARY KEY,
+            song_id TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS file_index (
+            path TEXT PRIMARY KEY,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS playlist_files (
+            path TEXT PRIMARY KEY,
+            mtime INTEGER NOT NULL
+        );
+        ",
+    )
+}
+
+/// 把 JSON 数据文件导入数据库，原文件保留在原处不删除。
+/// 曲库和播放队列还认更早版本的文件名；那时的歌曲没有 ID，导入时补上
+fn import_json_files(tx: &Transaction, ctx: &mut MigrationContext) -> rusqlite::Result<()> {
+    let mut library: Vec<MusicFile> =
+        read_json_file(ctx, &[LIBRARY_FILE, OLD_LIBRARY_FILE]).unwrap_or_default();
+    let mut queue: Vec<MusicFile> =
+        read_json_file(ctx, &[PLAYQUEUE_FILE, OLD_PLAYLIST_FILE]).unwrap_or_default();
+    let mut playlists: Vec<Playlist> = read_json_file(ctx, &[PLAYLISTS_FILE]).unwrap_or_default();
+    let settings: Option<AppSettings> = read_json_file(ctx, &[SETTINGS_FILE]);
+    let index: Option<LibraryIndex> = read_json_file(ctx, &[LIBRARY_INDEX_FILE]);
+
+    ensure_song_ids(&mut library, &[]);
+    ensure_song_ids(&mut queue, &library);
+    for playlist in &mut playlists {
+        ensure_song_ids(&mut playlist.songs, &library);
+    }
+
+    store::write_songs(tx, &library)?;
+    store::write_queue(tx, &queue)?;
+    store::write_playlists(tx, &playlists)?;
+    if let Some(settings) = &settings {
+        store::write_settings(tx, settings)?;
+    }
+    if let Some(index) = &index {
+        store::write_index(tx, index)?;
+    }
+
+    println!(
+        "[迁移] 导入曲库{}首，播放队列{}首，歌单{}个，设置{}，文件索引{}条",
+        library.len(),
+        queue.len(),
+        playlists.len(),
+        if settings.is_some() { "1份" } else { "无" },
+        index.map_or(0, |i| i.entries.len())
+    );
+    Ok(())
+}
+
+/// 以前系统歌单只在读取时临时补上，要等用户保存一次歌单才会写入；
+/// 现在歌单按条修改，系统歌单必须事先存在
+fn create_system_playlists(tx: &Transaction, _: &mut MigrationContext) -> rusqlite::Result<()> {
+    let mut playlists = store::read_playlists(tx)?;
+    let count = playlists.len();
+    ensure_system_playlists(&mut playlists);
+    if playlists.len() != count {
+        store::write_playlists(tx, &playlists)?;
+    }
+    Ok(())
+}
+
+/// 声学指纹原来单独存在 JSON 文件里，移除歌曲时要另外改文件；
+/// 放进数据库后和曲库在同一个事务里增删。已不在曲库里的歌曲的指纹不导入
+fn import_fingerprints(tx: &Transaction, ctx: &mut MigrationContext) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS fingerprints (
+            song_id TEXT PRIMARY KEY,
+            size INTEGER NOT NULL,
+            mtime INTEGER NOT NULL,
+            fingerprint TEXT NOT NULL
+        );
+        ",
+    )?;
+    let fingerprints: HashMap<String, StoredFingerprint> =
+        read_json_file(ctx, &[ACOUSTIC_FINGERPRINTS_FILE]).unwrap_or_default();
+    if fingerprints.is_empty() {
+        return Ok(());
+    }
+    store::upsert_fingerprints(tx, &fingerprints)?;
+    store::delete_orphan_fingerprints(tx)?;
+    println!("[迁移] 导入声学指纹{}条", fingerprints.len());
+    Ok(())
+}
+
+/// 按顺序尝试几个文件名，读取第一个存在的 JSON 文件。
+/// 文件损坏时改读备份，无法恢复时跳过，两种情况都记在 ctx.recoveries 里，
+/// 由 migrate 在事务提交后处理磁盘上的文件
+fn read_json_file<T: serde::de::DeserializeOwned>(
+    ctx: &mut MigrationContext,
+    names: &[&str],
+) -> Option<T> {
+    let path = names
+        .iter()
+        .map(|name| ctx.app_dir.join(name))
+        .find(|path| path.is_file())?;
+    recovery::load_json(&path, &mut ctx.recoveries)
+        .ok()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// 把 tests/fixtures/migrations 下的一组旧数据文件复制到临时目录，
+    /// 迁移时会改写或改名其中的文件，不能直接用原目录
+    fn fixture(name: &str) -> PathBuf {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/migrations")
+            .join(name);
+        let dir = std::env::temp_dir().join(format!(
+            "sonic-migration-{}-{}",
+            name,
+            crate::song_id::new_song_id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for entry in fs::read_dir(source).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), dir.join(entry.file_name())).unwrap();
+        }
+        dir
+    }
+
+    fn empty_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "sonic-migration-empty-{}",
+            crate::song_id::new_song_id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(conn: &mut Connection, dir: &Path) -> (Vec<u32>, Vec<DataRecovery>) {
+        let mut ctx = MigrationContext {
+            app_dir: dir,
+            recoveries: Vec::new(),
+        };
+        let applied = migrate(conn, &mut ctx).unwrap();
+        (applied, ctx.recoveries)
+    }
+
+    fn all_versions() -> Vec<u32> {
+        MIGRATIONS.iter().map(|m| m.version).collect()
+    }
+
+    #[test]
+    fn versions_are_consecutive() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn new_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let (applied, recoveries) = run(&mut conn, &empty_dir());
+
+        assert_eq!(applied, all_versions());
+        assert!(recoveries.is_empty());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        assert!(store::read_songs(&conn).unwrap().is_empty());
+        let playlists = store::read_playlists(&conn).unwrap();
+        let ids: Vec<&str> = playlists.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["local", "favorites"]);
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let dir = fixture("json");
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn, &dir);
+        let songs = store::read_songs(&conn).unwrap().len();
+
+        let (applied, _) = run(&mut conn, &dir);
+        assert!(applied.is_empty());
+        assert_eq!(store::read_songs(&conn).unwrap().len(), songs);
+    }
+
+    #[test]
+    fn imports_oldest_file_names_and_assigns_ids() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn, &fixture("legacy"));
+
+        let library = store::read_songs(&conn).unwrap();
+        assert_eq!(library.len(), 2);
+        assert!(library.iter().all(|s| !s.id.is_empty()));
+
+        // 队列里的本地歌曲沿用曲库里同一路径的 ID，在线歌曲按 BV 号生成
+        let queue = store::read_queue(&conn).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].id, library[1].id);
+        assert_eq!(queue[1].id, "bili:BV1xx411c7mD:2");
+    }
+
+    #[test]
+    fn imports_json_files() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let (_, recoveries) = run(&mut conn, &fixture("json"));
+        assert!(recoveries.is_empty());
+
+        let library = store::read_songs(&conn).unwrap();
+        let ids: Vec<&str> = library.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["a1", "a2", "a3"]);
+        assert_eq!(library[0].title.as_deref(), Some("晴天"));
+
+        let queue = store::read_queue(&conn).unwrap();
+        assert_eq!(
+            queue.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(),
+            ["a3", "a1"]
+        );
+
+        let playlists = store::read_playlists(&conn).unwrap();
+        let names: Vec<&str> = playlists.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["本地音乐", "我喜欢的音乐", "通勤"]);
+        assert_eq!(playlists[1].songs.len(), 1);
+        assert_eq!(playlists[2].songs.len(), 2);
+        assert_eq!(playlists[2].is_system, None);
+
+        let settings = store::read_settings(&conn).unwrap();
+        assert_eq!(
+            settings.download_folder.as_deref(),
+            Some("/music/downloads")
+        );
+        assert_eq!(settings.library_roots.len(), 1);
+        assert_eq!(settings.library_roots[0].exclude, ["**/Samples/**"]);
+
+        let index = store::read_index(&conn).unwrap();
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(index.entries["/music/晴天.flac"].tag_hash, "h1");
+        assert_eq!(index.playlist_files["/music/通勤.m3u8"], 1700000000000);
+
+        // 已不在曲库里的歌曲的指纹不导入
+        let fingerprints = store::read_fingerprints(&conn).unwrap();
+        assert_eq!(fingerprints.len(), 1);
+        assert_eq!(fingerprints["a1"].size, 4096);
+        assert_eq!(fingerprints["a1"].fingerprint, "0a1b2c3d");
+    }
+
+    #[test]
+    fn restores_corrupt_file_from_backup() {
+        let dir = fixture("corrupt");
+        let mut conn = Connection::open_in_memory().unwrap();
+        let (_, recoveries) = run(&mut conn, &dir);
+
+        assert_eq!(recoveries.len(), 1);
+        assert!(recoveries[0].file.ends_with(PLAYLISTS_FILE));
+        assert!(recoveries[0].restored_from.is_some());
+        assert!(recoveries[0].quarantined.is_some());
+
+        let playlists = store::read_playlists(&conn).unwrap();
+        let names: Vec<&str> = playlists.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["本地音乐", "我喜欢的音乐", "备份里的歌单"]);
+        assert_eq!(playlists[2].songs.len(), 1);
+        assert_eq!(store::read_songs(&conn).unwrap().len(), 1);
+
+        // 提交后损坏的文件改名留存，原位置换成备份
+        assert!(Path::new(recoveries[0].quarantined.as_ref().unwrap()).is_file());
+        assert_eq!(
+            fs::read(dir.join(PLAYLISTS_FILE)).unwrap(),
+            fs::read(recovery::backup_path(&dir.join(PLAYLISTS_FILE), 1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn keeps_corrupt_file_when_import_fails() {
+        let dir = fixture("corrupt");
+        let original = fs::read(dir.join(PLAYLISTS_FILE)).unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
+        // 缺少 position 列的旧表：建表那一步能通过，导入那一步写入失败
+        conn.execute_batch(
+            "CREATE TABLE songs (id TEXT PRIMARY KEY, data TEXT, path TEXT, artist TEXT, album TEXT)",
+        )
+        .unwrap();
+        let mut ctx = MigrationContext {
+            app_dir: &dir,
+            recoveries: Vec::new(),
+        };
+
+        assert!(migrate(&mut conn, &mut ctx).is_err());
+        assert!(ctx.recoveries.is_empty());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        assert_eq!(fs::read(dir.join(PLAYLISTS_FILE)).unwrap(), original);
+        let quarantined = fs::read_dir(&dir).unwrap().any(|e| {
+            e.unwrap()
+                .file_name()
+                .to_string_lossy()
+                .contains(".corrupt-")
+        });
+        assert!(!quarantined);
+    }
+
+    #[test]
+    fn rejects_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        let mut ctx = MigrationContext {
+            app_dir: Path::new(""),
+            recoveries: Vec::new(),
+        };
+        assert!(migrate(&mut conn, &mut ctx).is_err());
+    }
+}
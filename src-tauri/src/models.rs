@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 // 以后如果有其他公用的结构体（比如 AI 的向量数据），也都放这里
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct MusicFile {
+    /// 歌曲的稳定 ID，不随文件改名、移动而变化；歌单、播放队列都以它识别歌曲。
+    /// 旧数据里没有这个字段，加载时会补上
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
     pub path: String,
     pub name: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -12,6 +17,85 @@ pub struct MusicFile {
     pub bv_id: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub page: Option<u32>,
+    // 以下为从内嵌标签读取的信息，在线歌曲或读取失败时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    /// 合辑标记（ID3 的 TCMP、MP4 的 cpil、Vorbis 的 COMPILATION）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compilation: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disc_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    /// 时长（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// 根据文件头识别出的编码，如 "MP3"、"FLAC"、"ALAC"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    /// 容器格式，如 "MPEG"、"MP4"、"Ogg"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// 音频比特率（kbps）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate_mode: Option<BitrateMode>,
+    /// 采样率（Hz）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    /// 位深，有损编码没有位深
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bit_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u32>,
+    /// CUE 分轨的实际音频文件，此时 path 为 "音频路径#音轨号" 形式的虚拟路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    /// CUE 分轨在音频文件中的开始时间（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<f64>,
+    /// CUE 分轨的结束时间（秒），最后一轨为空，播放到文件结尾
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<f64>,
+    /// 响度分析结果，或从文件的 REPLAYGAIN_* 标签读到的值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_gain: Option<ReplayGain>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BitrateMode {
+    Cbr,
+    Vbr,
+    Lossless,
+}
+
+/// EBU R128 响度与 ReplayGain 2.0 增益（参考电平 -18 LUFS）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayGain {
+    /// 综合响度（LUFS）
+    pub loudness: f64,
+    /// 真峰值，线性值，1.0 为满幅
+    pub true_peak: f64,
+    /// 音轨增益（dB）
+    pub track_gain: f64,
+    /// 专辑增益（dB），没有专辑信息时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_gain: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_peak: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,3 +107,426 @@ pub struct Playlist {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_system: Option<bool>,
 }
+
+/// 增量扫描的结果：新增、标签有变化、以及文件已不存在的歌曲
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanDiff {
+    pub added: Vec<MusicFile>,
+    pub updated: Vec<MusicFile>,
+    pub removed: Vec<String>,
+    /// 扫描被取消时为 true，此时结果只包含已扫描的部分，也不会检测删除
+    #[serde(default)]
+    pub cancelled: bool,
+    /// 本次导入（或重新导入）的播放列表文件
+    #[serde(default)]
+    pub playlists: Vec<PlaylistImport>,
+    /// 扫描中发现的播放列表文件，由调用方在曲库更新后导入
+    #[serde(skip)]
+    pub playlist_files: Vec<PathBuf>,
+}
+
+/// 播放列表文件的导入结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistImport {
+    pub playlist_id: String,
+    pub name: String,
+    /// 播放列表文件的路径
+    pub path: String,
+    /// 成功对应到曲库歌曲的条目数
+    pub resolved: usize,
+    /// 找不到对应歌曲的条目
+    pub unresolved: Vec<UnresolvedEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedEntry {
+    pub line: usize,
+    pub location: String,
+}
+
+/// 扫描进度，通过 scan-progress 事件定时发给前端
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    pub scan_id: Option<String>,
+    pub files_visited: u64,
+    pub audio_found: u64,
+    pub current_dir: String,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+/// 曲库健康检查的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// 检查过的本地文件数（CUE 分轨按整轨文件计一次）
+    pub checked: usize,
+    pub missing: Vec<MissingFile>,
+    /// 根据找到的候选文件推测出的目录变化，按涉及的文件数从多到少排列
+    pub prefix_suggestions: Vec<PathRewrite>,
+}
+
+/// 已不存在的本地文件，以及在曲库根目录下找到的可能的新位置
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingFile {
+    pub path: String,
+    pub name: String,
+    /// 可能的新路径，最可能的排在前面
+    pub candidates: Vec<String>,
+}
+
+/// 把 from 开头的路径改为 to 开头；用于单个文件时 from、to 就是完整路径
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PathRewrite {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub count: usize,
+}
+
+/// 改路径后各处被修改的条目数
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RelocateResult {
+    pub library: usize,
+    pub queue: usize,
+    pub playlists: usize,
+}
+
+/// 修改标签时的改动。字段为 None 表示保持不变，空字符串表示清除；
+/// 音轨号为 0 表示清除；cover 为图片文件路径（JPEG / PNG）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEdit {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub genre: Option<String>,
+    pub cover: Option<String>,
+    pub lyrics: Option<String>,
+    /// 写入 REPLAYGAIN_* 标签，由响度分析填写，前端不能直接修改
+    #[serde(skip)]
+    pub replay_gain: Option<ReplayGain>,
+}
+
+/// 批量修改标签的结果，单个文件失败不影响其他文件
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEditResult {
+    pub updated: Vec<MusicFile>,
+    pub failed: Vec<TagEditFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TagEditFailure {
+    pub id: String,
+    pub path: String,
+    pub error: String,
+}
+
+/// 按文件名规则推断标签的预览：一首歌会被改动的字段
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TagInference {
+    pub id: String,
+    pub path: String,
+    /// 匹配上的规则
+    pub pattern: String,
+    pub changes: Vec<TagChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TagChange {
+    /// 字段名，与 MusicFile 的字段一致，如 "artist"、"trackNumber"
+    pub field: String,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// 响度分析的进度事件
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessProgress {
+    pub job_id: Option<String>,
+    pub total: usize,
+    pub analyzed: usize,
+    pub failed: usize,
+    pub current: String,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+/// 响度分析的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessResult {
+    pub updated: Vec<MusicFile>,
+    pub failed: Vec<TagEditFailure>,
+    pub cancelled: bool,
+}
+
+/// 进度条上显示的波形。peaks 为每段的最小值、最大值交替排列，
+/// 按 127 为满幅缩放成整数，长度为 buckets * 2
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Waveform {
+    pub buckets: usize,
+    /// 实际解码出的时长（秒）
+    pub duration: f64,
+    pub peaks: Vec<i8>,
+}
+
+/// 曲库筛选条件，字段为空表示不限
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryQuery {
+    /// 编码，不区分大小写，如 ["FLAC", "ALAC"]
+    #[serde(default)]
+    pub codecs: Vec<String>,
+    #[serde(default)]
+    pub containers: Vec<String>,
+    pub bitrate_mode: Option<BitrateMode>,
+    pub min_bitrate: Option<u32>,
+    pub max_bitrate: Option<u32>,
+    pub min_sample_rate: Option<u32>,
+    pub max_sample_rate: Option<u32>,
+    pub min_bit_depth: Option<u32>,
+    pub channels: Option<u32>,
+    pub sort_by: Option<LibrarySort>,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LibrarySort {
+    Title,
+    Artist,
+    Album,
+    Duration,
+    Codec,
+    Bitrate,
+    SampleRate,
+    BitDepth,
+    Channels,
+}
+
+/// 按声学指纹查重的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    /// 还没有指纹（或指纹已过期）的本地歌曲数，需要先运行响度分析
+    pub unanalyzed: usize,
+}
+
+/// 一组疑似同一录音的歌曲，按音质从高到低排列
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    /// 建议保留的歌曲 ID，即 songs 中的第一首
+    pub keep: String,
+    pub songs: Vec<DuplicateEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateEntry {
+    pub song: MusicFile,
+    /// 与建议保留的歌曲的相似度（0～1），保留的歌曲本身为 1。
+    /// 只是间接相似（A 像 B、B 像 C）时为它与组内最相似的歌曲的相似度
+    pub similarity: f64,
+    /// 相对建议保留的歌曲开头多出的时长（秒），负数表示少了一段；间接相似时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// 引用这首歌的歌单数
+    pub playlist_count: usize,
+}
+
+/// 保留一首、移除其余重复歌曲的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateResolution {
+    /// 合并了缺失标签后的保留歌曲
+    pub kept: MusicFile,
+    /// 从曲库移除的歌曲 ID
+    pub removed: Vec<String>,
+    /// 已移到回收站的文件
+    pub trashed_files: Vec<String>,
+    /// 引用被换成保留歌曲的歌单数
+    pub playlists: usize,
+    /// 播放队列里被替换的歌曲数
+    pub queue: usize,
+    pub failed: Vec<TagEditFailure>,
+}
+
+/// 从曲库移除歌曲的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryRemoval {
+    /// 从曲库移除的歌曲 ID
+    pub removed: Vec<String>,
+    /// 已移到回收站的文件
+    pub trashed_files: Vec<String>,
+    /// 有歌曲被移除的歌单数
+    pub playlists: usize,
+    /// 从播放队列移除的歌曲数
+    pub queue: usize,
+    pub failed: Vec<TagEditFailure>,
+}
+
+/// 按模板整理文件的计划，预览和执行用的是同一份
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizePlan {
+    pub root: String,
+    pub template: String,
+    pub moves: Vec<FileMove>,
+    /// 已经在模板位置上、不需要移动的歌曲数
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+    pub kind: FileMoveKind,
+    /// 歌词、.cue 跟随的音频文件，音频没移动成功时它们也不移动；
+    /// 多 FILE 的 .cue 引用的其他整轨文件跟随第一个文件
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// 目标位置已有文件或与其他文件重名，已自动加上序号
+    #[serde(default)]
+    pub renamed: bool,
+    /// 同一目录的歌曲被分到了多个目录时，封面复制到每个目录而不是移动
+    #[serde(default)]
+    pub copy: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileMoveKind {
+    #[default]
+    Audio,
+    Cue,
+    Lyrics,
+    Cover,
+}
+
+/// 执行或撤销整理的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizeResult {
+    /// 记录这次整理的日志 ID，撤销时使用
+    pub journal_id: String,
+    pub moved: usize,
+    pub failed: Vec<FileMoveFailure>,
+    /// 曲库、播放队列和歌单里被修改的路径数
+    pub relocated: RelocateResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMoveFailure {
+    pub from: String,
+    pub to: String,
+    pub error: String,
+}
+
+/// 还可以撤销的整理记录
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizeJournalInfo {
+    pub id: String,
+    /// 执行时间（毫秒时间戳）
+    pub created: u64,
+    pub root: String,
+    pub template: String,
+    pub files: usize,
+}
+
+/// 按专辑艺术家和专辑名聚合出来的专辑
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumSummary {
+    /// 由专辑艺术家和专辑名算出，曲库变化后保持不变
+    pub id: String,
+    pub title: String,
+    /// 专辑艺术家标签，没有时取第一首歌的主艺术家；合辑为「群星」
+    pub album_artist: String,
+    pub artist_ids: Vec<String>,
+    pub compilation: bool,
+    /// 各曲目中最早的年份
+    pub year: Option<u32>,
+    pub track_count: usize,
+    pub disc_count: usize,
+    /// 总时长（秒）
+    pub duration: f64,
+    /// 取封面用的文件路径，传给 get_cover_art
+    pub cover_path: String,
+}
+
+/// 从艺术家标签拆分出来的单个艺术家
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistSummary {
+    pub id: String,
+    pub name: String,
+    /// 作为专辑艺术家的专辑数
+    pub album_count: usize,
+    /// 参与的歌曲数，包括合作和合辑里的歌曲
+    pub track_count: usize,
+    /// 总时长（秒）
+    pub duration: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumDetail {
+    pub album: AlbumSummary,
+    pub tracks: Vec<MusicFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistDetail {
+    pub artist: ArtistSummary,
+    pub albums: Vec<AlbumSummary>,
+    /// 不是专辑艺术家、只有歌曲收录在内的专辑（合辑、合作专辑）
+    pub appears_on: Vec<AlbumSummary>,
+    pub tracks: Vec<MusicFile>,
+}
+
+/// 数据文件损坏后的恢复记录
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DataRecovery {
+    /// 损坏的文件
+    pub file: String,
+    pub error: String,
+    /// 用来恢复的备份，没有可用备份时为空（数据从空白开始）
+    pub restored_from: Option<String>,
+    /// 损坏的文件改名后的位置，留着以便手动找回数据
+    pub quarantined: Option<String>,
+    /// 发生时间（毫秒时间戳）
+    pub time: u64,
+}
+
+/// 歌单变化事件的负载
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistChange {
+    pub playlist_id: String,
+    /// 变化后的歌单，删除时为空
+    pub playlist: Option<Playlist>,
+}
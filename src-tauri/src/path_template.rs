@@ -0,0 +1,360 @@
+use crate::models::MusicFile;
+use std::path::PathBuf;
+
+// 单个文件名或目录名的最大长度（字节），给 " (2)" 之类的后缀和扩展名留出余量
+const MAX_COMPONENT_BYTES: usize = 180;
+
+/// 整理文件用的路径模板，如 `{albumartist}/{year} - {album}/{disc}-{track} {title}.{ext}`，
+/// 与 FilenamePattern 互为反向。字段缺失时替换为空，再去掉因此多出来的分隔符；
+/// 目录名整段为空时用「未知」代替
+#[derive(Debug, Clone)]
+pub struct PathTemplate {
+    pub source: String,
+    components: Vec<Vec<Part>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Track,
+    Disc,
+    Year,
+    Genre,
+    Ext,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "albumartist" => Field::AlbumArtist,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "year" => Field::Year,
+            "genre" => Field::Genre,
+            "ext" => Field::Ext,
+            _ => return None,
+        })
+    }
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("无效的路径模板 {}: {}", template, reason);
+        let template = template.trim().replace('\\', "/");
+        if template.is_empty() {
+            return Err(invalid("模板为空"));
+        }
+
+        let mut components = Vec::new();
+        for component in template.split('/') {
+            if component.trim().is_empty() || component == "." || component == ".." {
+                return Err(invalid("目录名不能为空或 . 和 .."));
+            }
+            let mut parts = Vec::new();
+            let mut rest = component;
+            while let Some(start) = rest.find('{') {
+                if start > 0 {
+                    parts.push(Part::Literal(rest[..start].to_string()));
+                }
+                let end = rest[start..]
+                    .find('}')
+                    .map(|i| start + i)
+                    .ok_or_else(|| invalid("缺少 }"))?;
+                let name = &rest[start + 1..end];
+                let field =
+                    Field::parse(name).ok_or_else(|| invalid(&format!("未知字段 {{{}}}", name)))?;
+                parts.push(Part::Field(field));
+                rest = &rest[end + 1..];
+            }
+            if !rest.is_empty() {
+                parts.push(Part::Literal(rest.to_string()));
+            }
+            components.push(parts);
+        }
+        if !components.last().is_some_and(|parts| {
+            parts
+                .iter()
+                .any(|p| matches!(p, Part::Field(f) if *f != Field::Ext))
+        }) {
+            return Err(invalid("文件名部分至少需要一个字段"));
+        }
+
+        Ok(Self {
+            source: template,
+            components,
+        })
+    }
+
+    /// 按歌曲标签生成相对根目录的路径。ext 为原文件的扩展名（不含点），
+    /// 模板里没有 {ext} 时自动加在末尾
+    pub fn render(&self, song: &MusicFile, ext: &str) -> PathBuf {
+        let mut path = PathBuf::new();
+        let last = self.components.len() - 1;
+        for (i, parts) in self.components.iter().enumerate() {
+            let is_file = i == last;
+            let mut text = String::new();
+            for part in parts {
+                match part {
+                    Part::Literal(literal) => text.push_str(literal),
+                    Part::Field(Field::Ext) => text.push_str(ext),
+                    Part::Field(field) => text.push_str(&sanitize(&field_value(song, *field))),
+                }
+            }
+            if is_file && !parts.contains(&Part::Field(Field::Ext)) && !ext.is_empty() {
+                text.push('.');
+                text.push_str(ext);
+            }
+            path.push(tidy(&text, is_file, ext));
+        }
+        path
+    }
+
+    /// 只生成目录部分，用于 CUE 整轨文件：几首歌共用一个文件，文件名保持不变
+    pub fn render_dir(&self, song: &MusicFile) -> PathBuf {
+        let full = self.render(song, "");
+        full.parent().map(PathBuf::from).unwrap_or_default()
+    }
+}
+
+fn field_value(song: &MusicFile, field: Field) -> String {
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .unwrap_or_default()
+            .to_string()
+    };
+    match field {
+        // 没有标题时用原文件名（不含扩展名）
+        Field::Title => song
+            .title
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .unwrap_or(&song.name)
+            .to_string(),
+        Field::Artist => text(&song.artist),
+        Field::AlbumArtist => {
+            let album_artist = text(&song.album_artist);
+            if album_artist.is_empty() {
+                text(&song.artist)
+            } else {
+                album_artist
+            }
+        }
+        Field::Album => text(&song.album),
+        Field::Genre => text(&song.genre),
+        Field::Track => song
+            .track_number
+            .map(|n| format!("{:02}", n))
+            .unwrap_or_default(),
+        Field::Disc => song.disc_number.map(|n| n.to_string()).unwrap_or_default(),
+        Field::Year => song.year.map(|n| n.to_string()).unwrap_or_default(),
+        Field::Ext => String::new(),
+    }
+}
+
+/// 替换文件名里不允许的字符（按 Windows 的规则，跨平台都安全）
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// 去掉字段缺失留下的多余分隔符和空格，截断过长的名字；
+/// 文件名部分只处理扩展名之前的内容
+fn tidy(text: &str, is_file: bool, ext: &str) -> String {
+    let (stem, suffix) = match text
+        .strip_suffix(ext)
+        .filter(|_| is_file && !ext.is_empty())
+    {
+        Some(stem) if stem.ends_with('.') => (&stem[..stem.len() - 1], format!(".{}", ext)),
+        _ => (text, String::new()),
+    };
+    let separators = |c: char| c.is_whitespace() || matches!(c, '-' | '_' | '.' | ',' | '·');
+    let mut cleaned = stem
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("()", "")
+        .replace("[]", "");
+    cleaned = cleaned.trim_matches(separators).to_string();
+    if cleaned.is_empty() {
+        cleaned = "未知".to_string();
+    }
+    if cleaned.len() > MAX_COMPONENT_BYTES {
+        let mut end = MAX_COMPONENT_BYTES;
+        while !cleaned.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned.truncate(end);
+        cleaned = cleaned.trim_end_matches(separators).to_string();
+    }
+    cleaned + &suffix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_TEMPLATE: &str = "{albumartist}/{year} - {album}/{disc}-{track} {title}.{ext}";
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    fn song() -> MusicFile {
+        MusicFile {
+            name: "track03".to_string(),
+            title: text("夜曲"),
+            artist: text("周杰伦"),
+            album: text("十一月的萧邦"),
+            year: Some(2005),
+            disc_number: Some(1),
+            track_number: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let cases = [
+            ("", "模板为空"),
+            ("{artist}//{title}", "目录名不能为空或 . 和 .."),
+            ("../{title}", "目录名不能为空或 . 和 .."),
+            ("{artist}/{title", "缺少 }"),
+            ("{artist}/{name}", "未知字段 {name}"),
+            ("{artist}/{ext}", "文件名部分至少需要一个字段"),
+            ("{artist}/固定文件名", "文件名部分至少需要一个字段"),
+        ];
+        for (template, reason) in cases {
+            let error = PathTemplate::parse(template).unwrap_err();
+            assert!(error.ends_with(reason), "{:?}: {}", template, error);
+        }
+    }
+
+    #[test]
+    fn renders_paths_from_tags() {
+        let cases = [
+            (
+                DEFAULT_TEMPLATE,
+                song(),
+                "flac",
+                "周杰伦/2005 - 十一月的萧邦/1-03 夜曲.flac",
+            ),
+            // 专辑艺术家优先，没有时用艺术家
+            (
+                "{albumartist}/{title}",
+                MusicFile {
+                    album_artist: text("群星"),
+                    ..song()
+                },
+                "mp3",
+                "群星/夜曲.mp3",
+            ),
+            // 缺失字段留下的分隔符被去掉
+            (
+                DEFAULT_TEMPLATE,
+                MusicFile {
+                    year: None,
+                    disc_number: None,
+                    ..song()
+                },
+                "flac",
+                "周杰伦/十一月的萧邦/03 夜曲.flac",
+            ),
+            // 没有标签时目录名用「未知」，标题用原文件名
+            (
+                DEFAULT_TEMPLATE,
+                MusicFile {
+                    name: "track03".to_string(),
+                    ..Default::default()
+                },
+                "mp3",
+                "未知/未知/track03.mp3",
+            ),
+            (
+                "{title} ({year})",
+                MusicFile {
+                    year: None,
+                    ..song()
+                },
+                "m4a",
+                "夜曲.m4a",
+            ),
+            // 模板里没有 {ext} 时加在末尾，反斜杠也当作目录分隔符
+            (
+                r"{artist}\{artist} - {title}",
+                song(),
+                "ape",
+                "周杰伦/周杰伦 - 夜曲.ape",
+            ),
+            (
+                "{artist} - {title}",
+                MusicFile {
+                    artist: None,
+                    ..song()
+                },
+                "wav",
+                "夜曲.wav",
+            ),
+            // 文件名里不允许的字符被替换，末尾的 _ 和其他分隔符一样去掉
+            (
+                "{artist}/{title}",
+                MusicFile {
+                    artist: text("AC/DC"),
+                    title: text("Who Made Who? *Live*"),
+                    ..song()
+                },
+                "mp3",
+                "AC_DC/Who Made Who_ _Live.mp3",
+            ),
+        ];
+        for (template, song, ext, expected) in cases {
+            let path = PathTemplate::parse(template).unwrap().render(&song, ext);
+            assert_eq!(path, PathBuf::from(expected), "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn truncates_long_names_at_char_boundary() {
+        let song = MusicFile {
+            title: Some(format!("a{}", "夜".repeat(100))),
+            ..song()
+        };
+        let path = PathTemplate::parse("{title}")
+            .unwrap()
+            .render(&song, "flac");
+        assert_eq!(path, PathBuf::from(format!("a{}.flac", "夜".repeat(59))));
+    }
+
+    #[test]
+    fn renders_directory_only_for_cue_sources() {
+        let template = PathTemplate::parse(DEFAULT_TEMPLATE).unwrap();
+        assert_eq!(
+            template.render_dir(&song()),
+            PathBuf::from("周杰伦/2005 - 十一月的萧邦")
+        );
+        assert_eq!(
+            PathTemplate::parse("{title}").unwrap().render_dir(&song()),
+            PathBuf::new()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::encoding::decode_text;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// 其他播放器导出的播放列表文件（.m3u / .m3u8 / .pls）
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistFile {
+    /// #PLAYLIST 指令给出的名称，没有时由调用方用文件名代替
+    pub title: Option<String>,
+    pub entries: Vec<PlaylistFileEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistFileEntry {
+    /// 在文件中的行号（从 1 开始），报告无法识别的条目时使用
+    pub line: usize,
+    /// 文件里写的原始位置，可能是相对路径、绝对路径或 URL
+    pub location: String,
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| matches!(ext.as_str(), "m3u" | "m3u8" | "pls"))
+}
+
+/// 读取并解析播放列表文件，.m3u 常见 GBK 等本地编码，这里自动识别
+pub fn read_playlist_file(path: &Path) -> std::io::Result<PlaylistFile> {
+    let text = decode_text(&fs::read(path)?);
+    let is_pls = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+    Ok(if is_pls {
+        parse_pls(&text)
+    } else {
+        parse_m3u(&text)
+    })
+}
+
+pub fn parse_m3u(text: &str) -> PlaylistFile {
+    let mut playlist = PlaylistFile::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('#') {
+            if let Some(title) = directive.strip_prefix("PLAYLIST:") {
+                playlist.title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            }
+            continue;
+        }
+        playlist.entries.push(PlaylistFileEntry {
+            line: i + 1,
+            location: line.to_string(),
+        });
+    }
+    playlist
+}
+
+/// PLS 是 INI 格式：File1=...、Title1=...，按编号而不是出现顺序排列
+pub fn parse_pls(text: &str) -> PlaylistFile {
+    let mut numbered: Vec<(u32, PlaylistFileEntry)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        let number = key
+            .get(..4)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("file"))
+            .and_then(|_| key[4..].parse::<u32>().ok());
+        if let Some(number) = number {
+            numbered.push((
+                number,
+                PlaylistFileEntry {
+                    line: i + 1,
+                    location: value.trim().to_string(),
+                },
+            ));
+        }
+    }
+    numbered.sort_by_key(|(number, _)| *number);
+
+    PlaylistFile {
+        title: None,
+        entries: numbered.into_iter().map(|(_, entry)| entry).collect(),
+    }
+}
+
+/// 把条目位置解析成本地路径：相对路径以播放列表所在目录为基准，
+/// 支持 file:// URL 和 Windows 风格的反斜杠；网络地址返回 None
+pub fn resolve_location(playlist_path: &Path, location: &str) -> Option<PathBuf> {
+    let location = match location.strip_prefix("file://") {
+        Some(rest) => {
+            let decoded = percent_decode(rest);
+            // file:///C:/Music/a.mp3 去掉开头的 / 才是 Windows 路径
+            match decoded.strip_prefix('/') {
+                Some(windows) if windows.get(1..2) == Some(":") => windows.to_string(),
+                _ => decoded,
+            }
+        }
+        None if location.contains("://") => return None,
+        None => location.to_string(),
+    };
+
+    let location = if cfg!(windows) {
+        location
+    } else {
+        location.replace('\\', "/")
+    };
+    let path = Path::new(&location);
+    let full = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        playlist_path.parent()?.join(path)
+    };
+    Some(normalize(&full))
+}
+
+/// 按字面处理 `.` 和 `..`，不访问文件系统（文件可能已经不在了）
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(byte) = hex {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
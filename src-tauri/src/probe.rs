@@ -0,0 +1,627 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// 查找 MPEG 帧同步时最多读取的字节数，兼容标签后面有一段填充的文件
+const MPEG_SYNC_SEARCH_LEN: usize = 4096;
+// moov 一般只有几百 KB，超过这个大小就不再读取，防止误读巨大的文件
+const MAX_MOOV_SIZE: u64 = 16 * 1024 * 1024;
+
+/// 通过文件头识别出的音频格式，与扩展名无关
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mpeg,
+    Adts,
+    Flac,
+    Wav,
+    Aiff,
+    Mp4,
+    OggVorbis,
+    OggOpus,
+    OggFlac,
+    Speex,
+    Ape,
+    WavPack,
+    Dsf,
+    Dff,
+}
+
+impl AudioFormat {
+    /// 容器格式名称，与编码分开显示（如 MP4 容器里的 ALAC）
+    pub fn container(self) -> &'static str {
+        match self {
+            AudioFormat::Mpeg => "MPEG",
+            AudioFormat::Adts => "ADTS",
+            AudioFormat::Flac => "FLAC",
+            AudioFormat::Wav => "WAV",
+            AudioFormat::Aiff => "AIFF",
+            AudioFormat::Mp4 => "MP4",
+            AudioFormat::OggVorbis
+            | AudioFormat::OggOpus
+            | AudioFormat::OggFlac
+            | AudioFormat::Speex => "Ogg",
+            AudioFormat::Ape => "APE",
+            AudioFormat::WavPack => "WavPack",
+            AudioFormat::Dsf => "DSF",
+            AudioFormat::Dff => "DFF",
+        }
+    }
+}
+
+/// 无损编码；WavPack 的混合模式也按无损处理
+pub fn is_lossless_codec(codec: &str) -> bool {
+    matches!(codec, "FLAC" | "ALAC" | "PCM" | "APE" | "WavPack" | "DSD")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedFormat {
+    pub format: AudioFormat,
+    /// 编码名称，如 "MP3"、"FLAC"、"ALAC"
+    pub codec: &'static str,
+}
+
+/// 读取文件头判断是否为可播放的音频，不是音频（或无法识别）时返回 None
+pub fn probe_audio(path: &Path) -> Option<DetectedFormat> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 64];
+    let len = read_up_to(&mut file, &mut header).ok()?;
+    let header = &header[..len];
+
+    // ID3v2 可能出现在 MP3、AAC、FLAC 前面，跳过后再判断
+    if header.starts_with(b"ID3") && len >= 10 {
+        let tag_size = syncsafe_u32(&header[6..10]) as u64;
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        file.seek(SeekFrom::Start(10 + tag_size + footer)).ok()?;
+        let mut after = vec![0u8; MPEG_SYNC_SEARCH_LEN];
+        let len = read_up_to(&mut file, &mut after).ok()?;
+        return probe_after_id3(&after[..len]);
+    }
+
+    let format = match header {
+        [b'f', b'L', b'a', b'C', ..] => AudioFormat::Flac,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => AudioFormat::Wav,
+        [b'R', b'F', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E', ..] => AudioFormat::Wav,
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C', ..] => {
+            AudioFormat::Aiff
+        }
+        [b'F', b'R', b'M', b'8', _, _, _, _, _, _, _, _, b'D', b'S', b'D', b' ', ..] => {
+            AudioFormat::Dff
+        }
+        [b'D', b'S', b'D', b' ', ..] => AudioFormat::Dsf,
+        [b'M', b'A', b'C', b' ', ..] => AudioFormat::Ape,
+        [b'w', b'v', b'p', b'k', ..] => AudioFormat::WavPack,
+        [b'O', b'g', b'g', b'S', ..] => probe_ogg(header)?,
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => {
+            let codec = probe_mp4_codec(&mut file)?;
+            return Some(DetectedFormat {
+                format: AudioFormat::Mp4,
+                codec,
+            });
+        }
+        _ => {
+            file.seek(SeekFrom::Start(0)).ok()?;
+            let mut head = vec![0u8; MPEG_SYNC_SEARCH_LEN];
+            let len = read_up_to(&mut file, &mut head).ok()?;
+            return probe_frame_sync(&head[..len]);
+        }
+    };
+
+    Some(DetectedFormat {
+        format,
+        codec: default_codec(format),
+    })
+}
+
+fn default_codec(format: AudioFormat) -> &'static str {
+    match format {
+        AudioFormat::Mpeg => "MP3",
+        AudioFormat::Adts | AudioFormat::Mp4 => "AAC",
+        AudioFormat::Flac | AudioFormat::OggFlac => "FLAC",
+        AudioFormat::Wav | AudioFormat::Aiff => "PCM",
+        AudioFormat::OggVorbis => "Vorbis",
+        AudioFormat::OggOpus => "Opus",
+        AudioFormat::Speex => "Speex",
+        AudioFormat::Ape => "APE",
+        AudioFormat::WavPack => "WavPack",
+        AudioFormat::Dsf | AudioFormat::Dff => "DSD",
+    }
+}
+
+fn probe_after_id3(data: &[u8]) -> Option<DetectedFormat> {
+    if data.starts_with(b"fLaC") {
+        return Some(DetectedFormat {
+            format: AudioFormat::Flac,
+            codec: "FLAC",
+        });
+    }
+    probe_frame_sync(data)
+}
+
+/// 跳过开头的 0 填充后，要求紧接着就是 MPEG 音频帧或 ADTS 帧的同步字，
+/// 不在整段数据里搜索，避免把恰好含有 0xFFF 的其他文件误认成音频
+fn probe_frame_sync(data: &[u8]) -> Option<DetectedFormat> {
+    let start = data.iter().position(|b| *b != 0)?;
+    let h = data.get(start..start + 4)?;
+    if h[0] != 0xFF || h[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+
+    let layer = (h[1] >> 1) & 0b11;
+    if layer == 0 {
+        // ADTS：layer 固定为 0，采样率索引不能是保留值
+        let sample_rate_index = (h[2] >> 2) & 0b1111;
+        return (h[1] & 0xF6 == 0xF0 && sample_rate_index < 13).then_some(DetectedFormat {
+            format: AudioFormat::Adts,
+            codec: "AAC",
+        });
+    }
+
+    let version = (h[1] >> 3) & 0b11;
+    let bitrate_index = h[2] >> 4;
+    let sample_rate_index = (h[2] >> 2) & 0b11;
+    if version == 0b01 || bitrate_index == 0b1111 || sample_rate_index == 0b11 {
+        return None;
+    }
+    let codec = match layer {
+        0b01 => "MP3",
+        0b10 => "MP2",
+        _ => "MP1",
+    };
+    Some(DetectedFormat {
+        format: AudioFormat::Mpeg,
+        codec,
+    })
+}
+
+// MPEG 音频帧头的比特率表（kbps），按 [MPEG-1 / MPEG-2、2.5][Layer I、II、III] 排列
+const MPEG_BITRATES: [[[u16; 15]; 3]; 2] = [
+    [
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+    ],
+    [
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+const MPEG_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+// 判断 CBR / VBR 时最多检查的帧数
+const MPEG_FRAMES_TO_CHECK: usize = 200;
+
+/// 判断 MP3 是否为可变码率：有 Xing / VBRI 头时以它为准（LAME 给 CBR 文件写的是 "Info"），
+/// 没有时检查前面若干帧的比特率是否一致。返回 Some(true) 表示 VBR
+pub fn mpeg_is_vbr(path: &Path) -> Option<bool> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 10];
+    read_up_to(&mut file, &mut header).ok()?;
+    let start = if header.starts_with(b"ID3") {
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        10 + syncsafe_u32(&header[6..10]) as u64 + footer
+    } else {
+        0
+    };
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut data = vec![0u8; 256 * 1024];
+    let len = read_up_to(&mut file, &mut data).ok()?;
+    let data = &data[..len];
+
+    let mut offset = data.iter().position(|b| *b != 0)?;
+    // Xing / Info 在边信息之后，VBRI 固定在帧头后 32 字节，都在第一帧的前 64 字节内
+    let first_frame = data.get(offset..(offset + 64).min(data.len()))?;
+    if find_bytes(first_frame, b"Xing").is_some() || find_bytes(first_frame, b"VBRI").is_some() {
+        return Some(true);
+    }
+    if find_bytes(first_frame, b"Info").is_some() {
+        return Some(false);
+    }
+
+    let mut first_bitrate = None;
+    for _ in 0..MPEG_FRAMES_TO_CHECK {
+        let Some((bitrate, frame_len)) = data.get(offset..offset + 4).and_then(mpeg_frame) else {
+            break;
+        };
+        match first_bitrate {
+            None => first_bitrate = Some(bitrate),
+            Some(first) if first != bitrate => return Some(true),
+            _ => {}
+        }
+        offset += frame_len;
+    }
+    first_bitrate.map(|_| false)
+}
+
+/// 解析 MPEG 音频帧头，返回比特率（kbps）和整帧长度
+fn mpeg_frame(h: &[u8]) -> Option<(u16, usize)> {
+    if h[0] != 0xFF || h[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (h[1] >> 3) & 0b11;
+    let layer = (h[1] >> 1) & 0b11;
+    let bitrate_index = (h[2] >> 4) as usize;
+    let sample_rate_index = ((h[2] >> 2) & 0b11) as usize;
+    if version == 0b01 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+    let sample_rate = *MPEG_SAMPLE_RATES.get(sample_rate_index)?
+        >> match version {
+            0b11 => 0,
+            0b10 => 1,
+            _ => 2,
+        };
+    let mpeg1 = version == 0b11;
+    // layer 字段：3 为 Layer I，2 为 Layer II，1 为 Layer III
+    let layer_index = (3 - layer) as usize;
+    let bitrate = MPEG_BITRATES[usize::from(!mpeg1)][layer_index][bitrate_index];
+    let padding = ((h[2] >> 1) & 1) as usize;
+    let bits = bitrate as usize * 1000;
+    let sample_rate = sample_rate as usize;
+    let frame_len = match layer_index {
+        0 => (12 * bits / sample_rate + padding) * 4,
+        2 if !mpeg1 => 72 * bits / sample_rate + padding,
+        _ => 144 * bits / sample_rate + padding,
+    };
+    Some((bitrate, frame_len.max(4)))
+}
+
+/// Ogg 第一页里的第一个包就是编码的识别头
+fn probe_ogg(header: &[u8]) -> Option<AudioFormat> {
+    let segments = *header.get(26)? as usize;
+    let packet = header.get(27 + segments..)?;
+    if packet.starts_with(b"OpusHead") {
+        Some(AudioFormat::OggOpus)
+    } else if packet.starts_with(b"\x01vorbis") {
+        Some(AudioFormat::OggVorbis)
+    } else if packet.starts_with(b"\x7fFLAC") {
+        Some(AudioFormat::OggFlac)
+    } else if packet.starts_with(b"Speex   ") {
+        Some(AudioFormat::Speex)
+    } else {
+        None
+    }
+}
+
+/// 遍历顶层 atom 找到 moov，再从 stsd 里取第一个音频采样条目的类型；
+/// 没有音频轨（比如纯视频）时返回 None
+fn probe_mp4_codec(file: &mut File) -> Option<&'static str> {
+    let file_len = file.metadata().ok()?.len();
+    let mut offset = 0u64;
+
+    while offset + 8 <= file_len {
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut atom = [0u8; 16];
+        file.read_exact(&mut atom[..8]).ok()?;
+        let mut size = u32::from_be_bytes(atom[..4].try_into().ok()?) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut atom[8..16]).ok()?;
+            size = u64::from_be_bytes(atom[8..16].try_into().ok()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - offset;
+        }
+        if size < header_len {
+            return None;
+        }
+
+        if &atom[4..8] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut moov = vec![0u8; body_len as usize];
+            file.read_exact(&mut moov).ok()?;
+            return find_audio_sample_entry(&moov);
+        }
+        offset += size;
+    }
+    None
+}
+
+fn find_audio_sample_entry(moov: &[u8]) -> Option<&'static str> {
+    // stsd 之后依次是 version/flags、条目数、第一个条目的大小，然后才是条目类型
+    let mut start = 0;
+    while let Some(pos) = find_bytes(&moov[start..], b"stsd") {
+        let entry_type = start + pos + 4 + 4 + 4 + 4;
+        let codec = moov
+            .get(entry_type..entry_type + 4)
+            .and_then(|fourcc| match fourcc {
+                b"mp4a" => Some("AAC"),
+                b"alac" => Some("ALAC"),
+                b"fLaC" => Some("FLAC"),
+                b"Opus" => Some("Opus"),
+                b"ac-3" => Some("AC-3"),
+                b"ec-3" => Some("E-AC-3"),
+                _ => None,
+            });
+        if codec.is_some() {
+            return codec;
+        }
+        start += pos + 4;
+    }
+    None
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn syncsafe_u32(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0u32, |acc, b| (acc << 7) | (*b as u32 & 0x7F))
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        let n = file.read(&mut buf[total..])?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// 把 bytes 写进临时文件。扩展名故意写错，识别结果只能来自文件内容
+    fn temp_file(bytes: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("sonic-probe-{}.txt", crate::song_id::new_song_id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// 识别结果：格式和编码名称
+    type Probed = Option<(AudioFormat, &'static str)>;
+
+    fn probe_bytes(bytes: &[u8]) -> Probed {
+        let path = temp_file(bytes);
+        let detected = probe_audio(&path);
+        fs::remove_file(&path).unwrap();
+        detected.map(|d| (d.format, d.codec))
+    }
+
+    fn concat(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    /// Ogg 第一页：27 字节页头（最后一字节为分段数）、分段表，然后是第一个包
+    fn ogg(packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.resize(26, 0);
+        page.push(1);
+        page.push(packet.len() as u8);
+        page.extend_from_slice(packet);
+        page
+    }
+
+    /// ftyp 后面跟一个 moov，moov 里的 stsd 第一个条目类型为 fourcc
+    fn mp4(fourcc: &[u8]) -> Vec<u8> {
+        let ftyp = concat(&[&16u32.to_be_bytes(), b"ftypM4A ", &[0; 4]]);
+        let body = concat(&[b"trakstsd", &[0; 12], fourcc, &[0; 8]]);
+        let moov = concat(&[&(body.len() as u32 + 8).to_be_bytes(), b"moov", &body]);
+        concat(&[&ftyp, &moov])
+    }
+
+    /// ID3v2 标签，tag_size 为标签体的大小，footer 为 true 时带 10 字节的尾部
+    fn id3(tag_size: u8, footer: bool) -> Vec<u8> {
+        let flags = if footer { 0x10 } else { 0 };
+        let mut tag = concat(&[b"ID3", &[4, 0, flags, 0, 0, 0, tag_size]]);
+        tag.resize(10 + tag_size as usize + if footer { 10 } else { 0 }, 0);
+        tag
+    }
+
+    // MPEG-1 Layer III，128 kbps，44.1 kHz
+    const MP3_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        let cases: Vec<(&str, Vec<u8>, Probed)> = vec![
+            (
+                "flac",
+                concat(&[b"fLaC", &[0; 8]]),
+                Some((AudioFormat::Flac, "FLAC")),
+            ),
+            (
+                "wav",
+                concat(&[b"RIFF", &[0; 4], b"WAVEfmt "]),
+                Some((AudioFormat::Wav, "PCM")),
+            ),
+            (
+                "rf64",
+                concat(&[b"RF64", &[0; 4], b"WAVEds64"]),
+                Some((AudioFormat::Wav, "PCM")),
+            ),
+            ("avi", concat(&[b"RIFF", &[0; 4], b"AVI LIST"]), None),
+            (
+                "aiff",
+                concat(&[b"FORM", &[0; 4], b"AIFFCOMM"]),
+                Some((AudioFormat::Aiff, "PCM")),
+            ),
+            (
+                "aifc",
+                concat(&[b"FORM", &[0; 4], b"AIFCFVER"]),
+                Some((AudioFormat::Aiff, "PCM")),
+            ),
+            (
+                "dff",
+                concat(&[b"FRM8", &[0; 8], b"DSD "]),
+                Some((AudioFormat::Dff, "DSD")),
+            ),
+            (
+                "dsf",
+                concat(&[b"DSD ", &[0; 8]]),
+                Some((AudioFormat::Dsf, "DSD")),
+            ),
+            (
+                "ape",
+                concat(&[b"MAC ", &[0; 8]]),
+                Some((AudioFormat::Ape, "APE")),
+            ),
+            (
+                "wavpack",
+                concat(&[b"wvpk", &[0; 8]]),
+                Some((AudioFormat::WavPack, "WavPack")),
+            ),
+            (
+                "opus",
+                ogg(b"OpusHead\x01\x02"),
+                Some((AudioFormat::OggOpus, "Opus")),
+            ),
+            (
+                "vorbis",
+                ogg(b"\x01vorbis\0\0"),
+                Some((AudioFormat::OggVorbis, "Vorbis")),
+            ),
+            (
+                "ogg flac",
+                ogg(b"\x7fFLAC\x01\0"),
+                Some((AudioFormat::OggFlac, "FLAC")),
+            ),
+            (
+                "speex",
+                ogg(b"Speex   1.2"),
+                Some((AudioFormat::Speex, "Speex")),
+            ),
+            ("ogg theora", ogg(b"\x80theora"), None),
+            ("m4a aac", mp4(b"mp4a"), Some((AudioFormat::Mp4, "AAC"))),
+            ("m4a alac", mp4(b"alac"), Some((AudioFormat::Mp4, "ALAC"))),
+            ("mp4 flac", mp4(b"fLaC"), Some((AudioFormat::Mp4, "FLAC"))),
+            ("mp4 video only", mp4(b"avc1"), None),
+            (
+                "mp3",
+                concat(&[&MP3_HEADER, &[0; 64]]),
+                Some((AudioFormat::Mpeg, "MP3")),
+            ),
+            (
+                "mp2",
+                concat(&[&[0xFF, 0xFD, 0x90, 0x00], &[0; 64]]),
+                Some((AudioFormat::Mpeg, "MP2")),
+            ),
+            (
+                "mp3 after padding",
+                concat(&[&[0; 100], &MP3_HEADER]),
+                Some((AudioFormat::Mpeg, "MP3")),
+            ),
+            (
+                "adts",
+                concat(&[&[0xFF, 0xF1, 0x50, 0x80], &[0; 64]]),
+                Some((AudioFormat::Adts, "AAC")),
+            ),
+            (
+                "id3 + mp3",
+                concat(&[&id3(20, false), &MP3_HEADER]),
+                Some((AudioFormat::Mpeg, "MP3")),
+            ),
+            (
+                "id3 footer + mp3",
+                concat(&[&id3(20, true), &MP3_HEADER]),
+                Some((AudioFormat::Mpeg, "MP3")),
+            ),
+            (
+                "id3 + flac",
+                concat(&[&id3(20, false), b"fLaC"]),
+                Some((AudioFormat::Flac, "FLAC")),
+            ),
+            ("id3 only", id3(20, false), None),
+            ("text", b"just some text, not audio".to_vec(), None),
+            // 同步字不在开头的不算，避免误认
+            ("sync later", concat(&[b"hello", &MP3_HEADER]), None),
+            ("empty", vec![], None),
+        ];
+        for (name, bytes, expected) in cases {
+            assert_eq!(probe_bytes(&bytes), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_frame_headers() {
+        let cases: [(&str, [u8; 4]); 5] = [
+            ("reserved version", [0xFF, 0xEB, 0x90, 0x00]),
+            ("bad bitrate", [0xFF, 0xFB, 0xF0, 0x00]),
+            ("reserved sample rate", [0xFF, 0xFB, 0x9C, 0x00]),
+            ("adts reserved sample rate", [0xFF, 0xF1, 0x34, 0x80]),
+            ("no sync", [0xFF, 0x1B, 0x90, 0x00]),
+        ];
+        for (name, header) in cases {
+            assert_eq!(probe_frame_sync(&header), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn parses_mpeg_frames() {
+        let cases = [
+            // MPEG-1 Layer III：144 * 比特率 / 采样率 + 填充
+            ([0xFF, 0xFB, 0x90, 0x00], Some((128, 417))),
+            ([0xFF, 0xFB, 0x92, 0x00], Some((128, 418))),
+            ([0xFF, 0xFB, 0xA0, 0x00], Some((160, 522))),
+            // MPEG-2 Layer III，22.05 kHz：72 * 比特率 / 采样率
+            ([0xFF, 0xF3, 0x90, 0x00], Some((80, 261))),
+            // MPEG-1 Layer I：以 4 字节的槽为单位
+            ([0xFF, 0xFF, 0x90, 0x00], Some((288, 312))),
+            // 自由格式（比特率索引 0）无法计算帧长
+            ([0xFF, 0xFB, 0x00, 0x00], None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(mpeg_frame(&header), expected, "{:02X?}", header);
+        }
+    }
+
+    /// 按比特率索引依次拼接 MPEG-1 Layer III 帧，first 为第一帧开头的额外内容（如 Xing 头）
+    fn mp3_stream(bitrate_indices: &[u8], first: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (i, &index) in bitrate_indices.iter().enumerate() {
+            let header = [0xFF, 0xFB, index << 4, 0x00];
+            let (_, frame_len) = mpeg_frame(&header).unwrap();
+            let mut frame = header.to_vec();
+            if i == 0 {
+                frame.extend_from_slice(first);
+            }
+            frame.resize(frame_len, 0);
+            data.extend(frame);
+        }
+        data
+    }
+
+    #[test]
+    fn detects_variable_bitrate() {
+        let xing = concat(&[&[0; 32], b"Xing"]);
+        let info = concat(&[&[0; 32], b"Info"]);
+        let vbri = concat(&[&[0; 32], b"VBRI"]);
+        let cases = [
+            ("cbr", mp3_stream(&[9; 10], &[]), Some(false)),
+            ("vbr", mp3_stream(&[9, 9, 10, 9], &[]), Some(true)),
+            ("xing", mp3_stream(&[9; 3], &xing), Some(true)),
+            ("vbri", mp3_stream(&[9; 3], &vbri), Some(true)),
+            // LAME 给 CBR 文件写的是 Info 头
+            ("info", mp3_stream(&[9, 10, 9], &info), Some(false)),
+            (
+                "id3 + vbr",
+                concat(&[&id3(30, false), &mp3_stream(&[9, 10], &[])]),
+                Some(true),
+            ),
+            ("not mpeg", b"not an mp3 file".to_vec(), None),
+        ];
+        for (name, bytes, expected) in cases {
+            let path = temp_file(&bytes);
+            assert_eq!(mpeg_is_vbr(&path), expected, "{}", name);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
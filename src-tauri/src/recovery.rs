@@ -0,0 +1,161 @@
+use crate::models::DataRecovery;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+use tauri_helper::auto_collect_command;
+
+/// 数据文件保留的历史版本数
+pub const BACKUP_COUNT: usize = 5;
+
+/// 数据文件损坏并被恢复（或无法恢复）时发出，负载为 DataRecovery
+pub const DATA_RECOVERED_EVENT: &str = "data-recovered";
+
+// 本次运行期间发生的恢复，前端启动得比恢复晚，收不到事件时可以通过命令查询
+static RECOVERIES: Mutex<Vec<DataRecovery>> = Mutex::new(Vec::new());
+
+/// 先写到同目录的临时文件并落盘，再改名替换正式文件。
+/// 中途崩溃或断电时，正式文件要么是旧内容，要么是完整的新内容
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = sibling(path, "tmp");
+    let result = File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = result.and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    sync_dir(path);
+    Ok(())
+}
+
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling(path, &format!("bak{}", n))
+}
+
+/// 读取 JSON 数据文件，不改动磁盘上的文件。文件不存在时返回 None；
+/// 内容损坏时改用最新的可用备份，恢复记录追加到 recoveries，没有可用备份时返回错误。
+/// 损坏的文件要等调用方确认采用了读到的数据后，再用 repair 改名留存、换成备份
+pub fn load_json<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    recoveries: &mut Vec<DataRecovery>,
+) -> Result<Option<T>, String> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("读取 {} 失败: {}", path.display(), e)),
+    };
+    let error = match serde_json::from_slice(&content) {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => e.to_string(),
+    };
+
+    let backup = (1..=BACKUP_COUNT).find_map(|n| {
+        let backup = backup_path(path, n);
+        let value: T = serde_json::from_slice(&fs::read(&backup).ok()?).ok()?;
+        Some((backup, value))
+    });
+    match backup {
+        Some((backup, value)) => {
+            recoveries.push(record(path, error, Some(&backup), None));
+            Ok(Some(value))
+        }
+        None => {
+            let message = format!("{} 已损坏且没有可用的备份: {}", path.display(), error);
+            recoveries.push(record(path, error, None, None));
+            Err(message)
+        }
+    }
+}
+
+/// 落实 load_json 记下的恢复：损坏的文件改名留存，有可用备份时复制回原位置
+pub fn repair(recovery: &mut DataRecovery) -> Result<(), String> {
+    let path = Path::new(&recovery.file);
+    let backup = match &recovery.restored_from {
+        Some(backup) => {
+            Some(fs::read(backup).map_err(|e| format!("读取备份 {} 失败: {}", backup, e))?)
+        }
+        None => None,
+    };
+    recovery.quarantined = quarantine(path).map(|p| p.to_string_lossy().to_string());
+    if let Some(content) = backup {
+        write_atomic(path, &content)
+            .map_err(|e| format!("从备份恢复 {} 失败: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// 把损坏的文件改名为 `文件名.corrupt-时间戳` 留存，返回新路径
+pub fn quarantine(path: &Path) -> Option<PathBuf> {
+    let target = sibling(path, &format!("corrupt-{}", now_millis()));
+    fs::rename(path, &target).ok().map(|_| target)
+}
+
+pub fn record(
+    file: &Path,
+    error: String,
+    restored_from: Option<&Path>,
+    quarantined: Option<&Path>,
+) -> DataRecovery {
+    DataRecovery {
+        file: file.to_string_lossy().to_string(),
+        error,
+        restored_from: restored_from.map(|p| p.to_string_lossy().to_string()),
+        quarantined: quarantined.map(|p| p.to_string_lossy().to_string()),
+        time: now_millis(),
+    }
+}
+
+/// 记录一次数据恢复并通知前端
+pub fn report(app_handle: &AppHandle, recovery: DataRecovery) {
+    match &recovery.restored_from {
+        Some(backup) => println!(
+            "[恢复] {} 已损坏（{}），已从 {} 恢复",
+            recovery.file, recovery.error, backup
+        ),
+        None => println!(
+            "[恢复] {} 已损坏（{}），没有可用的备份",
+            recovery.file, recovery.error
+        ),
+    }
+    let _ = app_handle.emit(DATA_RECOVERED_EVENT, &recovery);
+    RECOVERIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(recovery);
+}
+
+/// 本次启动以来发生过的数据恢复
+#[tauri::command]
+#[auto_collect_command]
+pub fn get_data_recoveries() -> Result<Vec<DataRecovery>, String> {
+    Ok(RECOVERIES.lock().unwrap_or_else(|e| e.into_inner()).clone())
+}
+
+/// 同目录下加了后缀的文件，如 `playlists.json` -> `playlists.json.bak1`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// 改名后同步目录，保证新的目录项也落盘（Windows 上不需要也不支持）
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,63 @@
+use crate::models::MusicFile;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// 计算指纹时从文件开头和结尾各读取的字节数
+const FINGERPRINT_SAMPLE_LEN: u64 = 64 * 1024;
+
+/// 为本地歌曲生成新的 ID
+pub fn new_song_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// 在线歌曲的 ID 由 BV 号和分 P 决定，同一首歌多次添加得到的 ID 相同
+pub fn online_song_id(bv_id: &str, page: Option<u32>) -> String {
+    format!("bili:{}:{}", bv_id, page.unwrap_or(1))
+}
+
+/// 给没有 ID 的歌曲补上 ID：在线歌曲按 BV 号生成，本地歌曲优先沿用曲库里同一路径的 ID。
+/// 返回是否有歌曲被修改
+pub fn ensure_song_ids(songs: &mut [MusicFile], library: &[MusicFile]) -> bool {
+    let mut by_path: HashMap<&str, &str> = HashMap::new();
+    for song in library.iter().filter(|s| !s.id.is_empty()) {
+        by_path
+            .entry(song.path.as_str())
+            .or_insert(song.id.as_str());
+    }
+
+    let mut changed = false;
+    for song in songs.iter_mut().filter(|s| s.id.is_empty()) {
+        song.id = match (&song.bv_id, song.is_online) {
+            (Some(bv_id), Some(true)) => online_song_id(bv_id, song.page),
+            _ => by_path
+                .get(song.path.as_str())
+                .map(|id| id.to_string())
+                .unwrap_or_else(new_song_id),
+        };
+        changed = true;
+    }
+    changed
+}
+
+/// 文件内容指纹：文件大小加上开头、结尾各一段数据的哈希，
+/// 不读取整个文件，用来认出改名或移动后的同一个文件
+pub fn file_fingerprint(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+
+    let mut buf = vec![0u8; FINGERPRINT_SAMPLE_LEN.min(size) as usize];
+    file.read_exact(&mut buf).ok()?;
+    hasher.update(&buf);
+    if size > FINGERPRINT_SAMPLE_LEN {
+        let tail_len = FINGERPRINT_SAMPLE_LEN.min(size - FINGERPRINT_SAMPLE_LEN);
+        file.seek(SeekFrom::End(-(tail_len as i64))).ok()?;
+        buf.truncate(tail_len as usize);
+        file.read_exact(&mut buf).ok()?;
+        hasher.update(&buf);
+    }
+    Some(hasher.finalize().to_hex().to_string())
+}
@@ -0,0 +1,906 @@
+use crate::db::{AppSettings, IndexEntry, LibraryIndex, StoredFingerprint};
+use crate::migrations::{self, MigrationContext};
+use crate::models::{MusicFile, Playlist, ScanDiff};
+use crate::recovery::{self, BACKUP_COUNT};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+
+const DATABASE_FILE: &str = "sonic.db";
+// 数据库快照目录，数据库损坏时从这里恢复
+const SNAPSHOT_DIR: &str = "backups";
+
+// 每个连接都要设置的选项，表结构由 migrations 负责
+const PRAGMAS: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    PRAGMA foreign_keys = ON;
+";
+
+static DATABASE: OnceLock<Mutex<Option<Connection>>> = OnceLock::new();
+
+/// 打开数据库并执行迁移。启动时在处理任何命令之前调用；
+/// 这里失败的话，之后每次访问数据库都会再试一次
+pub(crate) fn open_database(app_handle: &AppHandle) -> Result<(), String> {
+    with_db(app_handle, |_| Ok(()))
+}
+
+/// 在数据库连接上执行操作。第一次调用时打开数据库（没有时创建；损坏时从快照恢复），
+/// 并把数据迁移到当前版本。所有操作共用一个连接，依次执行
+pub(crate) fn with_db<T>(
+    app_handle: &AppHandle,
+    f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+) -> Result<T, String> {
+    let mut guard = DATABASE
+        .get_or_init(|| Mutex::new(None))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if guard.is_none() {
+        *guard = Some(open(app_handle)?);
+    }
+    let conn = guard.as_mut().expect("数据库已打开");
+    f(conn).map_err(|e| format!("数据库操作失败: {}", e))
+}
+
+fn open(app_handle: &AppHandle) -> Result<Connection, String> {
+    let app_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
+    fs::create_dir_all(app_dir.join(SNAPSHOT_DIR)).map_err(|e| e.to_string())?;
+    let path = app_dir.join(DATABASE_FILE);
+
+    let mut conn = match open_checked(&path) {
+        Ok(conn) => conn,
+        Err(error) => restore_snapshot(app_handle, &app_dir, &path, error)?,
+    };
+    // 先快照再迁移，迁移出问题时还能找回迁移前的数据
+    if let Err(e) = take_snapshot(&conn, &app_dir) {
+        println!("[数据库] 备份失败: {}", e);
+    }
+    let mut ctx = MigrationContext {
+        app_dir: &app_dir,
+        recoveries: Vec::new(),
+    };
+    let result = migrations::migrate(&mut conn, &mut ctx);
+    for record in ctx.recoveries {
+        recovery::report(app_handle, record);
+    }
+    result?;
+    Ok(conn)
+}
+
+/// 打开数据库并检查完整性
+fn open_checked(path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(|e| format!("打开数据库失败: {}", e))?;
+    let check: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| format!("检查数据库失败: {}", e))?;
+    if check != "ok" {
+        return Err(format!("数据库已损坏: {}", check));
+    }
+    conn.execute_batch(PRAGMAS)
+        .map_err(|e| format!("设置数据库失败: {}", e))?;
+    Ok(conn)
+}
+
+/// 数据库损坏时改名留存，从最新的可用快照恢复；没有可用快照时新建空数据库
+fn restore_snapshot(
+    app_handle: &AppHandle,
+    app_dir: &Path,
+    path: &Path,
+    error: String,
+) -> Result<Connection, String> {
+    // WAL 和共享内存文件属于损坏的数据库，跟着一起移走
+    let quarantined = recovery::quarantine(path);
+    for suffix in ["-wal", "-shm"] {
+        let mut side = path.as_os_str().to_os_string();
+        side.push(suffix);
+        let side = PathBuf::from(side);
+        if side.exists() {
+            let _ = recovery::quarantine(&side);
+        }
+    }
+
+    for snapshot in list_snapshots(app_dir) {
+        let restored = fs::read(&snapshot)
+            .and_then(|bytes| recovery::write_atomic(path, &bytes))
+            .map_err(|e| e.to_string())
+            .and_then(|_| open_checked(path));
+        match restored {
+            Ok(conn) => {
+                let record = recovery::record(path, error, Some(&snapshot), quarantined.as_deref());
+                recovery::report(app_handle, record);
+                return Ok(conn);
+            }
+            Err(e) => println!("[数据库] 快照 {} 不可用: {}", snapshot.display(), e),
+        }
+    }
+
+    let _ = fs::remove_file(path);
+    recovery::report(
+        app_handle,
+        recovery::record(path, error, None, quarantined.as_deref()),
+    );
+    open_checked(path)
+}
+
+/// 每次启动时把数据库完整复制一份到快照目录，只保留最近 BACKUP_COUNT 份
+fn take_snapshot(conn: &Connection, app_dir: &Path) -> rusqlite::Result<()> {
+    let target = app_dir
+        .join(SNAPSHOT_DIR)
+        .join(format!("sonic-{}.db", now_millis()));
+    conn.execute("VACUUM INTO ?1", params![target.to_string_lossy()])?;
+    for old in list_snapshots(app_dir).into_iter().skip(BACKUP_COUNT) {
+        let _ = fs::remove_file(old);
+    }
+    Ok(())
+}
+
+/// 快照目录里的数据库快照，最新的在前
+fn list_snapshots(app_dir: &Path) -> Vec<PathBuf> {
+    let mut snapshots: Vec<(u64, PathBuf)> = fs::read_dir(app_dir.join(SNAPSHOT_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let time = name
+                .strip_prefix("sonic-")?
+                .strip_suffix(".db")?
+                .parse()
+                .ok()?;
+            Some((time, entry.path()))
+        })
+        .collect();
+    snapshots.sort_by_key(|(time, _)| Reverse(*time));
+    snapshots.into_iter().map(|(_, path)| path).collect()
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(column: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(e)))
+}
+
+fn read_json_rows<T: serde::de::DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+) -> rusqlite::Result<Vec<T>> {
+    let mut stmt = conn.prepare_cached(sql)?;
+    let rows = stmt.query_map([], |row| from_json(0, &row.get::<_, String>(0)?))?;
+    rows.collect()
+}
+
+pub(crate) fn read_songs(conn: &Connection) -> rusqlite::Result<Vec<MusicFile>> {
+    read_json_rows(conn, "SELECT data FROM songs ORDER BY position")
+}
+
+/// 用 songs 替换整个曲库。只写入有变化的行，曲库很大时改一两首歌也很快
+pub(crate) fn write_songs(conn: &Connection, songs: &[MusicFile]) -> rusqlite::Result<()> {
+    let mut existing: HashMap<String, (usize, String)> = HashMap::new();
+    {
+        let mut stmt = conn.prepare_cached("SELECT id, position, data FROM songs")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+        for row in rows {
+            let (id, value) = row?;
+            existing.insert(id, value);
+        }
+    }
+
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO songs (id, position, data) VALUES (?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET position = excluded.position, data = excluded.data",
+    )?;
+    for (position, song) in songs.iter().enumerate() {
+        let data = to_json(song)?;
+        let unchanged = existing
+            .remove(&song.id)
+            .is_some_and(|(p, d)| p == position && d == data);
+        if !unchanged {
+            upsert.execute(params![song.id, position, data])?;
+        }
+    }
+
+    let mut delete = conn.prepare_cached("DELETE FROM songs WHERE id = ?1")?;
+    for id in existing.keys() {
+        delete.execute(params![id])?;
+    }
+    Ok(())
+}
+
+/// 把 songs 并入曲库：ID 已存在的更新那一条，否则路径不在曲库里时追加到末尾
+pub(crate) fn merge_songs(conn: &Connection, songs: &[MusicFile]) -> rusqlite::Result<()> {
+    let mut ids: HashSet<String> = HashSet::new();
+    let mut paths: HashSet<String> = HashSet::new();
+    let mut next_position: usize = 0;
+    {
+        let mut stmt = conn.prepare_cached("SELECT id, path, position FROM songs")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, usize>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, path, position) = row?;
+            ids.insert(id);
+            paths.extend(path);
+            next_position = next_position.max(position + 1);
+        }
+    }
+
+    let mut update = conn.prepare_cached("UPDATE songs SET data = ?2 WHERE id = ?1")?;
+    let mut insert =
+        conn.prepare_cached("INSERT INTO songs (id, position, data) VALUES (?1, ?2, ?3)")?;
+    for song in songs {
+        if ids.contains(&song.id) {
+            paths.insert(song.path.clone());
+            update.execute(params![song.id, to_json(song)?])?;
+        } else if paths.insert(song.path.clone()) {
+            ids.insert(song.id.clone());
+            insert.execute(params![song.id, next_position, to_json(song)?])?;
+            next_position += 1;
+        }
+    }
+    Ok(())
+}
+
+/// 把扫描得到的变化并入曲库：先删除 removed 路径上的歌曲，updated 只更新仍在曲库里的行，
+/// added 追加到末尾（ID 已存在时更新那一行，如改名后沿用原来的 ID）。
+/// 只动 diff 里的行，扫描期间其他操作对曲库的修改不会被覆盖
+pub(crate) fn apply_scan_diff(conn: &Connection, diff: &ScanDiff) -> rusqlite::Result<()> {
+    let mut delete = conn.prepare_cached("DELETE FROM songs WHERE path = ?1")?;
+    for path in &diff.removed {
+        delete.execute(params![path])?;
+    }
+    let mut update = conn.prepare_cached("UPDATE songs SET data = ?2 WHERE id = ?1")?;
+    for song in &diff.updated {
+        update.execute(params![song.id, to_json(song)?])?;
+    }
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO songs (id, position, data)
+         VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM songs), ?2)
+         ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+    )?;
+    for song in &diff.added {
+        upsert.execute(params![song.id, to_json(song)?])?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_song(conn: &Connection, id: &str) -> rusqlite::Result<Option<MusicFile>> {
+    let data: Option<String> = conn
+        .prepare_cached("SELECT data FROM songs WHERE id = ?1")?
+        .query_row(params![id], |row| row.get(0))
+        .optional()?;
+    data.map(|data| from_json(0, &data)).transpose()
+}
+
+/// 更新曲库里的一首歌，歌单条目和播放队列里的副本一起更新。返回包含这首歌的歌单 ID
+pub(crate) fn update_song(conn: &Connection, song: &MusicFile) -> rusqlite::Result<Vec<String>> {
+    let data = to_json(song)?;
+    for sql in [
+        "UPDATE songs SET data = ?2 WHERE id = ?1",
+        "UPDATE playlist_entries SET data = ?2 WHERE song_id = ?1",
+        "UPDATE queue SET data = ?2 WHERE song_id = ?1",
+    ] {
+        conn.prepare_cached(sql)?.execute(params![song.id, data])?;
+    }
+    playlists_containing(conn, &song.id)
+}
+
+/// 从曲库删除歌曲，连同歌单条目、播放队列里的这些歌和它们的指纹。
+/// 返回有歌曲被删除的歌单 ID，以及从播放队列删除的数量
+pub(crate) fn delete_songs(
+    conn: &Connection,
+    ids: &[String],
+) -> rusqlite::Result<(Vec<String>, usize)> {
+    let mut playlists: Vec<String> = Vec::new();
+    let mut queue = 0;
+    for id in ids {
+        for playlist in playlists_containing(conn, id)? {
+            if !playlists.contains(&playlist) {
+                playlists.push(playlist);
+            }
+        }
+        conn.prepare_cached("DELETE FROM playlist_entries WHERE song_id = ?1")?
+            .execute(params![id])?;
+        queue += conn
+            .prepare_cached("DELETE FROM queue WHERE song_id = ?1")?
+            .execute(params![id])?;
+        conn.prepare_cached("DELETE FROM fingerprints WHERE song_id = ?1")?
+            .execute(params![id])?;
+        conn.prepare_cached("DELETE FROM songs WHERE id = ?1")?
+            .execute(params![id])?;
+    }
+    Ok((playlists, queue))
+}
+
+/// 曲库里还有没有从这个整轨文件拆分出的 CUE 分轨
+pub(crate) fn has_cue_tracks(conn: &Connection, source_path: &str) -> rusqlite::Result<bool> {
+    conn.prepare_cached(
+        "SELECT EXISTS (SELECT 1 FROM songs WHERE json_extract(data, '$.sourcePath') = ?1)",
+    )?
+    .query_row(params![source_path], |row| row.get(0))
+}
+
+/// 按路径找一首歌：先找曲库，再找播放队列和歌单（在线歌曲通常只在这两处）
+pub(crate) fn find_song_by_path(
+    conn: &Connection,
+    path: &str,
+) -> rusqlite::Result<Option<MusicFile>> {
+    for sql in [
+        "SELECT data FROM songs WHERE path = ?1 LIMIT 1",
+        "SELECT data FROM queue WHERE json_extract(data, '$.path') = ?1 LIMIT 1",
+        "SELECT data FROM playlist_entries WHERE json_extract(data, '$.path') = ?1 LIMIT 1",
+    ] {
+        let data: Option<String> = conn
+            .prepare_cached(sql)?
+            .query_row(params![path], |row| row.get(0))
+            .optional()?;
+        if let Some(data) = data {
+            return from_json(0, &data).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// 把播放队列和歌单里路径为 path 的歌曲换成 song。返回有变化的歌单 ID
+pub(crate) fn replace_song_at(
+    conn: &Connection,
+    path: &str,
+    song: &MusicFile,
+) -> rusqlite::Result<Vec<String>> {
+    let playlists: Vec<String> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT DISTINCT playlist_id FROM playlist_entries
+             WHERE json_extract(data, '$.path') = ?1",
+        )?;
+        let rows = stmt.query_map(params![path], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let data = to_json(song)?;
+    for sql in [
+        "UPDATE playlist_entries SET song_id = ?2, data = ?3 WHERE json_extract(data, '$.path') = ?1",
+        "UPDATE queue SET song_id = ?2, data = ?3 WHERE json_extract(data, '$.path') = ?1",
+    ] {
+        conn.prepare_cached(sql)?
+            .execute(params![path, song.id, data])?;
+    }
+    Ok(playlists)
+}
+
+/// 把歌单和播放队列里的 old_id 换成 song，然后从曲库删除 old_id（如同一个文件被收录了两次，
+/// 或者合并重复的录音）。歌单里已经有 song 时直接去掉 old_id，不会出现两次。
+/// 返回有变化的歌单 ID，以及播放队列里被替换的数量
+pub(crate) fn redirect_song(
+    conn: &Connection,
+    old_id: &str,
+    song: &MusicFile,
+) -> rusqlite::Result<(Vec<String>, usize)> {
+    let playlists = playlists_containing(conn, old_id)?;
+    let data = to_json(song)?;
+    conn.prepare_cached(
+        "DELETE FROM playlist_entries WHERE song_id = ?1 AND playlist_id IN
+         (SELECT playlist_id FROM playlist_entries WHERE song_id = ?2)",
+    )?
+    .execute(params![old_id, song.id])?;
+    conn.prepare_cached("UPDATE playlist_entries SET song_id = ?2, data = ?3 WHERE song_id = ?1")?
+        .execute(params![old_id, song.id, data])?;
+    let queue = conn
+        .prepare_cached("UPDATE queue SET song_id = ?2, data = ?3 WHERE song_id = ?1")?
+        .execute(params![old_id, song.id, data])?;
+    delete_songs(conn, &[old_id.to_string()])?;
+    Ok((playlists, queue))
+}
+
+/// 曲库里路径为 path、ID 不是 id 的歌曲
+pub(crate) fn other_songs_at(
+    conn: &Connection,
+    path: &str,
+    id: &str,
+) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached("SELECT id FROM songs WHERE path = ?1 AND id != ?2")?;
+    let rows = stmt.query_map(params![path, id], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// rewrite_songs 改过的歌曲
+#[derive(Default)]
+pub(crate) struct SongRewrite {
+    pub library: Vec<MusicFile>,
+    pub queue: Vec<MusicFile>,
+    /// 改过的歌单条目数，以及这些条目所在的歌单
+    pub playlist_entries: usize,
+    pub playlists: Vec<String>,
+}
+
+/// 用 f 逐条修改曲库、播放队列和歌单条目里的歌曲，只写回 f 返回 true 的行
+pub(crate) fn rewrite_songs(
+    conn: &Connection,
+    mut f: impl FnMut(&mut MusicFile) -> bool,
+) -> rusqlite::Result<SongRewrite> {
+    let mut rewrite = SongRewrite::default();
+    for table in ["songs", "queue", "playlist_entries"] {
+        let playlist_column = if table == "playlist_entries" {
+            "playlist_id"
+        } else {
+            "''"
+        };
+        let rows: Vec<(i64, String, String)> = {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT rowid, data, {} FROM {}",
+                playlist_column, table
+            ))?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        let mut update =
+            conn.prepare_cached(&format!("UPDATE {} SET data = ?2 WHERE rowid = ?1", table))?;
+        for (rowid, data, playlist_id) in rows {
+            let mut song: MusicFile = from_json(1, &data)?;
+            if !f(&mut song) {
+                continue;
+            }
+            update.execute(params![rowid, to_json(&song)?])?;
+            match table {
+                "songs" => rewrite.library.push(song),
+                "queue" => rewrite.queue.push(song),
+                _ => {
+                    rewrite.playlist_entries += 1;
+                    if !rewrite.playlists.contains(&playlist_id) {
+                        rewrite.playlists.push(playlist_id);
+                    }
+                }
+            }
+        }
+    }
+    Ok(rewrite)
+}
+
+/// 按 rewrite 修改文件索引的路径和其中记录的 .cue 路径，只动有变化的记录
+pub(crate) fn rewrite_index_paths(
+    conn: &Connection,
+    rewrite: impl Fn(&str) -> Option<String>,
+) -> rusqlite::Result<()> {
+    let mut moved: Vec<(String, String, IndexEntry)> = Vec::new();
+    for (path, mut entry) in read_index(conn)?.entries {
+        let new_path = rewrite(&path);
+        let cue_path = entry.cue_path.as_deref().and_then(&rewrite);
+        if new_path.is_none() && cue_path.is_none() {
+            continue;
+        }
+        if cue_path.is_some() {
+            entry.cue_path = cue_path;
+        }
+        let new_path = new_path.unwrap_or_else(|| path.clone());
+        moved.push((path, new_path, entry));
+    }
+    // 先删后写，路径互换（如撤销整理）时不会互相覆盖
+    let mut delete = conn.prepare_cached("DELETE FROM file_index WHERE path = ?1")?;
+    for (path, _, _) in &moved {
+        delete.execute(params![path])?;
+    }
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO file_index (path, data) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET data = excluded.data",
+    )?;
+    for (_, path, entry) in &moved {
+        upsert.execute(params![path, to_json(entry)?])?;
+    }
+    Ok(())
+}
+
+fn playlists_containing(conn: &Connection, song_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare_cached("SELECT DISTINCT playlist_id FROM playlist_entries WHERE song_id = ?1")?;
+    let rows = stmt.query_map(params![song_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub(crate) fn read_queue(conn: &Connection) -> rusqlite::Result<Vec<MusicFile>> {
+    read_json_rows(conn, "SELECT data FROM queue ORDER BY position")
+}
+
+pub(crate) fn write_queue(conn: &Connection, songs: &[MusicFile]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM queue", [])?;
+    let mut insert =
+        conn.prepare_cached("INSERT INTO queue (position, song_id, data) VALUES (?1, ?2, ?3)")?;
+    for (position, song) in songs.iter().enumerate() {
+        insert.execute(params![position, song.id, to_json(song)?])?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_playlists(conn: &Connection) -> rusqlite::Result<Vec<Playlist>> {
+    let mut playlists: Vec<Playlist> = {
+        let mut stmt =
+            conn.prepare_cached("SELECT id, name, is_system FROM playlists ORDER BY position")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Playlist {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                songs: vec![],
+                is_system: row.get(2)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for playlist in &mut playlists {
+        playlist.songs = read_playlist_entries(conn, &playlist.id)?;
+    }
+    Ok(playlists)
+}
+
+fn read_playlist_entries(conn: &Connection, playlist_id: &str) -> rusqlite::Result<Vec<MusicFile>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT data FROM playlist_entries WHERE playlist_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map(params![playlist_id], |row| {
+        from_json(0, &row.get::<_, String>(0)?)
+    })?;
+    rows.collect()
+}
+
+pub(crate) fn read_playlist(conn: &Connection, id: &str) -> rusqlite::Result<Option<Playlist>> {
+    let playlist = conn
+        .query_row(
+            "SELECT id, name, is_system FROM playlists WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(Playlist {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    songs: vec![],
+                    is_system: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    playlist
+        .map(|mut playlist| {
+            playlist.songs = read_playlist_entries(conn, id)?;
+            Ok(playlist)
+        })
+        .transpose()
+}
+
+/// 新建歌单，排在所有歌单后面
+pub(crate) fn insert_playlist(conn: &Connection, playlist: &Playlist) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO playlists (id, position, name, is_system)
+         VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM playlists), ?2, ?3)",
+        params![playlist.id, playlist.name, playlist.is_system],
+    )?;
+    write_playlist_entries(conn, &playlist.id, &playlist.songs)
+}
+
+pub(crate) fn rename_playlist(conn: &Connection, id: &str, name: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE playlists SET name = ?2 WHERE id = ?1",
+        params![id, name],
+    )?;
+    Ok(())
+}
+
+/// 删除歌单，条目随外键一起删除
+pub(crate) fn delete_playlist(conn: &Connection, id: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// 把曲库里有的歌曲换成曲库中的最新版本：有 ID 的按 ID 找，没有 ID 的按路径找。
+/// 曲库里没有的歌曲保持原样
+pub(crate) fn refresh_songs(conn: &Connection, songs: &mut [MusicFile]) -> rusqlite::Result<()> {
+    let mut by_id = conn.prepare_cached("SELECT data FROM songs WHERE id = ?1")?;
+    let mut by_path = conn.prepare_cached("SELECT data FROM songs WHERE path = ?1 LIMIT 1")?;
+    for song in songs.iter_mut() {
+        let current: Option<String> = if song.id.is_empty() {
+            by_path
+                .query_row(params![song.path], |row| row.get(0))
+                .optional()?
+        } else {
+            by_id
+                .query_row(params![song.id], |row| row.get(0))
+                .optional()?
+        };
+        if let Some(current) = current {
+            *song = from_json(0, &current)?;
+        }
+    }
+    Ok(())
+}
+
+/// 用 playlists 替换全部歌单。歌曲没变的歌单不重写条目
+pub(crate) fn write_playlists(conn: &Connection, playlists: &[Playlist]) -> rusqlite::Result<()> {
+    let kept: HashSet<&str> = playlists.iter().map(|p| p.id.as_str()).collect();
+    let existing: Vec<String> = {
+        let mut stmt = conn.prepare_cached("SELECT id FROM playlists")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for id in existing.iter().filter(|id| !kept.contains(id.as_str())) {
+        conn.execute("DELETE FROM playlists WHERE id = ?1", params![id])?;
+    }
+
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO playlists (id, position, name, is_system) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(id) DO UPDATE SET
+             position = excluded.position, name = excluded.name, is_system = excluded.is_system",
+    )?;
+    for (position, playlist) in playlists.iter().enumerate() {
+        upsert.execute(params![
+            playlist.id,
+            position,
+            playlist.name,
+            playlist.is_system
+        ])?;
+        write_playlist_entries(conn, &playlist.id, &playlist.songs)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_playlist_entries(
+    conn: &Connection,
+    playlist_id: &str,
+    songs: &[MusicFile],
+) -> rusqlite::Result<()> {
+    let data = songs
+        .iter()
+        .map(to_json)
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let current: Vec<String> = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT data FROM playlist_entries WHERE playlist_id = ?1 ORDER BY position",
+        )?;
+        let rows = stmt.query_map(params![playlist_id], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    if current == data {
+        return Ok(());
+    }
+
+    conn.execute(
+        "DELETE FROM playlist_entries WHERE playlist_id = ?1",
+        params![playlist_id],
+    )?;
+    let mut insert = conn.prepare_cached(
+        "INSERT INTO playlist_entries (playlist_id, position, song_id, data) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, (song, data)) in songs.iter().zip(data).enumerate() {
+        insert.execute(params![playlist_id, position, song.id, data])?;
+    }
+    Ok(())
+}
+
+/// 设置按字段分行保存，值为 JSON。AppSettings 新增字段时旧数据库里没有对应的行，
+/// 读出来是字段的默认值
+pub(crate) fn read_settings(conn: &Connection) -> rusqlite::Result<AppSettings> {
+    let mut stmt = conn.prepare_cached("SELECT key, value FROM settings")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            from_json::<serde_json::Value>(1, &row.get::<_, String>(1)?)?,
+        ))
+    })?;
+    let fields = rows.collect::<rusqlite::Result<serde_json::Map<_, _>>>()?;
+    serde_json::from_value(serde_json::Value::Object(fields))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))
+}
+
+pub(crate) fn write_settings(conn: &Connection, settings: &AppSettings) -> rusqlite::Result<()> {
+    let fields = match serde_json::to_value(settings) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        Ok(_) => return Ok(()),
+        Err(e) => return Err(rusqlite::Error::ToSqlConversionFailure(Box::new(e))),
+    };
+    conn.execute("DELETE FROM settings", [])?;
+    let mut insert = conn.prepare_cached("INSERT INTO settings (key, value) VALUES (?1, ?2)")?;
+    for (key, value) in &fields {
+        insert.execute(params![key, to_json(value)?])?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_index(conn: &Connection) -> rusqlite::Result<LibraryIndex> {
+    let mut index = LibraryIndex::default();
+    {
+        let mut stmt = conn.prepare_cached("SELECT path, data FROM file_index")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                from_json::<IndexEntry>(1, &row.get::<_, String>(1)?)?,
+            ))
+        })?;
+        for row in rows {
+            let (path, entry) = row?;
+            index.entries.insert(path, entry);
+        }
+    }
+    let mut stmt = conn.prepare_cached("SELECT path, mtime FROM playlist_files")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    index.playlist_files = rows.collect::<rusqlite::Result<_>>()?;
+    Ok(index)
+}
+
+/// 只写入 before 到 after 之间有变化的索引记录，before 是修改前读出的索引。
+/// 其他操作在这期间写入的记录保持不变
+pub(crate) fn write_index_changes(
+    conn: &Connection,
+    before: &LibraryIndex,
+    after: &LibraryIndex,
+) -> rusqlite::Result<()> {
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO file_index (path, data) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET data = excluded.data",
+    )?;
+    for (path, entry) in &after.entries {
+        let data = to_json(entry)?;
+        let old = before.entries.get(path).map(to_json).transpose()?;
+        if old.as_ref() != Some(&data) {
+            upsert.execute(params![path, data])?;
+        }
+    }
+    let mut delete = conn.prepare_cached("DELETE FROM file_index WHERE path = ?1")?;
+    for path in before.entries.keys() {
+        if !after.entries.contains_key(path) {
+            delete.execute(params![path])?;
+        }
+    }
+
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO playlist_files (path, mtime) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET mtime = excluded.mtime",
+    )?;
+    for (path, mtime) in &after.playlist_files {
+        if before.playlist_files.get(path) != Some(mtime) {
+            upsert.execute(params![path, mtime])?;
+        }
+    }
+    let mut delete = conn.prepare_cached("DELETE FROM playlist_files WHERE path = ?1")?;
+    for path in before.playlist_files.keys() {
+        if !after.playlist_files.contains_key(path) {
+            delete.execute(params![path])?;
+        }
+    }
+    Ok(())
+}
+
+/// 记下导入过的播放列表文件及导入时的修改时间
+pub(crate) fn record_playlist_file(
+    conn: &Connection,
+    path: &str,
+    mtime: u64,
+) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO playlist_files (path, mtime) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET mtime = excluded.mtime",
+    )?
+    .execute(params![path, mtime])?;
+    Ok(())
+}
+
+/// 删除这些路径的文件索引和播放列表文件记录
+pub(crate) fn delete_index_entries(conn: &Connection, paths: &[String]) -> rusqlite::Result<()> {
+    for path in paths {
+        conn.prepare_cached("DELETE FROM file_index WHERE path = ?1")?
+            .execute(params![path])?;
+        conn.prepare_cached("DELETE FROM playlist_files WHERE path = ?1")?
+            .execute(params![path])?;
+    }
+    Ok(())
+}
+
+/// 写入应用自己改写过的文件（比如写入标签）的索引记录，.cue 的路径和修改时间沿用原来的记录
+pub(crate) fn touch_index_entry(
+    conn: &Connection,
+    path: &str,
+    mut entry: IndexEntry,
+) -> rusqlite::Result<()> {
+    let previous: Option<String> = conn
+        .prepare_cached("SELECT data FROM file_index WHERE path = ?1")?
+        .query_row(params![path], |row| row.get(0))
+        .optional()?;
+    if let Some(previous) = previous {
+        let previous: IndexEntry = from_json(0, &previous)?;
+        entry.cue_path = previous.cue_path;
+        entry.cue_mtime = previous.cue_mtime;
+    }
+    conn.prepare_cached(
+        "INSERT INTO file_index (path, data) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET data = excluded.data",
+    )?
+    .execute(params![path, to_json(&entry)?])?;
+    Ok(())
+}
+
+/// 用 index 替换文件索引，同样只写入有变化的记录
+pub(crate) fn write_index(conn: &Connection, index: &LibraryIndex) -> rusqlite::Result<()> {
+    let mut existing: HashMap<String, String> = {
+        let mut stmt = conn.prepare_cached("SELECT path, data FROM file_index")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO file_index (path, data) VALUES (?1, ?2)
+         ON CONFLICT(path) DO UPDATE SET data = excluded.data",
+    )?;
+    for (path, entry) in &index.entries {
+        let data = to_json(entry)?;
+        if existing.remove(path).as_ref() != Some(&data) {
+            upsert.execute(params![path, data])?;
+        }
+    }
+    let mut delete = conn.prepare_cached("DELETE FROM file_index WHERE path = ?1")?;
+    for path in existing.keys() {
+        delete.execute(params![path])?;
+    }
+
+    conn.execute("DELETE FROM playlist_files", [])?;
+    let mut insert =
+        conn.prepare_cached("INSERT INTO playlist_files (path, mtime) VALUES (?1, ?2)")?;
+    for (path, mtime) in &index.playlist_files {
+        insert.execute(params![path, mtime])?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_fingerprints(
+    conn: &Connection,
+) -> rusqlite::Result<HashMap<String, StoredFingerprint>> {
+    let mut stmt =
+        conn.prepare_cached("SELECT song_id, size, mtime, fingerprint FROM fingerprints")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get(0)?,
+            StoredFingerprint {
+                size: row.get(1)?,
+                mtime: row.get(2)?,
+                fingerprint: row.get(3)?,
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+/// 写入（或替换）这些歌曲的指纹，其余歌曲的指纹不变
+pub(crate) fn upsert_fingerprints(
+    conn: &Connection,
+    fingerprints: &HashMap<String, StoredFingerprint>,
+) -> rusqlite::Result<()> {
+    let mut upsert = conn.prepare_cached(
+        "INSERT INTO fingerprints (song_id, size, mtime, fingerprint) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(song_id) DO UPDATE SET
+            size = excluded.size, mtime = excluded.mtime, fingerprint = excluded.fingerprint",
+    )?;
+    for (id, stored) in fingerprints {
+        upsert.execute(params![id, stored.size, stored.mtime, stored.fingerprint])?;
+    }
+    Ok(())
+}
+
+/// 删除已不在曲库里的歌曲的指纹
+pub(crate) fn delete_orphan_fingerprints(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM fingerprints WHERE song_id NOT IN (SELECT id FROM songs)",
+        [],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::commands::file_scan::apply_path_changes;
+use crate::commands::library_roots::library_rules;
+use crate::commands::playlist_import::import_discovered;
+use crate::db::{load_library_index, read_library, read_settings, save_scan_result};
+use notify_debouncer_full::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// 曲库变化时发给前端的事件，payload 为 ScanDiff
+pub const LIBRARY_CHANGED_EVENT: &str = "library-changed";
+
+// 复制大量文件时会产生一连串事件，等安静下来再统一处理
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 监听所有曲库根目录，保存在 Tauri 的托管状态里
+pub struct LibraryWatcher {
+    debouncer: Mutex<Debouncer<RecommendedWatcher, RecommendedCache>>,
+}
+
+/// 在 `run` 的 setup 阶段调用，开始监听设置里的所有曲库根目录
+pub fn start_library_watcher(app_handle: &AppHandle) -> Result<(), String> {
+    let handle = app_handle.clone();
+    let debouncer = new_debouncer(
+        DEBOUNCE_TIMEOUT,
+        None,
+        move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                let paths: BTreeSet<PathBuf> = events
+                    .into_iter()
+                    .filter(|e| !e.kind.is_access())
+                    .flat_map(|e| e.event.paths)
+                    .collect();
+                if !paths.is_empty() {
+                    handle_changes(&handle, paths.into_iter().collect());
+                }
+            }
+            Err(errors) => {
+                for e in errors {
+                    println!("[监听] 出错: {}", e);
+                }
+            }
+        },
+    )
+    .map_err(|e| format!("创建目录监听失败: {}", e))?;
+
+    app_handle.manage(LibraryWatcher {
+        debouncer: Mutex::new(debouncer),
+    });
+
+    for root in read_settings(app_handle)?.library_roots {
+        watch_root(app_handle, &root.path);
+    }
+    Ok(())
+}
+
+/// 把新的根目录加入监听，已监听的目录重复调用也没有影响
+pub(crate) fn watch_root(app_handle: &AppHandle, root: &str) {
+    let Some(watcher) = app_handle.try_state::<LibraryWatcher>() else {
+        return;
+    };
+    let Ok(mut debouncer) = watcher.debouncer.lock() else {
+        return;
+    };
+    match debouncer.watch(Path::new(root), RecursiveMode::Recursive) {
+        Ok(()) => println!("[监听] 开始监听: {}", root),
+        Err(e) => println!("[监听] 无法监听 {}: {}", root, e),
+    }
+}
+
+pub(crate) fn unwatch_root(app_handle: &AppHandle, root: &str) {
+    let Some(watcher) = app_handle.try_state::<LibraryWatcher>() else {
+        return;
+    };
+    let Ok(mut debouncer) = watcher.debouncer.lock() else {
+        return;
+    };
+    if debouncer.unwatch(Path::new(root)).is_ok() {
+        println!("[监听] 停止监听: {}", root);
+    }
+}
+
+fn handle_changes(app_handle: &AppHandle, paths: Vec<PathBuf>) {
+    let rules = match library_rules(app_handle) {
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("[监听] 读取曲库规则失败: {}", e);
+            return;
+        }
+    };
+    // 读不出来时不能当成空曲库处理，否则写回去会把曲库清空
+    let (mut index, mut library) = match (load_library_index(app_handle), read_library(app_handle))
+    {
+        (Ok(index), Ok(library)) => (index, library),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("[监听] 读取曲库失败: {}", e);
+            return;
+        }
+    };
+
+    let before = index.clone();
+    let mut diff = apply_path_changes(&paths, &rules, &mut index, &mut library);
+    let library_changed =
+        !diff.added.is_empty() || !diff.updated.is_empty() || !diff.removed.is_empty();
+    if !library_changed && diff.playlist_files.is_empty() {
+        return;
+    }
+
+    diff.playlists = import_discovered(app_handle, &diff.playlist_files, &library, &mut index);
+    if !library_changed && diff.playlists.is_empty() {
+        return;
+    }
+    // 只写入变化的歌曲和索引，和正在进行的扫描、改标签等操作互不覆盖
+    if let Err(e) = save_scan_result(app_handle, &diff, &before, &index) {
+        println!("[监听] 保存曲库失败: {}", e);
+        return;
+    }
+
+    println!(
+        "[监听] 曲库已更新：新增{}首，更新{}首，移除{}首",
+        diff.added.len(),
+        diff.updated.len(),
+        diff.removed.len()
+    );
+    let _ = app_handle.emit(LIBRARY_CHANGED_EVENT, &diff);
+}
@@ -0,0 +1,17 @@
+REM COMMENT "ExactAudioCopy v1.6"
+PERFORMER "Radiohead"
+TITLE "OK Computer"
+FILE "01 - Airbag.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Airbag"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Paranoid Android"
+    INDEX 00 04:44:20
+FILE "02 - Paranoid Android.wav" WAVE
+    INDEX 01 00:00:00
+  TRACK 03 AUDIO
+    TITLE "Subterranean Homesick Alien"
+    INDEX 00 06:23:10
+FILE "03 - Subterranean Homesick Alien.wav" WAVE
+    INDEX 01 00:00:00
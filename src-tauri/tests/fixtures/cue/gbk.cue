@@ -0,0 +1,16 @@
+REM GENRE 
+REM DATE 2003
+PERFORMER "ܽ"
+TITLE "Ҷ"
+FILE "ܽ - Ҷ.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Ը֮"
+    PERFORMER "ܽ"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "ų"
+    INDEX 00 05:39:40
+    INDEX 01 05:42:10
+  TRACK 03 AUDIO
+    TITLE ""
+    INDEX 01 09:18:52
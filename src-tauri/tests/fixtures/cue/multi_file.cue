@@ -0,0 +1,19 @@
+REM GENRE "Classical"
+REM DATE 1999/05
+PERFORMER "Berliner Philharmoniker"
+TITLE "Beethoven: Symphonies 5 & 7"
+FILE "CD1 - Symphony No. 5.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "I. Allegro con brio"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "II. Andante con moto"
+    PERFORMER "Herbert von Karajan"
+    INDEX 00 07:31:20
+    INDEX 01 07:33:00
+FILE CD2.flac WAVE
+  TRACK 03 AUDIO
+    TITLE "III. Poco sostenuto - Vivace"
+    INDEX 01 00:00:00
+  TRACK 04 AUDIO
+    INDEX 01 13:05:74
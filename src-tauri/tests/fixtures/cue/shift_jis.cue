@@ -0,0 +1,14 @@
+REM GENRE J-Pop
+REM DATE 2018
+PERFORMER "FcqJ"
+TITLE ""
+FILE "FcqJ - .wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Ȃ"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE ""
+    INDEX 01 04:33:15
+  TRACK 03 AUDIO
+    TITLE ""
+    INDEX 01 09:23:05
@@ -1,5 +1,5 @@
 import { convertFileSrc, invoke } from "@tauri-apps/api/core";
-import { usePlayerStore, FAVORITES_PLAYLIST_ID } from "../stores/usePlayerStore";
+import { usePlayerStore, FAVORITES_PLAYLIST_ID, songKey, type Song } from "../stores/usePlayerStore";
 import { useEffect, useRef, useState } from "react";
 import { createPortal } from "react-dom";
 import { Play, Pause, SkipBack, SkipForward, Volume2, Heart, Plus, ListMusic, List, X, Download, Shuffle, Repeat, Repeat1, Trash2 } from "lucide-react";
@@ -65,16 +65,27 @@ export default function PlayerBar() {
   };
 
   const handleTimeUpdate = () => {
-    if (audioRef.current) setProgress(audioRef.current.currentTime);
+    const audio = audioRef.current;
+    if (!audio) return;
+    // CUE 分轨共用整轨文件，播到本轨结束时间就切到下一首
+    if (currentSong?.endTime != null && audio.currentTime >= currentSong.endTime) {
+      audio.pause();
+      playNext();
+      return;
+    }
+    setProgress(audio.currentTime - (currentSong?.startTime ?? 0));
   };
 
   const handleLoadedMetadata = () => {
-    if (audioRef.current) setDuration(audioRef.current.duration);
+    const audio = audioRef.current;
+    if (!audio) return;
+    const end = currentSong?.endTime ?? audio.duration;
+    setDuration(end - (currentSong?.startTime ?? 0));
   };
 
   const handleSeek = (e: React.ChangeEvent<HTMLInputElement>) => {
     const time = Number(e.target.value);
-    if (audioRef.current) audioRef.current.currentTime = time;
+    if (audioRef.current) audioRef.current.currentTime = time + (currentSong?.startTime ?? 0);
     setProgress(time);
   };
 
@@ -103,10 +114,12 @@ export default function PlayerBar() {
           const blobUrl = URL.createObjectURL(blob);
           audioRef.current.src = blobUrl;
         } else {
-          audioRef.current.src = getAssetUrl(currentSong.path, false);
+          audioRef.current.src = getAssetUrl(currentSong.sourcePath ?? currentSong.path, false);
         }
         
-        if (prevProgress > 0) {
+        if (currentSong.startTime != null) {
+          audioRef.current.currentTime = currentSong.startTime;
+        } else if (prevProgress > 0) {
           audioRef.current.currentTime = prevProgress;
         }
         
@@ -162,8 +175,9 @@ export default function PlayerBar() {
     }
   };
 
-  const userPlaylists = playlists.filter(p => p.id !== "local" && !p.songs.some(s => s.path === currentSong?.path));
-  const isInFavorites = currentSong && playlists.find(p => p.id === FAVORITES_PLAYLIST_ID)?.songs.some(s => s.path === currentSong.path);
+  const isCurrentSong = (song: Song) => currentSong != null && songKey(song) === songKey(currentSong);
+  const userPlaylists = playlists.filter(p => p.id !== "local" && !p.songs.some(isCurrentSong));
+  const isInFavorites = currentSong && playlists.find(p => p.id === FAVORITES_PLAYLIST_ID)?.songs.some(s => songKey(s) === songKey(currentSong));
   const isOnlineMusic = currentSong?.bvId && !currentSong?.isDownloaded;
 
   return (
@@ -173,7 +187,7 @@ export default function PlayerBar() {
         
         <audio
           ref={audioRef}
-          src={currentSong?.bvId && !currentSong?.isDownloaded ? undefined : (currentSong ? getAssetUrl(currentSong.path, false) : undefined)}
+          src={currentSong?.bvId && !currentSong?.isDownloaded ? undefined : (currentSong ? getAssetUrl(currentSong.sourcePath ?? currentSong.path, false) : undefined)}
           onTimeUpdate={handleTimeUpdate}
           onLoadedMetadata={handleLoadedMetadata}
           onEnded={playNext}
@@ -317,14 +331,14 @@ export default function PlayerBar() {
             </div>
             <div className="flex-1 overflow-y-auto p-3 space-y-1">
               {playQueue.length === 0 ? <p className="text-sm text-gray-500 py-4 text-center">播放列表为空</p> : playQueue.map((song: import("../stores/usePlayerStore").Song, index: number) => (
-                <div key={`${song.path}-${index}`} className={`group flex items-center gap-3 p-3 rounded-2xl cursor-pointer transition-all ${currentSong?.path === song.path ? "bg-gradient-to-r from-pink-500/20 to-purple-500/20 text-pink-600 dark:text-pink-400" : "hover:bg-gray-100 dark:hover:bg-white/5"}`} onClick={() => playSong(song)}>
+                <div key={`${song.path}-${index}`} className={`group flex items-center gap-3 p-3 rounded-2xl cursor-pointer transition-all ${isCurrentSong(song) ? "bg-gradient-to-r from-pink-500/20 to-purple-500/20 text-pink-600 dark:text-pink-400" : "hover:bg-gray-100 dark:hover:bg-white/5"}`} onClick={() => playSong(song)}>
                   <div className="w-6 h-6 rounded flex items-center justify-center text-xs">
-                    {currentSong?.path === song.path && isPlaying ? <span className="animate-pulse text-pink-500">▶</span> : <span className="text-gray-400">{index + 1}</span>}
+                    {isCurrentSong(song) && isPlaying ? <span className="animate-pulse text-pink-500">▶</span> : <span className="text-gray-400">{index + 1}</span>}
                   </div>
                   <span className="truncate flex-1 text-sm font-medium dark:text-white">{song.name}</span>
                   <span className={clsx(
                     "text-xs px-2 py-0.5 rounded-full shrink-0",
-                    currentSong?.path === song.path
+                    isCurrentSong(song)
                       ? (song.isOnline 
                           ? "bg-white/40 text-white font-bold" 
                           : "bg-white/40 text-green-200 font-bold")
@@ -336,7 +350,7 @@ export default function PlayerBar() {
                   </span>
                   <button 
                     className="opacity-0 group-hover:opacity-100 p-1 hover:bg-red-100 dark:hover:bg-red-500/20 rounded transition-all"
-                    onClick={(e) => { e.stopPropagation(); removeFromPlayQueue(song.path); }}
+                    onClick={(e) => { e.stopPropagation(); removeFromPlayQueue(songKey(song)); }}
                   >
                     <X size={14} className="text-red-500" />
                   </button>
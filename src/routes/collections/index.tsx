@@ -1,5 +1,5 @@
 import { createFileRoute } from "@tanstack/react-router";
-import { usePlayerStore } from "../../stores/usePlayerStore";
+import { usePlayerStore, songKey } from "../../stores/usePlayerStore";
 import { Virtuoso } from "react-virtuoso";
 import clsx from "clsx";
 import { ListMusic, HardDrive, Trash2, FolderOpen, Plus, X, ListPlus, Play, Search } from "lucide-react";
@@ -63,7 +63,7 @@ function CollectionsComponent() {
 
   const handleRemoveFromPlaylist = () => {
     if (!contextMenu || !search.playlistId) return;
-    removeSongFromPlaylist(search.playlistId, contextMenu.song.path);
+    removeSongFromPlaylist(search.playlistId, songKey(contextMenu.song));
     setContextMenu(null);
   };
 
@@ -85,7 +85,7 @@ function CollectionsComponent() {
   };
 
   const availablePlaylists = playlists.filter(
-    p => p.id !== search.playlistId && !p.songs.some(s => s.path === contextMenu?.song.path)
+    p => p.id !== search.playlistId && !p.songs.some(s => contextMenu != null && songKey(s) === songKey(contextMenu.song))
   );
 
   if (search.playlistId) {
@@ -148,7 +148,7 @@ function CollectionsComponent() {
               className="h-full w-full"
               data={filteredSongs}
               itemContent={(_index, song) => {
-                const isActive = currentSong?.path === song.path;
+                const isActive = currentSong != null && songKey(currentSong) === songKey(song);
                 return (
                   <div
                     className={clsx(
@@ -1,13 +1,72 @@
 import { create } from "zustand";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 export interface Song {
+  // 后端分配的稳定 ID，文件改名、移动后不变
+  id?: string;
   path: string;
   name: string;
   isOnline?: boolean;
   bvId?: string;
   page?: number;
   isDownloaded?: boolean;
+  title?: string;
+  artist?: string;
+  album?: string;
+  albumArtist?: string;
+  trackNumber?: number;
+  discNumber?: number;
+  year?: number;
+  genre?: string;
+  duration?: number;
+  codec?: string;
+  container?: string;
+  // 音频参数：比特率 kbps，采样率 Hz；有损编码没有位深
+  bitrate?: number;
+  bitrateMode?: 'cbr' | 'vbr' | 'lossless';
+  sampleRate?: number;
+  bitDepth?: number;
+  channels?: number;
+  // CUE 分轨：实际播放的整轨文件和该轨在文件中的起止时间（秒）
+  sourcePath?: string;
+  startTime?: number;
+  endTime?: number;
+  // 响度分析结果（EBU R128 / ReplayGain 2.0），增益单位 dB，峰值为线性值
+  replayGain?: {
+    loudness: number;
+    truePeak: number;
+    trackGain: number;
+    albumGain?: number;
+    albumPeak?: number;
+  };
+}
+
+// 识别同一首歌：优先用 id，还没有 id 的歌曲（刚从前端创建、尚未保存）退回到 path
+export const songKey = (song: Song) => song.id || song.path;
+
+export interface LibraryRemoval {
+  removed: string[];
+  trashedFiles: string[];
+  playlists: number;
+  queue: number;
+  failed: { id: string; path: string; error: string }[];
+}
+
+// 修改标签：不填的字段保持不变，空字符串清除；trackNumber 为 0 清除，cover 为图片文件路径
+export interface TagEdit {
+  title?: string;
+  artist?: string;
+  album?: string;
+  trackNumber?: number;
+  genre?: string;
+  cover?: string;
+  lyrics?: string;
+}
+
+export interface TagEditResult {
+  updated: Song[];
+  failed: { id: string; path: string; error: string }[];
 }
 
 export interface Playlist {
@@ -17,6 +76,11 @@ export interface Playlist {
   isSystem?: boolean;
 }
 
+export interface PlaylistChange {
+  playlistId: string;
+  playlist: Playlist | null;
+}
+
 export interface AppSettings {
   downloadFolder: string | null;
 }
@@ -48,7 +112,7 @@ interface PlayerState {
   playPlaylist: (songs: Song[]) => void;
   addMusic: (songs: Song[]) => void;
   addToNext: (song: Song) => void;
-  removeFromPlayQueue: (songPath: string) => void;
+  removeFromPlayQueue: (key: string) => void;
   setIsPlaying: (state: boolean) => void;
   playPrev: () => void;
   playNext: () => void;
@@ -60,14 +124,17 @@ interface PlayerState {
   toggleShuffle: () => void;
   toggleRepeat: () => void;
 
-  createPlaylist: (name: string, isSystem?: boolean) => void;
-  deletePlaylist: (id: string) => void;
-  addSongToPlaylist: (playlistId: string, song: Song) => void;
-  removeSongFromPlaylist: (playlistId: string, songPath: string) => void;
-  updatePlaylistSongs: (playlistId: string, songs: Song[]) => void;
+  createPlaylist: (name: string) => Promise<void>;
+  deletePlaylist: (id: string) => Promise<void>;
+  addSongToPlaylist: (playlistId: string, song: Song) => Promise<void>;
+  removeSongFromPlaylist: (playlistId: string, key: string) => Promise<void>;
+  moveInPlaylist: (playlistId: string, indices: number[], to: number) => Promise<void>;
   loadPlaylists: () => Promise<void>;
 
   scanMusic: (path: string) => Promise<void>;
+  rewritePathPrefix: (from: string, to: string) => Promise<void>;
+  editTags: (songIds: string[], edit: TagEdit) => Promise<TagEditResult>;
+  removeFromLibrary: (songIds: string[], deleteFiles?: boolean) => Promise<LibraryRemoval>;
   initPlaylist: () => Promise<void>;
   loadSettings: () => Promise<void>;
   setDownloadFolder: (folder: string | null) => Promise<void>;
@@ -109,9 +176,11 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
   };
 
   const setDownloadFolder = async (folder: string | null) => {
-    const newSettings = { download_folder: folder };
     set({ settings: { downloadFolder: folder } });
     try {
+      // 设置里还有曲库目录等其他字段，先读出来再整体保存，避免被覆盖
+      const current = await invoke<Record<string, unknown>>("load_settings");
+      const newSettings = { ...current, download_folder: folder };
       await invoke("save_settings", { settings: newSettings });
     } catch (e) {
       console.error("保存设置失败:", e);
@@ -121,6 +190,61 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
   initPlaylist();
   loadSettings();
 
+  // 后端监听到曲库目录变化后会推送事件，这里重新加载曲库
+  listen("library-changed", async () => {
+    try {
+      const savedLibrary = await invoke<Song[]>("load_library");
+      set({ localLibrary: savedLibrary });
+      await get().loadPlaylists();
+    } catch (e) {
+      console.error("刷新曲库失败:", e);
+    }
+  });
+
+  // 整理、重定位文件时后端已改好并保存了播放队列，这里按原 id 换成新路径的歌曲，
+  // 避免下次保存队列时把旧路径写回去
+  listen<Record<string, Song>>("queue-changed", (event) => {
+    const replace = (song: Song) => event.payload[songKey(song)] ?? song;
+    const { playQueue, originalQueue, currentSong } = get();
+    set({
+      playQueue: playQueue.map(replace),
+      originalQueue: originalQueue.map(replace),
+      currentSong: currentSong && replace(currentSong),
+    });
+  });
+
+  // 后端会给还没有 id 的歌曲分配 id，保存后补回播放队列，下次保存时沿用同一个 id
+  const savePlayQueue = async (songs: Song[]) => {
+    try {
+      const saved = await invoke<Song[]>("save_play_queue", { songs });
+      if (songs.every((s) => s.id)) return;
+      const ids = new Map(saved.map((s) => [s.path, s.id]));
+      const withId = (song: Song) => (song.id ? song : { ...song, id: ids.get(song.path) });
+      const { playQueue, originalQueue, currentSong } = get();
+      set({
+        playQueue: playQueue.map(withId),
+        originalQueue: originalQueue.map(withId),
+        currentSong: currentSong && withId(currentSong),
+      });
+    } catch (e) {
+      console.error("保存播放队列失败:", e);
+    }
+  };
+
+  // 歌单的增删改由后端逐条完成，这里只按返回或推送的结果替换对应的歌单
+  const applyPlaylistChange = ({ playlistId, playlist }: PlaylistChange) => {
+    const playlists = get().playlists;
+    if (!playlist) {
+      set({ playlists: playlists.filter((p) => p.id !== playlistId) });
+    } else if (playlists.some((p) => p.id === playlistId)) {
+      set({ playlists: playlists.map((p) => (p.id === playlistId ? playlist : p)) });
+    } else {
+      set({ playlists: [...playlists, playlist] });
+    }
+  };
+
+  listen<PlaylistChange>("playlists-changed", (event) => applyPlaylistChange(event.payload));
+
   const shuffleArray = <T>(array: T[]): T[] => {
     const result = [...array];
     for (let i = result.length - 1; i > 0; i--) {
@@ -150,7 +274,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
     resetPlayQueue: () => {
       const allSongs = get().localLibrary;
       set({ playQueue: allSongs, originalQueue: allSongs });
-      invoke("save_play_queue", { songs: allSongs });
+      savePlayQueue(allSongs);
     },
     clearPlayQueue: () => {
       set({ 
@@ -159,18 +283,18 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
         currentSong: null,
         isPlaying: false 
       });
-      invoke("save_play_queue", { songs: [] });
+      savePlayQueue([]);
     },
     setVolume: (val) => set({ volume: val }),
     playSong: (song) => {
       const { playQueue, currentSong, playMode, originalQueue } = get();
-      const exists = playQueue.some(s => s.path === song.path);
+      const exists = playQueue.some(s => songKey(s) === songKey(song));
       let newQueue: Song[];
       
       if (exists) {
         newQueue = playQueue;
       } else if (currentSong) {
-        const currentIndex = playQueue.findIndex(s => s.path === currentSong.path);
+        const currentIndex = playQueue.findIndex(s => songKey(s) === songKey(currentSong));
         const insertIndex = currentIndex !== -1 ? currentIndex + 1 : playQueue.length;
         newQueue = [...playQueue];
         newQueue.splice(insertIndex, 0, song);
@@ -180,7 +304,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       
       const newOriginalQueue = playMode === 'shuffle' ? originalQueue : [...newQueue];
       set({ playQueue: newQueue, originalQueue: newOriginalQueue, currentSong: song, isPlaying: true });
-      invoke("save_play_queue", { songs: newQueue });
+      savePlayQueue(newQueue);
     },
     playPlaylist: (songs: Song[]) => {
       if (songs.length === 0) return;
@@ -191,7 +315,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
         isPlaying: true,
         playMode: 'sequence'
       });
-      invoke("save_play_queue", { songs });
+      savePlayQueue(songs);
     },
     setIsPlaying: (state) => set({ isPlaying: state }),
     playPrev: () => {
@@ -200,7 +324,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       if (playQueue.length === 0 || !currentSong) return;
 
       const currentIndex = playQueue.findIndex(
-        (song) => song.path === currentSong.path
+        (song) => songKey(song) === songKey(currentSong)
       );
       
       let prevIndex: number;
@@ -218,7 +342,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       if (playQueue.length === 0 || !currentSong) return;
 
       const currentIndex = playQueue.findIndex(
-        (song) => song.path === currentSong.path
+        (song) => songKey(song) === songKey(currentSong)
       );
 
       let nextIndex: number;
@@ -241,7 +365,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       } else if (mode !== 'shuffle' && state.playMode === 'shuffle') {
         const currentSong = state.currentSong;
         const restored = [...state.originalQueue];
-        const newIndex = currentSong ? restored.findIndex(s => s.path === currentSong.path) : 0;
+        const newIndex = currentSong ? restored.findIndex(s => songKey(s) === songKey(currentSong)) : 0;
         set({ playMode: mode, playQueue: restored, originalQueue: [] });
         if (newIndex > 0) {
           const [song] = restored.splice(newIndex, 1);
@@ -259,7 +383,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
         const restored = [...state.originalQueue];
         set({ playMode: 'sequence', playQueue: restored, originalQueue: [] });
         if (currentSong) {
-          const newIndex = restored.findIndex(s => s.path === currentSong.path);
+          const newIndex = restored.findIndex(s => songKey(s) === songKey(currentSong));
           if (newIndex > 0) {
             const [song] = restored.splice(newIndex, 1);
             restored.unshift(song);
@@ -284,103 +408,70 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       }
     },
 
-    createPlaylist: (name: string, isSystem: boolean = false) => {
-      const id = isSystem 
-        ? FAVORITES_PLAYLIST_ID
-        : Date.now().toString();
-      const newPlaylist: Playlist = {
-        id,
-        name,
-        songs: [],
-        isSystem,
-      };
-      const newPlaylists = [...get().playlists, newPlaylist];
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
+    createPlaylist: async (name: string) => {
+      try {
+        const playlist = await invoke<Playlist>("create_playlist", { name });
+        applyPlaylistChange({ playlistId: playlist.id, playlist });
+      } catch (e) {
+        get().showToast(`创建歌单失败: ${e}`, 'error');
+      }
     },
 
-    deletePlaylist: (id: string) => {
-      const newPlaylists = get().playlists.filter((p) => p.id !== id);
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
+    deletePlaylist: async (id: string) => {
+      try {
+        await invoke("delete_playlist", { playlistId: id });
+        applyPlaylistChange({ playlistId: id, playlist: null });
+      } catch (e) {
+        get().showToast(`删除歌单失败: ${e}`, 'error');
+      }
     },
 
-    addSongToPlaylist: (playlistId: string, song: Song) => {
-      const newPlaylists = get().playlists.map((p) => {
-        if (p.id === playlistId) {
-          const exists = p.songs.some((s) => s.path === song.path);
-          if (!exists) {
-            return { ...p, songs: [...p.songs, song] };
-          }
-        }
-        return p;
-      });
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
+    addSongToPlaylist: async (playlistId: string, song: Song) => {
+      try {
+        const playlist = await invoke<Playlist>("add_to_playlist", { playlistId, songs: [song] });
+        applyPlaylistChange({ playlistId, playlist });
+      } catch (e) {
+        get().showToast(`添加到歌单失败: ${e}`, 'error');
+      }
     },
 
-    removeSongFromPlaylist: (playlistId: string, songPath: string) => {
-      const newPlaylists = get().playlists.map((p) => {
-        if (p.id === playlistId) {
-          return { ...p, songs: p.songs.filter((s) => s.path !== songPath) };
-        }
-        return p;
-      });
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
+    removeSongFromPlaylist: async (playlistId: string, key: string) => {
+      try {
+        const playlist = await invoke<Playlist>("remove_from_playlist", { playlistId, songIds: [key] });
+        applyPlaylistChange({ playlistId, playlist });
+      } catch (e) {
+        get().showToast(`从歌单移除失败: ${e}`, 'error');
+      }
     },
 
-    updatePlaylistSongs: (playlistId: string, songs: Song[]) => {
-      const newPlaylists = get().playlists.map((p) => {
-        if (p.id === playlistId) {
-          return { ...p, songs };
-        }
-        return p;
-      });
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
+    moveInPlaylist: async (playlistId: string, indices: number[], to: number) => {
+      try {
+        const playlist = await invoke<Playlist>("move_in_playlist", { playlistId, indices, to });
+        applyPlaylistChange({ playlistId, playlist });
+      } catch (e) {
+        get().showToast(`调整歌单顺序失败: ${e}`, 'error');
+      }
     },
 
     convertOnlineToLocal: async (oldPath: string, newPath: string, songName: string) => {
-      const newSong = { path: newPath, name: songName, isOnline: false, isDownloaded: true };
-      
-      const newLibrary = mergeUnique(get().localLibrary, [newSong]);
-      set({ localLibrary: newLibrary });
-      invoke("save_to_library", { songs: newLibrary });
-
-      await get().loadPlaylists();
-      
-      const currentPlaylists = get().playlists;
-      const newPlaylists = currentPlaylists.map((p) => {
-        const newSongs = p.songs.map((s) => {
-          if (s.path === oldPath) {
-            return { ...s, path: newPath, isOnline: false, isDownloaded: true };
-          }
-          return s;
+      // 后端在一个事务里把曲库、歌单和播放队列里的这首歌换成下载的文件，沿用原来的 id；
+      // 曲库和歌单由推送的事件刷新，这里替换播放队列和当前歌曲
+      try {
+        const song = await invoke<Song>("convert_online_to_local", { oldPath, newPath, name: songName });
+        const replace = (s: Song) => (s.path === oldPath ? song : s);
+        const { playQueue, originalQueue, currentSong } = get();
+        const updatedQueue = playQueue.map(replace);
+        const inQueue = updatedQueue.some((s) => songKey(s) === songKey(song));
+        set({
+          playQueue: inQueue ? updatedQueue : [...updatedQueue, song],
+          originalQueue: originalQueue.map(replace),
+          currentSong: currentSong && replace(currentSong),
         });
-        return { ...p, songs: newSongs };
-      });
-      set({ playlists: newPlaylists });
-      invoke("save_playlists", { playlists: newPlaylists });
-
-      const updatedPlayQueue = get().playQueue.map((s) => {
-        if (s.path === oldPath) {
-          return { ...s, path: newPath, isOnline: false, isDownloaded: true };
+        if (!inQueue) {
+          savePlayQueue(get().playQueue);
         }
-        return s;
-      });
-
-      const songExists = updatedPlayQueue.some(s => s.path === newPath);
-      const finalPlayQueue = songExists 
-        ? updatedPlayQueue 
-        : [...updatedPlayQueue, newSong];
-
-      set({ playQueue: finalPlayQueue });
-      invoke("save_play_queue", { songs: finalPlayQueue });
-
-      if (get().currentSong?.path === oldPath) {
-        const updatedSong = { ...get().currentSong!, path: newPath, isOnline: false, isDownloaded: true, bvId: undefined, page: undefined };
-        set({ currentSong: updatedSong });
+      } catch (e) {
+        get().showToast(`转换为本地歌曲失败: ${e}`, 'error');
       }
     },
 
@@ -397,26 +488,60 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
 
     scanMusic: async (path) => {
       try {
-        const songs: Song[] = await invoke("scan_music", { targetDir: path });
-        const newLibrary = mergeUnique(get().localLibrary, songs);
+        await invoke("rescan_music", { targetDir: path });
+        const newLibrary = await invoke<Song[]>("load_library");
         set({ localLibrary: newLibrary });
-        invoke("save_to_library", { songs: newLibrary });
+        // 扫描时会导入目录里的 .m3u / .pls 播放列表
+        await get().loadPlaylists();
       } catch (e) {
         console.error("Rust扫描翻车了:", e);
       }
     },
+    rewritePathPrefix: async (from, to) => {
+      // 后端会同时改曲库、播放队列、歌单和设置，改完全部重新加载
+      await invoke("rewrite_path_prefix", { from, to });
+      await initPlaylist();
+      await loadSettings();
+    },
+    editTags: async (songIds, edit) => {
+      // 曲库由 library-changed 事件刷新，这里把播放队列和当前歌曲换成新标签
+      const result = await invoke<TagEditResult>("edit_tags", { songIds, edit });
+      const updated = new Map(result.updated.map(s => [songKey(s), s]));
+      const replace = (song: Song) => updated.get(songKey(song)) ?? song;
+      const { playQueue, originalQueue, currentSong } = get();
+      set({
+        playQueue: playQueue.map(replace),
+        originalQueue: originalQueue.map(replace),
+        currentSong: currentSong && replace(currentSong),
+      });
+      return result;
+    },
+    removeFromLibrary: async (songIds, deleteFiles = false) => {
+      // 曲库和歌单由后端推送的事件刷新，这里把播放队列里被移除的歌曲去掉（后端已保存）
+      const result = await invoke<LibraryRemoval>("remove_from_library", { songIds, deleteFiles });
+      const removed = new Set(result.removed);
+      const keep = (song: Song) => !removed.has(songKey(song));
+      const { playQueue, originalQueue, currentSong } = get();
+      const newQueue = playQueue.filter(keep);
+      set({
+        playQueue: newQueue,
+        originalQueue: originalQueue.filter(keep),
+        currentSong: currentSong && !keep(currentSong) ? newQueue[0] ?? null : currentSong,
+      });
+      return result;
+    },
     addMusic: (songs) => {
       const newQueue = mergeUnique(get().playQueue, songs);
       set({ playQueue: newQueue });
     },
     addToNext: (song: Song) => {
       const { playQueue, currentSong, playMode, originalQueue } = get();
-      const exists = playQueue.some(s => s.path === song.path);
+      const exists = playQueue.some(s => songKey(s) === songKey(song));
       if (exists) return;
       
       let insertIndex = playQueue.length;
       if (currentSong) {
-        const currentIndex = playQueue.findIndex(s => s.path === currentSong.path);
+        const currentIndex = playQueue.findIndex(s => songKey(s) === songKey(currentSong));
         if (currentIndex !== -1) {
           insertIndex = currentIndex + 1;
         }
@@ -426,18 +551,19 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
       newQueue.splice(insertIndex, 0, song);
       const newOriginalQueue = playMode === 'shuffle' ? originalQueue : [...newQueue];
       set({ playQueue: newQueue, originalQueue: newOriginalQueue });
-      invoke("save_play_queue", { songs: newQueue });
+      savePlayQueue(newQueue);
     },
-    removeFromPlayQueue: (songPath: string) => {
+    removeFromPlayQueue: (key: string) => {
       const { playMode, originalQueue } = get();
-      const newQueue = get().playQueue.filter(s => s.path !== songPath);
+      const newQueue = get().playQueue.filter(s => songKey(s) !== key);
       const newOriginalQueue = playMode === 'shuffle' 
-        ? originalQueue.filter(s => s.path !== songPath) 
+        ? originalQueue.filter(s => songKey(s) !== key) 
         : [...newQueue];
       set({ playQueue: newQueue, originalQueue: newOriginalQueue });
-      invoke("save_play_queue", { songs: newQueue });
+      savePlayQueue(newQueue);
       
-      if (get().currentSong?.path === songPath) {
+      const currentSong = get().currentSong;
+      if (currentSong && songKey(currentSong) === key) {
         set({ currentSong: newQueue.length > 0 ? newQueue[0] : null });
       }
     },
@@ -455,7 +581,7 @@ export const usePlayerStore = create<PlayerState>((set, get) => {
 
 function mergeUnique(origin: Song[], addition: Song[]): Song[] {
   const uniqueMap = new Map<string, Song>();
-  origin.forEach((song) => uniqueMap.set(song.path, song));
-  addition.forEach((song) => uniqueMap.set(song.path, song));
+  origin.forEach((song) => uniqueMap.set(songKey(song), song));
+  addition.forEach((song) => uniqueMap.set(songKey(song), song));
   return Array.from(uniqueMap.values());
 }
\ No newline at end of file